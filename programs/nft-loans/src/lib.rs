@@ -1,264 +1,1197 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{
+    bpf_loader_upgradeable,
+    program_option::COption,
     sysvar::{
+        self,
         rent::Rent
     },
     clock
 };
+use anchor_lang::system_program;
+use anchor_spl::associated_token::{self, AssociatedToken};
 use anchor_spl::token::{self, TokenAccount, Token, Mint};
+use std::convert::TryFrom;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
 pub mod constants {
+    use anchor_lang::prelude::Pubkey;
+
     pub const NFT_COLLATERIZED_LOANS_SEED: &[u8] = b"config";
     pub const NFT_COLLATERIZED_LOANS_ST_VAULT_SEED: &[u8] = b"st_vault";
     pub const NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED: &[u8] = b"nft_vault";
+    pub const NFT_COLLATERIZED_LOANS_USER_STATS_SEED: &[u8] = b"user_stats";
+    pub const NFT_COLLATERIZED_LOANS_CURRENCY_SEED: &[u8] = b"currency";
+    pub const NFT_COLLATERIZED_LOANS_BUNDLE_SEED: &[u8] = b"bundle";
+    pub const NFT_COLLATERIZED_LOANS_COLLATERAL_SEED: &[u8] = b"collateral";
+    pub const NFT_COLLATERIZED_LOANS_PRICE_FEED_SEED: &[u8] = b"price_feed";
+    pub const NFT_COLLATERIZED_LOANS_AUCTION_SEED: &[u8] = b"auction";
+    pub const NFT_COLLATERIZED_LOANS_BID_VAULT_SEED: &[u8] = b"bid_vault";
+    pub const NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED: &[u8] = b"fee_vault";
+    pub const NFT_COLLATERIZED_LOANS_POOL_SEED: &[u8] = b"pool";
+    pub const NFT_COLLATERIZED_LOANS_POOL_VAULT_SEED: &[u8] = b"pool_vault";
+    pub const NFT_COLLATERIZED_LOANS_SHARE_MINT_SEED: &[u8] = b"share_mint";
+    pub const NFT_COLLATERIZED_LOANS_SHARE_ESCROW_SEED: &[u8] = b"share_escrow";
+    pub const NFT_COLLATERIZED_LOANS_WITHDRAWAL_SEED: &[u8] = b"withdrawal";
+    pub const NFT_COLLATERIZED_LOANS_DEFAULTED_SEED: &[u8] = b"defaulted";
+    pub const NFT_COLLATERIZED_LOANS_NOTE_SEED: &[u8] = b"note";
+    pub const NFT_COLLATERIZED_LOANS_LISTING_SEED: &[u8] = b"listing";
+    pub const BASIS_POINTS: u64 = 10_000;
+    // pool rates are yearly
+    pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+    // borrow index of a fresh pool, 18 decimals
+    pub const BORROW_INDEX_ONE: u128 = 1_000_000_000_000_000_000;
+    // highest yearly rate a pool curve reaches at full utilization, in basis points, 1000% a
+    // year. Bounds the index growth so accruals stay within u128.
+    pub const MAX_BORROW_RATE_BPS: u64 = 100_000;
+    // liquidation auction kinds
+    pub const DUTCH_AUCTION: u8 = 0;
+    pub const ENGLISH_AUCTION: u8 = 1;
+
+    // stands in for the mint of native SOL currencies
+    pub const NATIVE_SOL_MINT: Pubkey = anchor_lang::solana_program::system_program::ID;
 }
 
-#[program]
-pub mod nft_loans {
-    use super::*;
+// Token Metadata CPIs built by hand, the published crate needs a newer solana-program.
+pub mod metaplex {
+    use anchor_lang::prelude::*;
+    use anchor_lang::solana_program::{
+        instruction::{AccountMeta, Instruction},
+        program::invoke_signed,
+    };
 
-    pub fn initialize(ctx: Context<Initialize>, _fee_pt: u8) -> Result<()> {
-        let configuration = &mut ctx.accounts.configuration;
-        configuration.stablecoin_mint = ctx.accounts.stablecoin_mint.key();
-        configuration.stablecoin_vault = ctx.accounts.stablecoin_vault.key();
-        configuration.order_id = 0;
-        configuration.total_additional_collateral = 0;
-        configuration.fee_pt = _fee_pt;
-        Ok(())
+    declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+    // MetadataInstruction::FreezeDelegatedAccount
+    const FREEZE_DELEGATED_ACCOUNT: u8 = 26;
+    // MetadataInstruction::ThawDelegatedAccount
+    const THAW_DELEGATED_ACCOUNT: u8 = 27;
+    // MetadataInstruction::Transfer
+    const TRANSFER: u8 = 49;
+    // TransferArgs::V1
+    const TRANSFER_V1: u8 = 0;
+
+    pub struct DelegatedAccount<'info> {
+        pub delegate: AccountInfo<'info>,
+        pub token_account: AccountInfo<'info>,
+        pub edition: AccountInfo<'info>,
+        pub mint: AccountInfo<'info>,
+        pub token_program: AccountInfo<'info>,
     }
 
-    // create_order
-    pub fn create_order(ctx: Context<CreateOrder>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
-        if _request_amount == 0 {
-            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+    // Freezes an nft account through its master edition, the delegate must sign.
+    pub fn freeze_delegated_account<'info>(program: AccountInfo<'info>, accounts: DelegatedAccount<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        invoke_delegated_account(FREEZE_DELEGATED_ACCOUNT, program, accounts, signer_seeds)
+    }
+
+    // Thaws an nft account frozen by freeze_delegated_account.
+    pub fn thaw_delegated_account<'info>(program: AccountInfo<'info>, accounts: DelegatedAccount<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        invoke_delegated_account(THAW_DELEGATED_ACCOUNT, program, accounts, signer_seeds)
+    }
+
+    fn invoke_delegated_account<'info>(instruction: u8, program: AccountInfo<'info>, accounts: DelegatedAccount<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let ix = Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(accounts.delegate.key(), true),
+                AccountMeta::new(accounts.token_account.key(), false),
+                AccountMeta::new_readonly(accounts.edition.key(), false),
+                AccountMeta::new_readonly(accounts.mint.key(), false),
+                AccountMeta::new_readonly(accounts.token_program.key(), false),
+            ],
+            data: vec![instruction],
+        };
+
+        invoke_signed(
+            &ix,
+            &[
+                accounts.delegate,
+                accounts.token_account,
+                accounts.edition,
+                accounts.mint,
+                accounts.token_program,
+                program,
+            ],
+            signer_seeds,
+        )
+        .map_err(Into::into)
+    }
+
+    // Reads the verified collection out of a metadata account of the mint.
+    pub fn verified_collection(metadata: &AccountInfo, mint: &Pubkey) -> Result<Option<Pubkey>> {
+        let (address, _) = Pubkey::find_program_address(&[b"metadata", ID.as_ref(), mint.as_ref()], &ID);
+        if metadata.key() != address || *metadata.owner != ID {
+            return Err(crate::ErrorCode::InvalidMetadata.into());
         }
 
-        // Transfer collateral to vault.
-        {
-            let cpi_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.user_nft_vault.to_account_info(),
-                    to: ctx.accounts.nft_vault.to_account_info(),
-                    authority: ctx.accounts.signer.to_account_info(), //todo use user account as signer
-                },
-            );
-            token::transfer(cpi_ctx, 1)?;
+        let data = metadata.try_borrow_data()?;
+        let mut reader = Reader { data: &data, offset: 0 };
+        // key, update authority, mint
+        reader.skip(1 + 32 + 32)?;
+        // name, symbol, uri
+        for _ in 0..3 {
+            let len = reader.u32()? as usize;
+            reader.skip(len)?;
+        }
+        // seller fee basis points
+        reader.skip(2)?;
+        // creators, 34 bytes each
+        if reader.u8()? == 1 {
+            let creators = reader.u32()? as usize;
+            reader.skip(creators.checked_mul(34).ok_or(crate::ErrorCode::InvalidMetadata)?)?;
+        }
+        // primary sale happened, is mutable
+        reader.skip(2)?;
+        // edition nonce, token standard
+        for _ in 0..2 {
+            if reader.u8()? == 1 {
+                reader.skip(1)?;
+            }
+        }
+        if reader.u8()? == 1 {
+            let verified = reader.u8()? == 1;
+            let key = reader.pubkey()?;
+            if verified {
+                return Ok(Some(key));
+            }
         }
+        Ok(None)
+    }
 
-        // Transfer additional collateral to vault
-        {
-            let cpi_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.user_stablecoin_vault.to_account_info(),
-                    to: ctx.accounts.stablecoin_vault.to_account_info(),
-                    authority: ctx.accounts.signer.to_account_info(), //todo use user account as signer
-                },
-            );
-            token::transfer(cpi_ctx, _additional_collateral)?;
+    struct Reader<'a> {
+        data: &'a [u8],
+        offset: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+            let end = self.offset.checked_add(len).ok_or(crate::ErrorCode::InvalidMetadata)?;
+            let bytes = self.data.get(self.offset..end).ok_or(crate::ErrorCode::InvalidMetadata)?;
+            self.offset = end;
+            Ok(bytes)
         }
 
-        let clock = clock::Clock::get().unwrap();
+        fn skip(&mut self, len: usize) -> Result<()> {
+            self.take(len).map(|_| ())
+        }
 
-        // Save Info
-        let order = &mut ctx.accounts.order;
-        order.borrower = ctx.accounts.signer.key();
-        order.stablecoin_vault = ctx.accounts.stablecoin_vault.key();
-        order.nft_mint = ctx.accounts.nft_mint.key();
-        order.nft_vault = ctx.accounts.nft_vault.key();
-        order.request_amount = _request_amount;
-        order.interest = _interest;
-        order.period = _period;
-        order.additional_collateral = _additional_collateral;
-        order.lender = order.key(); // just a placeholder
-        order.created_at = clock.unix_timestamp as u64;
-        order.loan_start_time = 0; // placeholder
-        order.paid_back_at = 0;
-        order.withdrew_at = 0;
+        fn u8(&mut self) -> Result<u8> {
+            Ok(self.take(1)?[0])
+        }
+
+        fn u32(&mut self) -> Result<u32> {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(self.take(4)?);
+            Ok(u32::from_le_bytes(bytes))
+        }
 
-        let nft_collaterized_loans = &mut ctx.accounts.configuration;
-        nft_collaterized_loans.total_additional_collateral += _additional_collateral;
+        fn pubkey(&mut self) -> Result<Pubkey> {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(self.take(32)?);
+            Ok(Pubkey::new_from_array(bytes))
+        }
+    }
 
-        nft_collaterized_loans.order_id += 1;
+    pub struct Transfer<'info> {
+        pub token: AccountInfo<'info>,
+        pub token_owner: AccountInfo<'info>,
+        pub destination: AccountInfo<'info>,
+        pub destination_owner: AccountInfo<'info>,
+        pub mint: AccountInfo<'info>,
+        pub metadata: AccountInfo<'info>,
+        pub edition: AccountInfo<'info>,
+        pub owner_token_record: AccountInfo<'info>,
+        pub destination_token_record: AccountInfo<'info>,
+        pub authority: AccountInfo<'info>,
+        pub payer: AccountInfo<'info>,
+        pub system_program: AccountInfo<'info>,
+        pub sysvar_instructions: AccountInfo<'info>,
+        pub spl_token_program: AccountInfo<'info>,
+        pub spl_ata_program: AccountInfo<'info>,
+        // the Token Metadata program id stands in for a missing rule set
+        pub authorization_rules_program: AccountInfo<'info>,
+        pub authorization_rules: AccountInfo<'info>,
+    }
 
-        order.order_status = true;
+    // Moves a programmable NFT, thawing and re-freezing it and moving its token record.
+    pub fn transfer<'info>(program: AccountInfo<'info>, accounts: Transfer<'info>, amount: u64, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let mut data = vec![TRANSFER, TRANSFER_V1];
+        data.extend_from_slice(&amount.to_le_bytes());
+        // no authorization data
+        data.push(0);
 
-        Ok(())
+        let ix = Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(accounts.token.key(), false),
+                AccountMeta::new_readonly(accounts.token_owner.key(), false),
+                AccountMeta::new(accounts.destination.key(), false),
+                AccountMeta::new_readonly(accounts.destination_owner.key(), false),
+                AccountMeta::new_readonly(accounts.mint.key(), false),
+                AccountMeta::new(accounts.metadata.key(), false),
+                AccountMeta::new_readonly(accounts.edition.key(), false),
+                AccountMeta::new(accounts.owner_token_record.key(), false),
+                AccountMeta::new(accounts.destination_token_record.key(), false),
+                AccountMeta::new_readonly(accounts.authority.key(), true),
+                AccountMeta::new(accounts.payer.key(), true),
+                AccountMeta::new_readonly(accounts.system_program.key(), false),
+                AccountMeta::new_readonly(accounts.sysvar_instructions.key(), false),
+                AccountMeta::new_readonly(accounts.spl_token_program.key(), false),
+                AccountMeta::new_readonly(accounts.spl_ata_program.key(), false),
+                AccountMeta::new_readonly(accounts.authorization_rules_program.key(), false),
+                AccountMeta::new_readonly(accounts.authorization_rules.key(), false),
+            ],
+            data,
+        };
+
+        invoke_signed(
+            &ix,
+            &[
+                accounts.token,
+                accounts.token_owner,
+                accounts.destination,
+                accounts.destination_owner,
+                accounts.mint,
+                accounts.metadata,
+                accounts.edition,
+                accounts.owner_token_record,
+                accounts.destination_token_record,
+                accounts.authority,
+                accounts.payer,
+                accounts.system_program,
+                accounts.sysvar_instructions,
+                accounts.spl_token_program,
+                accounts.spl_ata_program,
+                accounts.authorization_rules_program,
+                accounts.authorization_rules,
+                program,
+            ],
+            signer_seeds,
+        )
+        .map_err(Into::into)
     }
+}
 
-    pub fn cancel_order(ctx: Context<CancelOrder>, _order_id: u64) -> Result<()> {
-        let order = &mut ctx.accounts.order;
-        let configuration = &mut ctx.accounts.configuration;
+// Token-2022 CPIs and extension parsing built by hand, spl-token-2022 needs a newer solana-program.
+// Mints and token accounts of both token programs share the SPL Token base layout.
+pub mod token_2022 {
+    use anchor_lang::prelude::*;
+    use anchor_lang::solana_program::{
+        instruction::{AccountMeta, Instruction},
+        program::{invoke, invoke_signed},
+        program_pack::{IsInitialized, Pack},
+    };
+    use anchor_spl::associated_token;
+    use anchor_spl::token::spl_token;
+    use std::convert::TryFrom;
+
+    declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+    // TokenInstruction::CloseAccount
+    const CLOSE_ACCOUNT: u8 = 9;
+    // TokenInstruction::TransferChecked
+    const TRANSFER_CHECKED: u8 = 12;
+    // TokenInstruction::TransferFeeExtension
+    const TRANSFER_FEE_EXTENSION: u8 = 26;
+    // TransferFeeInstruction::HarvestWithheldTokensToMint
+    const HARVEST_WITHHELD_TOKENS_TO_MINT: u8 = 4;
+
+    // Extensions follow the base state padded to the token account length and an account type,
+    // each one a u16 type and a u16 length ahead of its value.
+    const ACCOUNT_TYPE_OFFSET: usize = spl_token::state::Account::LEN;
+    const ACCOUNT_TYPE_MINT: u8 = 1;
+    const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+    // ExtensionType
+    const UNINITIALIZED: u16 = 0;
+    const TRANSFER_FEE_CONFIG: u16 = 1;
+    const NON_TRANSFERABLE: u16 = 9;
+    const PERMANENT_DELEGATE: u16 = 12;
+    // TransferFeeConfig, two authorities and the withheld amount ahead of the older and newer fees
+    const OLDER_TRANSFER_FEE_OFFSET: usize = 32 + 32 + 8;
+    const NEWER_TRANSFER_FEE_OFFSET: usize = OLDER_TRANSFER_FEE_OFFSET + 8 + 8 + 2;
+    const TRANSFER_FEE_CONFIG_LEN: usize = NEWER_TRANSFER_FEE_OFFSET + 8 + 8 + 2;
+
+    const MAX_FEE_BASIS_POINTS: u128 = 10_000;
+
+    // Whether the program is the SPL Token or the Token-2022 program.
+    pub fn is_token_program(program: &Pubkey) -> bool {
+        *program == ID || *program == spl_token::ID
+    }
+
+    // Fee of the transfer fee extension, charged from `epoch` on.
+    #[derive(Clone, Copy)]
+    pub struct TransferFee {
+        pub epoch: u64,
+        pub maximum_fee: u64,
+        pub basis_points: u16,
+    }
 
-        if order.loan_start_time != 0 && order.order_status == false {
-            return Err(ErrorCode::LoanAlreadyStarted.into());
+    impl TransferFee {
+        fn read(data: &[u8]) -> TransferFee {
+            let mut epoch = [0u8; 8];
+            epoch.copy_from_slice(&data[..8]);
+            let mut maximum_fee = [0u8; 8];
+            maximum_fee.copy_from_slice(&data[8..16]);
+            TransferFee {
+                epoch: u64::from_le_bytes(epoch),
+                maximum_fee: u64::from_le_bytes(maximum_fee),
+                basis_points: u16::from_le_bytes([data[16], data[17]]),
+            }
         }
 
-        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        // Fee withheld from a transfer of `amount`, rounded up and capped by the maximum.
+        pub fn fee(&self, amount: u64) -> Result<u64> {
+            let fee = (amount as u128)
+                .checked_mul(self.basis_points as u128)
+                .ok_or(crate::ErrorCode::MathOverflow)?
+                .checked_add(MAX_FEE_BASIS_POINTS - 1)
+                .ok_or(crate::ErrorCode::MathOverflow)?
+                / MAX_FEE_BASIS_POINTS;
+            Ok(u64::try_from(fee).map_err(|_| crate::ErrorCode::MathOverflow)?.min(self.maximum_fee))
+        }
 
-        // Transfer back nft collateral.
-        {
-            let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
-            let signer = &[&seeds[..]];
+        // Smallest transfer that still delivers `amount` once the fee is withheld.
+        pub fn gross_up(&self, amount: u64) -> Result<u64> {
+            // the fee is capped, so adding the maximum always delivers enough
+            let capped = amount.checked_add(self.maximum_fee).ok_or(crate::ErrorCode::MathOverflow)?;
+            let rate = MAX_FEE_BASIS_POINTS.checked_sub(self.basis_points as u128).unwrap_or(0);
+            if rate == 0 {
+                return Ok(capped);
+            }
+            // gross - ceil(gross * bps / 10000) >= amount for gross = ceil(amount * 10000 / (10000 - bps))
+            let gross = (amount as u128)
+                .checked_mul(MAX_FEE_BASIS_POINTS)
+                .ok_or(crate::ErrorCode::MathOverflow)?
+                .checked_add(rate - 1)
+                .ok_or(crate::ErrorCode::MathOverflow)?
+                / rate;
+            Ok(u64::try_from(gross).unwrap_or(u64::MAX).min(capped))
+        }
+    }
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.nft_vault.to_account_info(),
-                    to: ctx.accounts.user_nft_vault.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
-                },
-                signer
-            );
-            token::transfer(cpi_ctx, 1)?;
+    // Mint of either token program, with the extensions collateral tokens care about.
+    pub struct MintState {
+        pub decimals: u8,
+        // older and newer fee of the transfer fee extension
+        pub transfer_fees: Option<(TransferFee, TransferFee)>,
+        pub non_transferable: bool,
+        pub permanent_delegate: Option<Pubkey>,
+    }
+
+    impl MintState {
+        // Fee charged on transfers during the epoch, if the mint has one.
+        pub fn transfer_fee(&self, epoch: u64) -> Option<TransferFee> {
+            self.transfer_fees.map(|(older, newer)| if epoch >= newer.epoch { newer } else { older })
         }
 
-        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        // Amount to send during the epoch so that `amount` arrives.
+        pub fn gross_up(&self, amount: u64, epoch: u64) -> Result<u64> {
+            match self.transfer_fee(epoch) {
+                Some(fee) => fee.gross_up(amount),
+                None => Ok(amount),
+            }
+        }
 
-        // Transfer back additional collateral
-        {
-            let seeds = &[ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
-            let signer = &[&seeds[..]];
+        // Non-transferable tokens cannot be seized, and a permanent delegate could move them out of the vault.
+        pub fn check_collateral(&self) -> Result<()> {
+            if self.non_transferable || self.permanent_delegate.is_some() {
+                return Err(crate::ErrorCode::UnsupportedTokenExtension.into());
+            }
+            Ok(())
+        }
+    }
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.stablecoin_vault.to_account_info(),
-                    to: ctx.accounts.user_stablecoin_vault.to_account_info(),
-                    authority: ctx.accounts.stablecoin_vault.to_account_info(),
-                },
-                signer
-            );
+    // Reads a mint of either token program.
+    pub fn mint(info: &AccountInfo) -> Result<MintState> {
+        if !is_token_program(info.owner) {
+            return Err(crate::ErrorCode::InvalidTokenAccount.into());
+        }
+        let data = info.try_borrow_data()?;
+        let base = data.get(..spl_token::state::Mint::LEN).ok_or(crate::ErrorCode::InvalidTokenAccount)?;
+        let base = spl_token::state::Mint::unpack_from_slice(base)?;
+        if !base.is_initialized() {
+            return Err(crate::ErrorCode::InvalidTokenAccount.into());
+        }
 
-            token::transfer(cpi_ctx, order.additional_collateral)?;
+        let mut state = MintState {
+            decimals: base.decimals,
+            transfer_fees: None,
+            non_transferable: false,
+            permanent_delegate: None,
+        };
+        for (extension, value) in extensions(&data, ACCOUNT_TYPE_MINT)? {
+            match extension {
+                TRANSFER_FEE_CONFIG => {
+                    if value.len() < TRANSFER_FEE_CONFIG_LEN {
+                        return Err(crate::ErrorCode::InvalidTokenAccount.into());
+                    }
+                    state.transfer_fees = Some((
+                        TransferFee::read(&value[OLDER_TRANSFER_FEE_OFFSET..]),
+                        TransferFee::read(&value[NEWER_TRANSFER_FEE_OFFSET..]),
+                    ));
+                }
+                NON_TRANSFERABLE => state.non_transferable = true,
+                PERMANENT_DELEGATE => {
+                    // an all zero delegate stands for none
+                    let delegate = value.get(..32).ok_or(crate::ErrorCode::InvalidTokenAccount)?;
+                    if delegate.iter().any(|byte| *byte != 0) {
+                        state.permanent_delegate = Some(Pubkey::new(delegate));
+                    }
+                }
+                _ => {}
+            }
         }
-        configuration.total_additional_collateral -= order.additional_collateral;
+        Ok(state)
+    }
 
-        Ok(())
+    // Reads a token account of either token program.
+    pub fn account(info: &AccountInfo) -> Result<spl_token::state::Account> {
+        if !is_token_program(info.owner) {
+            return Err(crate::ErrorCode::InvalidTokenAccount.into());
+        }
+        let data = info.try_borrow_data()?;
+        if data.len() > ACCOUNT_TYPE_OFFSET && data[ACCOUNT_TYPE_OFFSET] != ACCOUNT_TYPE_ACCOUNT {
+            return Err(crate::ErrorCode::InvalidTokenAccount.into());
+        }
+        let base = data.get(..spl_token::state::Account::LEN).ok_or(crate::ErrorCode::InvalidTokenAccount)?;
+        let account = spl_token::state::Account::unpack_from_slice(base)?;
+        if !account.is_initialized() {
+            return Err(crate::ErrorCode::InvalidTokenAccount.into());
+        }
+        Ok(account)
     }
 
-    pub fn give_loan(ctx: Context<GiveLoan>, _order_id: u64) -> Result<()> {
-        let order = &mut ctx.accounts.order;
+    // Whether the account holds tokens of the mint for the owner, under the same token program.
+    pub fn is_account_of(info: &AccountInfo, mint: &AccountInfo, owner: &Pubkey) -> bool {
+        info.owner == mint.owner && matches!(account(info), Ok(account) if account.mint == mint.key() && account.owner == *owner)
+    }
 
-        if order.loan_start_time != 0 && order.order_status == false {
-            return Err(ErrorCode::LoanAlreadyStarted.into());
+    fn extensions(data: &[u8], account_type: u8) -> Result<Vec<(u16, &[u8])>> {
+        let mut extensions = Vec::new();
+        if data.len() <= ACCOUNT_TYPE_OFFSET {
+            return Ok(extensions);
+        }
+        if data[ACCOUNT_TYPE_OFFSET] != account_type {
+            return Err(crate::ErrorCode::InvalidTokenAccount.into());
         }
 
-        // Transfer back additional collateral
-        {
-            let cpi_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.lender_stablecoin_vault.to_account_info(),
-                    to: ctx.accounts.borrower_stablecoin_vault.to_account_info(),
-                    authority: ctx.accounts.signer.to_account_info(),
-                },
-            );
-            token::transfer(cpi_ctx, order.request_amount)?;
+        let mut offset = ACCOUNT_TYPE_OFFSET + 1;
+        while offset + 4 <= data.len() {
+            let extension = u16::from_le_bytes([data[offset], data[offset + 1]]);
+            if extension == UNINITIALIZED {
+                break;
+            }
+            let len = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+            let value = data.get(offset + 4..offset + 4 + len).ok_or(crate::ErrorCode::InvalidTokenAccount)?;
+            extensions.push((extension, value));
+            offset += 4 + len;
         }
+        Ok(extensions)
+    }
 
-        // Save Info
-        order.lender = ctx.accounts.signer.key();
-        order.loan_start_time = clock::Clock::get().unwrap().unix_timestamp as u64;
-        order.order_status = false;
+    // Associated token account of the owner, derived under the token program of the mint.
+    pub fn associated_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[owner.as_ref(), token_program.as_ref(), mint.as_ref()], &associated_token::ID).0
+    }
 
-        Ok(())
+    pub struct CreateAssociatedAccount<'info> {
+        pub payer: AccountInfo<'info>,
+        pub associated_token: AccountInfo<'info>,
+        pub authority: AccountInfo<'info>,
+        pub mint: AccountInfo<'info>,
+        pub system_program: AccountInfo<'info>,
+        pub token_program: AccountInfo<'info>,
+        pub rent: AccountInfo<'info>,
     }
 
-    pub fn payback(ctx: Context<Payback>, _order_id: u64) -> Result<()> {
-        let order = &mut ctx.accounts.order;
-        let configuration = &mut ctx.accounts.configuration;
+    // Creates an associated token account, the anchor-spl helper only knows the SPL Token program.
+    pub fn create_associated_account<'info>(program: AccountInfo<'info>, accounts: CreateAssociatedAccount<'info>) -> Result<()> {
+        let ix = Instruction {
+            program_id: associated_token::ID,
+            accounts: vec![
+                AccountMeta::new(accounts.payer.key(), true),
+                AccountMeta::new(accounts.associated_token.key(), false),
+                AccountMeta::new_readonly(accounts.authority.key(), false),
+                AccountMeta::new_readonly(accounts.mint.key(), false),
+                AccountMeta::new_readonly(accounts.system_program.key(), false),
+                AccountMeta::new_readonly(accounts.token_program.key(), false),
+                AccountMeta::new_readonly(accounts.rent.key(), false),
+            ],
+            data: vec![],
+        };
 
-        if order.loan_start_time == 0 && order.order_status == true {
-            return Err(ErrorCode::LoanNotProvided.into());
-        }
+        invoke(
+            &ix,
+            &[
+                accounts.payer,
+                accounts.associated_token,
+                accounts.authority,
+                accounts.mint,
+                accounts.system_program,
+                accounts.token_program,
+                accounts.rent,
+                program,
+            ],
+        )
+        .map_err(Into::into)
+    }
 
-        let clock = clock::Clock::get().unwrap();
-        if order.loan_start_time.checked_add(order.period).unwrap() < clock.unix_timestamp as u64 {
-            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+    pub struct TransferChecked<'info> {
+        pub from: AccountInfo<'info>,
+        pub mint: AccountInfo<'info>,
+        pub to: AccountInfo<'info>,
+        pub authority: AccountInfo<'info>,
+    }
+
+    // Transfers through either token program, Token-2022 refuses plain transfers of fee mints.
+    pub fn transfer_checked<'info>(program: AccountInfo<'info>, accounts: TransferChecked<'info>, amount: u64, decimals: u8, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let mut data = vec![TRANSFER_CHECKED];
+        data.extend_from_slice(&amount.to_le_bytes());
+        data.push(decimals);
+
+        let ix = Instruction {
+            program_id: program.key(),
+            accounts: vec![
+                AccountMeta::new(accounts.from.key(), false),
+                AccountMeta::new_readonly(accounts.mint.key(), false),
+                AccountMeta::new(accounts.to.key(), false),
+                AccountMeta::new_readonly(accounts.authority.key(), true),
+            ],
+            data,
+        };
+
+        invoke_signed(
+            &ix,
+            &[
+                accounts.from,
+                accounts.mint,
+                accounts.to,
+                accounts.authority,
+                program,
+            ],
+            signer_seeds,
+        )
+        .map_err(Into::into)
+    }
+
+    pub struct CloseAccount<'info> {
+        pub account: AccountInfo<'info>,
+        pub mint: AccountInfo<'info>,
+        pub destination: AccountInfo<'info>,
+        pub authority: AccountInfo<'info>,
+    }
+
+    // Closes an emptied token account. Token-2022 keeps the fees withheld on transfers into it
+    // and refuses to close it until they are harvested to the mint.
+    pub fn close_account<'info>(program: AccountInfo<'info>, accounts: CloseAccount<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        if program.key() == ID && mint(&accounts.mint)?.transfer_fees.is_some() {
+            let ix = Instruction {
+                program_id: ID,
+                accounts: vec![
+                    AccountMeta::new(accounts.mint.key(), false),
+                    AccountMeta::new(accounts.account.key(), false),
+                ],
+                data: vec![TRANSFER_FEE_EXTENSION, HARVEST_WITHHELD_TOKENS_TO_MINT],
+            };
+            invoke(&ix, &[accounts.mint.clone(), accounts.account.clone(), program.clone()])?;
         }
 
-        // Save Info
-        order.paid_back_at = clock.unix_timestamp as u64;
+        let ix = Instruction {
+            program_id: program.key(),
+            accounts: vec![
+                AccountMeta::new(accounts.account.key(), false),
+                AccountMeta::new(accounts.destination.key(), false),
+                AccountMeta::new_readonly(accounts.authority.key(), true),
+            ],
+            data: vec![CLOSE_ACCOUNT],
+        };
 
-        // Pay Loan
-        {
-            let cpi_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.user_stablecoin_vault.to_account_info(),
-                    to: ctx.accounts.lender_stablecoin_vault.to_account_info(),
-                    authority: ctx.accounts.signer.to_account_info(),
-                },
-            );
-            token::transfer(cpi_ctx, order.request_amount.checked_add(order.interest).unwrap())?;
+        invoke_signed(
+            &ix,
+            &[
+                accounts.account,
+                accounts.destination,
+                accounts.authority,
+                program,
+            ],
+            signer_seeds,
+        )
+        .map_err(Into::into)
+    }
+}
+
+// Price adapters, reading the program-native feed or a Pyth / Switchboard price account by layout.
+pub mod oracle {
+    use super::*;
+
+    // price sources a feed can be configured with
+    pub const NATIVE: u8 = 0;
+    pub const PYTH: u8 = 1;
+    pub const SWITCHBOARD: u8 = 2;
+
+    pub mod pyth {
+        anchor_lang::declare_id!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+    }
+
+    pub mod switchboard {
+        anchor_lang::declare_id!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+    }
+
+    // pyth v2 price account
+    const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+    const PYTH_VERSION: u32 = 2;
+    const PYTH_PRICE_ACCOUNT: u32 = 3;
+    const PYTH_TRADING: u32 = 1;
+    const PYTH_EXPO_OFFSET: usize = 20;
+    const PYTH_TIMESTAMP_OFFSET: usize = 96;
+    const PYTH_AGG_OFFSET: usize = 208;
+
+    // switchboard on-demand pull feed, offsets include the 8 byte discriminator. PullFeedAccountData in
+    // switchboard-xyz/solana-sdk rust/switchboard-on-demand/src/on_demand/accounts/pull_feed.rs holds
+    // 32 submissions of 64 bytes, authority, queue, feed hash, initialized_at, permissions,
+    // max_variance, min_responses, name and four single bytes, then last_update_timestamp, lut_slot
+    // and 32 reserved bytes ahead of result, a CurrentResult starting with value and std_dev.
+    const SWITCHBOARD_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+    const SWITCHBOARD_TIMESTAMP_OFFSET: usize = 2216;
+    const SWITCHBOARD_VALUE_OFFSET: usize = 2264;
+    const SWITCHBOARD_STD_DEV_OFFSET: usize = 2280;
+    const SWITCHBOARD_EXPO: i32 = -18;
+
+    // price and confidence in raw units of the loan currency
+    pub struct OraclePrice {
+        pub price: u64,
+        pub confidence: u64,
+        pub last_updated: u64,
+    }
+
+    pub fn read_price(price_feed: &PriceFeed, price_account: Option<&AccountInfo>) -> Result<OraclePrice> {
+        if price_feed.source == NATIVE {
+            return Ok(OraclePrice {
+                price: price_feed.price,
+                confidence: price_feed.confidence,
+                last_updated: price_feed.last_updated,
+            });
         }
 
-        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
-        // Transfer back nft collateral.
-        {
-            let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
-            let signer = &[&seeds[..]];
+        let price_account = price_account.ok_or(crate::ErrorCode::PriceFeedMismatch)?;
+        if price_account.key() != price_feed.price_account {
+            return Err(crate::ErrorCode::PriceFeedMismatch.into());
+        }
+        let data = price_account.try_borrow_data()?;
+        match price_feed.source {
+            PYTH if *price_account.owner == pyth::ID => read_pyth(&data, price_feed.decimals),
+            SWITCHBOARD if *price_account.owner == switchboard::ID => read_switchboard(&data, price_feed.decimals),
+            _ => Err(crate::ErrorCode::InvalidOraclePrice.into()),
+        }
+    }
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.nft_vault.to_account_info(),
-                    to: ctx.accounts.user_nft_vault.to_account_info(),
-                    authority: ctx.accounts.nft_vault.to_account_info(),
-                },
-                signer
-            );
-            token::transfer(cpi_ctx, 1)?;
+    fn read_pyth(data: &[u8], decimals: u8) -> Result<OraclePrice> {
+        if read_u32(data, 0)? != PYTH_MAGIC || read_u32(data, 4)? != PYTH_VERSION || read_u32(data, 8)? != PYTH_PRICE_ACCOUNT {
+            return Err(crate::ErrorCode::InvalidOraclePrice.into());
+        }
+        if read_u32(data, PYTH_AGG_OFFSET + 16)? != PYTH_TRADING {
+            return Err(crate::ErrorCode::InvalidOraclePrice.into());
         }
 
-        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
-        // Transfer back additional collateral
-        {
-            let seeds = &[ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
-            let signer = &[&seeds[..]];
+        let expo = read_u32(data, PYTH_EXPO_OFFSET)? as i32;
+        let price = i64::from_le_bytes(read_bytes(data, PYTH_AGG_OFFSET)?);
+        let confidence = u64::from_le_bytes(read_bytes(data, PYTH_AGG_OFFSET + 8)?);
+        let timestamp = i64::from_le_bytes(read_bytes(data, PYTH_TIMESTAMP_OFFSET)?);
+        Ok(OraclePrice {
+            price: scale(price as i128, expo, decimals)?,
+            confidence: scale(confidence as i128, expo, decimals)?,
+            last_updated: u64::try_from(timestamp).map_err(|_| crate::ErrorCode::InvalidOraclePrice)?,
+        })
+    }
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.stablecoin_vault.to_account_info(),
-                    to: ctx.accounts.user_stablecoin_vault.to_account_info(),
-                    authority: ctx.accounts.stablecoin_vault.to_account_info(),
-                },
-                signer
-            );
-            token::transfer(cpi_ctx, order.additional_collateral)?;
+    fn read_switchboard(data: &[u8], decimals: u8) -> Result<OraclePrice> {
+        if read_bytes::<8>(data, 0)? != SWITCHBOARD_DISCRIMINATOR {
+            return Err(crate::ErrorCode::InvalidOraclePrice.into());
         }
-        configuration.total_additional_collateral -= order.additional_collateral;
 
-        Ok(())
+        let value = i128::from_le_bytes(read_bytes(data, SWITCHBOARD_VALUE_OFFSET)?);
+        let std_dev = i128::from_le_bytes(read_bytes(data, SWITCHBOARD_STD_DEV_OFFSET)?);
+        let timestamp = i64::from_le_bytes(read_bytes(data, SWITCHBOARD_TIMESTAMP_OFFSET)?);
+        Ok(OraclePrice {
+            price: scale(value, SWITCHBOARD_EXPO, decimals)?,
+            confidence: scale(std_dev, SWITCHBOARD_EXPO, decimals)?,
+            last_updated: u64::try_from(timestamp).map_err(|_| crate::ErrorCode::InvalidOraclePrice)?,
+        })
     }
 
-    pub fn liquidate(ctx: Context<Liquidate>, _order_id: u64) -> Result<()> {
-        let order = &mut ctx.accounts.order;
+    // mantissa * 10^expo expressed with the decimals of the loan currency
+    fn scale(mantissa: i128, expo: i32, decimals: u8) -> Result<u64> {
+        let mantissa = u128::try_from(mantissa).map_err(|_| crate::ErrorCode::InvalidOraclePrice)?;
+        let shift = expo + decimals as i32;
+        let scaled = if shift >= 0 {
+            10u128
+                .checked_pow(shift as u32)
+                .and_then(|factor| mantissa.checked_mul(factor))
+                .ok_or(crate::ErrorCode::MathOverflow)?
+        } else {
+            10u128.checked_pow(shift.unsigned_abs()).map_or(0, |factor| mantissa / factor)
+        };
+        u64::try_from(scaled).map_err(|_| crate::ErrorCode::MathOverflow.into())
+    }
+
+    fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(data.get(offset..offset + N).ok_or(crate::ErrorCode::InvalidOraclePrice)?);
+        Ok(bytes)
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(read_bytes(data, offset)?))
+    }
+}
+
+#[program]
+pub mod nft_loans {
+    use super::*;
+
+    // Whoever opens the configuration of a mint becomes its authority.
+    pub fn initialize(ctx: Context<Initialize>, _fee_pt: u8) -> Result<()> {
         let configuration = &mut ctx.accounts.configuration;
+        configuration.stablecoin_mint = ctx.accounts.stablecoin_mint.key();
+        configuration.stablecoin_vault = ctx.accounts.stablecoin_vault.key();
+        configuration.order_id = 0;
+        configuration.fee_pt = _fee_pt;
+        configuration.total_additional_collateral = 0;
+        configuration.active_loans = 0;
+        configuration.total_principal_outstanding = 0;
+        configuration.total_volume_lent = 0;
+        configuration.total_interest_paid = 0;
+        configuration.total_liquidations = 0;
+        // Only zero-length loans are rejected until the authority sets the bounds
+        configuration.min_period = 1;
+        configuration.max_period = u64::MAX;
+        configuration.max_interest_bps = u64::MAX;
+        configuration.min_additional_collateral = 0;
+        configuration.authority = ctx.accounts.signer.key();
 
-        if order.loan_start_time == 0 && order.order_status == true {
-            return Err(ErrorCode::LoanNotProvided.into());
+        // The configuration mint is the first approved loan currency
+        let currency = &mut ctx.accounts.currency;
+        currency.configuration = configuration.key();
+        currency.stablecoin_mint = ctx.accounts.stablecoin_mint.key();
+        currency.stablecoin_vault = ctx.accounts.stablecoin_vault.key();
+        currency.fee_vault = ctx.accounts.fee_vault.key();
+        currency.enabled = true;
+        Ok(())
+    }
+
+    pub fn update_loan_terms(ctx: Context<UpdateLoanTerms>, _min_period: u64, _max_period: u64, _max_interest_bps: u64, _min_additional_collateral: u64) -> Result<()> {
+        if _min_period == 0 || _min_period > _max_period {
+            return Err(ErrorCode::InvalidLoanTerms.into());
         }
 
-        let clock = clock::Clock::get().unwrap();
-        if order.loan_start_time.checked_add(order.period).unwrap() > clock.unix_timestamp as u64 {
-            return Err(ErrorCode::RepaymentPeriodNotExceeded.into());
+        let configuration = &mut ctx.accounts.configuration;
+        configuration.min_period = _min_period;
+        configuration.max_period = _max_period;
+        configuration.max_interest_bps = _max_interest_bps;
+        configuration.min_additional_collateral = _min_additional_collateral;
+        Ok(())
+    }
+
+    // Defaulted loans are auctioned from start_bps down to end_bps of the debt over the duration.
+    // A zero duration hands the nft straight to the lender.
+    pub fn update_auction_terms(ctx: Context<UpdateLoanTerms>, _duration: u64, _start_bps: u64, _end_bps: u64) -> Result<()> {
+        if _start_bps < _end_bps {
+            return Err(ErrorCode::InvalidLoanTerms.into());
         }
 
-        if order.withdrew_at != 0 {
-            return Err(ErrorCode::AlreadyLiquidated.into());
+        let configuration = &mut ctx.accounts.configuration;
+        configuration.auction_duration = _duration;
+        configuration.auction_start_bps = _start_bps;
+        configuration.auction_end_bps = _end_bps;
+        Ok(())
+    }
+
+    // English auctions open at the closing price of the auction terms and every bid must beat
+    // the last one by the increment. Bids within the extension window push the end back.
+    pub fn update_auction_kind(ctx: Context<UpdateLoanTerms>, _kind: u8, _extension: u64, _min_bid_increment_bps: u64) -> Result<()> {
+        if _kind > constants::ENGLISH_AUCTION {
+            return Err(ErrorCode::InvalidLoanTerms.into());
         }
 
-        // Save Info
-        order.withdrew_at = clock.unix_timestamp as u64;
+        let configuration = &mut ctx.accounts.configuration;
+        configuration.auction_kind = _kind;
+        configuration.auction_extension = _extension;
+        configuration.min_bid_increment_bps = _min_bid_increment_bps;
+        Ok(())
+    }
 
+    // Keepers liquidating someone else's loan earn bounty_bps of the principal, out of the
+    // additional collateral going to the lender or out of the reserve in the fee vault.
+    pub fn update_keeper_bounty(ctx: Context<UpdateLoanTerms>, _bounty_bps: u64, _from_reserve: bool) -> Result<()> {
+        if _bounty_bps > constants::BASIS_POINTS {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let configuration = &mut ctx.accounts.configuration;
+        configuration.keeper_bounty_bps = _bounty_bps;
+        configuration.bounty_from_reserve = _from_reserve;
+        Ok(())
+    }
+
+    pub fn add_currency(ctx: Context<AddCurrency>, _min_additional_collateral: u64) -> Result<()> {
+        let currency = &mut ctx.accounts.currency;
+        currency.configuration = ctx.accounts.configuration.key();
+        currency.stablecoin_mint = ctx.accounts.stablecoin_mint.key();
+        currency.stablecoin_vault = ctx.accounts.stablecoin_vault.key();
+        currency.fee_vault = ctx.accounts.fee_vault.key();
+        currency.enabled = true;
+        currency.min_additional_collateral = _min_additional_collateral;
+        Ok(())
+    }
+
+    // The minimum of the configuration mint is kept on the configuration.
+    pub fn update_currency(ctx: Context<UpdateCurrency>, _enabled: bool, _min_additional_collateral: u64) -> Result<()> {
+        let configuration = &mut ctx.accounts.configuration;
+        let currency = &mut ctx.accounts.currency;
+        currency.enabled = _enabled;
+        if configuration.totals(currency) {
+            configuration.min_additional_collateral = _min_additional_collateral;
+        } else {
+            currency.min_additional_collateral = _min_additional_collateral;
+        }
+        Ok(())
+    }
+
+    pub fn add_collateral_token(ctx: Context<AddCollateralToken>) -> Result<()> {
+        token_2022::mint(&ctx.accounts.collateral_mint)?.check_collateral()?;
+
+        let collateral_token = &mut ctx.accounts.collateral_token;
+        collateral_token.configuration = ctx.accounts.configuration.key();
+        collateral_token.mint = ctx.accounts.collateral_mint.key();
+        collateral_token.enabled = true;
+        Ok(())
+    }
+
+    pub fn update_collateral_token(ctx: Context<UpdateCollateralToken>, _enabled: bool) -> Result<()> {
+        ctx.accounts.collateral_token.enabled = _enabled;
+        Ok(())
+    }
+
+    pub fn add_price_feed(ctx: Context<AddPriceFeed>, _collection: Pubkey, _stablecoin_mint: Pubkey, _oracle: Pubkey, _max_staleness: u64, _liquidation_ltv_bps: u64) -> Result<()> {
+        if _liquidation_ltv_bps == 0 {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.configuration = ctx.accounts.configuration.key();
+        price_feed.collection = _collection;
+        price_feed.stablecoin_mint = _stablecoin_mint;
+        price_feed.oracle = _oracle;
+        price_feed.max_staleness = _max_staleness;
+        price_feed.liquidation_ltv_bps = _liquidation_ltv_bps;
+        Ok(())
+    }
+
+    pub fn update_price_feed(ctx: Context<UpdatePriceFeed>, _oracle: Pubkey, _max_staleness: u64, _liquidation_ltv_bps: u64) -> Result<()> {
+        if _liquidation_ltv_bps == 0 {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.oracle = _oracle;
+        price_feed.max_staleness = _max_staleness;
+        price_feed.liquidation_ltv_bps = _liquidation_ltv_bps;
+        Ok(())
+    }
+
+    // Selects where the price of the collection is read from. Pyth and Switchboard prices are
+    // converted to the decimals of the loan currency.
+    pub fn set_price_source(ctx: Context<UpdatePriceFeed>, _source: u8, _price_account: Pubkey, _decimals: u8) -> Result<()> {
+        if _source > oracle::SWITCHBOARD {
+            return Err(ErrorCode::InvalidOraclePrice.into());
+        }
+
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.source = _source;
+        price_feed.price_account = _price_account;
+        price_feed.decimals = _decimals;
+        Ok(())
+    }
+
+    pub fn submit_price(ctx: Context<SubmitPrice>, _price: u64, _confidence: u64) -> Result<()> {
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.price = _price;
+        price_feed.confidence = _confidence;
+        price_feed.last_updated = clock::Clock::get().unwrap().unix_timestamp as u64;
+        Ok(())
+    }
+
+    pub fn initialize_user_stats(ctx: Context<InitializeUserStats>) -> Result<()> {
+        let user_stats = &mut ctx.accounts.user_stats;
+        user_stats.open(ctx.accounts.signer.key(), ctx.accounts.configuration.key());
+        user_stats.open_orders = 0;
+        user_stats.active_borrows = 0;
+        user_stats.active_loans = 0;
+        user_stats.total_borrowed = 0;
+        user_stats.total_lent = 0;
+        user_stats.order_ids = Vec::new();
+        Ok(())
+    }
+
+    // Grows a configuration created before the loan metrics to the current layout. Only the
+    // upgrade authority of the program can migrate it, and it becomes the authority of the
+    // configuration. The deployed fields keep their values and the appended counters start at
+    // zero, loans already funded are counted when their orders are migrated.
+    pub fn migrate_configuration(ctx: Context<MigrateConfiguration>) -> Result<()> {
+        let configuration = ctx.accounts.configuration.to_account_info();
+        grow_account(
+            &configuration,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + Configuration::LEN,
+        )?;
+
+        let mut data = configuration.try_borrow_mut_data()?;
+        let mut migrated = Configuration::try_deserialize(&mut &data[..])?;
+        migrated.authority = ctx.accounts.signer.key();
+        // Only zero-length loans are rejected until the authority sets the bounds
+        migrated.min_period = 1;
+        migrated.max_period = u64::MAX;
+        migrated.max_interest_bps = u64::MAX;
+
+        // The configuration mint becomes the first approved loan currency, with the additional
+        // collateral already locked in its vault and an empty fee vault
+        let currency = &mut ctx.accounts.currency;
+        currency.configuration = configuration.key();
+        currency.stablecoin_mint = migrated.stablecoin_mint;
+        currency.stablecoin_vault = migrated.stablecoin_vault;
+        currency.enabled = true;
+        currency.total_additional_collateral = migrated.total_additional_collateral;
+        currency.fee_vault = ctx.accounts.fee_vault.key();
+
+        let mut writer: &mut [u8] = &mut data[..];
+        migrated.try_serialize(&mut writer)?;
+        Ok(())
+    }
+
+    // Grows an order created before the position index to the current layout. Anyone can pay
+    // the extra rent, the new fields only take what the order id and the configuration tell.
+    // The configuration has to be migrated first.
+    pub fn migrate_order(ctx: Context<MigrateOrder>, _order_id: u64) -> Result<()> {
+        let order = ctx.accounts.order.to_account_info();
+        grow_account(
+            &order,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + Order::LEN,
+        )?;
+
+        // The appended fields read as zero until they are filled in
+        let mut data = order.try_borrow_mut_data()?;
+        let mut migrated = Order::try_deserialize(&mut &data[..])?;
+        migrated.order_id = _order_id;
+        // Orders were lent in the configuration mint before there were other currencies
+        migrated.stablecoin_mint = ctx.accounts.configuration.stablecoin_mint;
+
+        // Loans funded before the metrics existed are counted now
+        if !migrated.order_status {
+            ctx.accounts.configuration.record_loan(&mut ctx.accounts.currency, migrated.request_amount)?;
+        }
+
+        let mut writer: &mut [u8] = &mut data[..];
+        migrated.try_serialize(&mut writer)?;
+        Ok(())
+    }
+
+    // create_order
+    pub fn create_order(ctx: Context<CreateOrder>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        // Transfer collateral to vault.
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_nft_vault.to_account_info(), &ctx.accounts.nft_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), 1)?;
+
+        // Transfer additional collateral to vault
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _additional_collateral)?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = ctx.accounts.nft_mint.key();
+        order.nft_vault = ctx.accounts.nft_vault.key();
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+
+        // Transfer back nft collateral.
         let nonce = *(ctx.bumps.get("nft_vault").unwrap());
-        // Transfer nft collateral.
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order)?;
+
+        Ok(())
+    }
+
+    pub fn give_loan(ctx: Context<GiveLoan>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+
+        if order.borrower == ctx.accounts.signer.key() {
+            return Err(ErrorCode::CannotLendToSelf.into());
+        }
+
+        // Transfer loan to the borrower
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.borrower_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), order.request_amount)?;
+
+        // Save Info
+        order.lender = ctx.accounts.signer.key();
+        order.loan_start_time = clock::Clock::get().unwrap().unix_timestamp as u64;
+        order.order_status = false;
+        order.note_mint = ctx.accounts.note_mint.key();
+
+        ctx.accounts.lender_stats.open(ctx.accounts.signer.key(), ctx.accounts.configuration.key());
+        start_loan(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+        fit_user_stats(&ctx.accounts.lender_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Mint the loan note and drop the mint authority so it stays 1-of-1
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.note_mint.to_account_info(),
+                    to: ctx.accounts.lender_note_vault.to_account_info(),
+                    authority: ctx.accounts.order.to_account_info(),
+                },
+                signer
+            );
+            token::mint_to(cpi_ctx, 1)?;
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::SetAuthority {
+                    current_authority: ctx.accounts.order.to_account_info(),
+                    account_or_mint: ctx.accounts.note_mint.to_account_info(),
+                },
+                signer
+            );
+            token::set_authority(cpi_ctx, token::spl_token::instruction::AuthorityType::MintTokens, None)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn payback(ctx: Context<Payback>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), order.repayment_amount()?)?;
+
+        // Transfer back nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), order.additional_collateral)?;
+
+        close_listing(&ctx.accounts.listing, &ctx.accounts.payee, ctx.program_id)?;
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    // hands the right to pay back the loan and take the nft to another user
+    pub fn transfer_borrower(ctx: Context<TransferBorrower>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        if order.loan_start_time == 0 && order.order_status == true {
+            return Err(ErrorCode::LoanNotProvided.into());
+        }
+        if order.withdrew_at != 0 {
+            return Err(ErrorCode::AlreadyLiquidated.into());
+        }
+
+        let clock = clock::Clock::get().unwrap();
+        if order.due_at()? < clock.unix_timestamp as u64 {
+            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+        }
+
+        order.borrower = ctx.accounts.new_borrower.key();
+        ctx.accounts.new_borrower_stats.open(ctx.accounts.new_borrower.key(), ctx.accounts.configuration.key());
+        move_borrow(&mut ctx.accounts.borrower_stats, &mut ctx.accounts.new_borrower_stats, order.order_id)?;
+        fit_user_stats(&ctx.accounts.new_borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    // pays back the loan out of the price a buyer pays for the nft
+    pub fn sell_and_repay(ctx: Context<SellAndRepay>, _order_id: u64, _price: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        if order.loan_start_time == 0 && order.order_status == true {
+            return Err(ErrorCode::LoanNotProvided.into());
+        }
+        if order.withdrew_at != 0 {
+            return Err(ErrorCode::AlreadyLiquidated.into());
+        }
+
+        let clock = clock::Clock::get().unwrap();
+        if order.due_at()? < clock.unix_timestamp as u64 {
+            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+        }
+
+        // The buyer pays exactly the price. It has to cover the debt, which goes to the payee,
+        // and the borrower keeps the rest.
+        let repayment = order.repayment_amount()?;
+        let surplus = _price.checked_sub(repayment).ok_or(ErrorCode::SalePriceTooLow)?;
+
+        // Save Info
+        order.paid_back_at = clock.unix_timestamp as u64;
+
+        // Pay Loan
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.lender_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, repayment)?;
+        }
+
+        // Pay the borrower
+        if surplus > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.borrower_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, surplus)?;
+        }
+
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        // Deliver the nft to the buyer.
         {
             let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
             let signer = &[&seeds[..]];
@@ -267,7 +1200,7 @@ pub mod nft_loans {
                 ctx.accounts.token_program.to_account_info(),
                 token::Transfer {
                     from: ctx.accounts.nft_vault.to_account_info(),
-                    to: ctx.accounts.user_nft_vault.to_account_info(),
+                    to: ctx.accounts.buyer_nft_vault.to_account_info(),
                     authority: ctx.accounts.nft_vault.to_account_info(),
                 },
                 signer
@@ -276,160 +1209,7958 @@ pub mod nft_loans {
         }
 
         let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
-        // Transfer additional collateral
+        // Transfer back additional collateral
+        {
+            let seeds = &[vault_prefix(&ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key), ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.borrower_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.stablecoin_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, order.additional_collateral)?;
+        }
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    // offers the loan note for sale, the listing is approved to move it on purchase
+    pub fn list_loan(ctx: Context<ListLoan>, _order_id: u64, _price: u64) -> Result<()> {
+        let order = &ctx.accounts.order;
+
+        if order.loan_start_time == 0 && order.order_status == true {
+            return Err(ErrorCode::LoanNotProvided.into());
+        }
+        if order.withdrew_at != 0 {
+            return Err(ErrorCode::AlreadyLiquidated.into());
+        }
+        if _price == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+        // give_loan_sol and pools mint no note, those loans stay with their lender
+        if order.stablecoin_mint == constants::NATIVE_SOL_MINT || order.note_mint == Pubkey::default() {
+            return Err(ErrorCode::UnsupportedLoanListing.into());
+        }
+        // The seller holds the note
+        let note_vault = &ctx.accounts.note_vault;
+        if note_vault.mint != order.note_mint || note_vault.amount != 1 {
+            return Err(ErrorCode::InvalidLoanNote.into());
+        }
+
+        // The note stays with the seller so the seller keeps being repaid until the sale
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Approve {
+                    to: ctx.accounts.note_vault.to_account_info(),
+                    delegate: ctx.accounts.listing.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::approve(cpi_ctx, 1)?;
+        }
+
+        let clock = clock::Clock::get().unwrap();
+        let listing = &mut ctx.accounts.listing;
+        listing.order = order.key();
+        listing.seller = ctx.accounts.signer.key();
+        listing.note_vault = ctx.accounts.note_vault.key();
+        listing.price = _price;
+        listing.listed_at = clock.unix_timestamp as u64;
+
+        Ok(())
+    }
+
+    pub fn cancel_loan_listing(ctx: Context<CancelLoanListing>) -> Result<()> {
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Revoke {
+                source: ctx.accounts.note_vault.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
+            },
+        );
+        token::revoke(cpi_ctx)
+    }
+
+    // Anyone can close the listing of a loan that was paid back or liquidated, or that the seller
+    // can no longer deliver because the note left the listed account. The rent goes to the seller.
+    pub fn close_loan_listing(ctx: Context<CloseLoanListing>) -> Result<()> {
+        if ctx.accounts.order.data_is_empty() {
+            return Ok(());
+        }
+        let listing = &ctx.accounts.listing;
+        let listed = Account::<TokenAccount>::try_from(&ctx.accounts.note_vault)
+            .map(|note_vault| note_vault.owner == listing.seller && note_vault.amount == 1 && note_vault.delegate == COption::Some(listing.key()))
+            .unwrap_or(false);
+        if listed {
+            return Err(ErrorCode::LoanStillActive.into());
+        }
+        Ok(())
+    }
+
+    pub fn buy_loan(ctx: Context<BuyLoan>, _order_id: u64, _max_price: u64) -> Result<()> {
+        let order = &ctx.accounts.order;
+
+        if order.stablecoin_mint == constants::NATIVE_SOL_MINT || order.note_mint == Pubkey::default() {
+            return Err(ErrorCode::UnsupportedLoanListing.into());
+        }
+        if order.withdrew_at != 0 {
+            return Err(ErrorCode::AlreadyLiquidated.into());
+        }
+        // The seller can relist at another price before the purchase lands
+        if ctx.accounts.listing.price > _max_price {
+            return Err(ErrorCode::ListingPriceTooHigh.into());
+        }
+
+        // Pay the seller
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.seller_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, ctx.accounts.listing.price)?;
+        }
+
+        let nonce = *(ctx.bumps.get("listing").unwrap());
+        // Move the note to the buyer, who is repaid from now on
         {
-            let seeds = &[ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+            let order_key = order.key();
+            let seeds = &[order_key.as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref(), &[nonce]];
             let signer = &[&seeds[..]];
 
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.stablecoin_vault.to_account_info(),
-                    to: ctx.accounts.lender_stablecoin_vault.to_account_info(),
-                    authority: ctx.accounts.stablecoin_vault.to_account_info(),
-                },
-                signer
-            );
-            token::transfer(cpi_ctx, order.additional_collateral)?;
-        }
-        configuration.total_additional_collateral -= order.additional_collateral;
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.note_vault.to_account_info(),
+                    to: ctx.accounts.buyer_note_vault.to_account_info(),
+                    authority: ctx.accounts.listing.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+        }
+
+        // The buyer takes over the position of the lender
+        let configuration = ctx.accounts.configuration.key();
+        move_loan(&mut ctx.accounts.order, &mut ctx.accounts.lender_stats, &mut ctx.accounts.buyer_stats, ctx.accounts.signer.key(), configuration)?;
+        fit_user_stats(&ctx.accounts.buyer_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    // The holder of a note received outside the marketplace takes over the position of the lender.
+    pub fn claim_loan_position(ctx: Context<ClaimLoanPosition>, _order_id: u64) -> Result<()> {
+        let order = &ctx.accounts.order;
+
+        if order.loan_start_time == 0 && order.order_status == true {
+            return Err(ErrorCode::LoanNotProvided.into());
+        }
+        if order.withdrew_at != 0 {
+            return Err(ErrorCode::AlreadyLiquidated.into());
+        }
+        if order.payee(&ctx.accounts.note_vault)? != ctx.accounts.signer.key() {
+            return Err(ErrorCode::InvalidLoanNote.into());
+        }
+
+        let configuration = ctx.accounts.configuration.key();
+        move_loan(&mut ctx.accounts.order, &mut ctx.accounts.lender_stats, &mut ctx.accounts.holder_stats, ctx.accounts.signer.key(), configuration)?;
+        fit_user_stats(&ctx.accounts.holder_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    // Burns the note of a paid back or liquidated loan and closes its account, the rent goes to the holder.
+    pub fn close_loan_note(ctx: Context<CloseLoanNote>) -> Result<()> {
+        if !ctx.accounts.order.data_is_empty() {
+            return Err(ErrorCode::LoanStillActive.into());
+        }
+
+        if ctx.accounts.note_vault.amount > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.note_mint.to_account_info(),
+                    from: ctx.accounts.note_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::burn(cpi_ctx, ctx.accounts.note_vault.amount)?;
+        }
+
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.note_vault.to_account_info(),
+                destination: ctx.accounts.signer.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
+            },
+        );
+        token::close_account(cpi_ctx)
+    }
+
+    pub fn liquidate(ctx: Context<Liquidate>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_liquidated(&ctx.accounts.configuration, order, ctx.remaining_accounts, clock.unix_timestamp as u64, ctx.program_id)?;
+
+        // Transfer nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Keepers are paid for settling loans of offline lenders
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), order.payee(&ctx.accounts.note_vault)?, order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer additional collateral and the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        close_listing(&ctx.accounts.listing, &ctx.accounts.payee, ctx.program_id)?;
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    // Anyone can start the auction of a defaulted loan, keepers are paid the bounty up front.
+    pub fn start_dutch_auction<'info>(ctx: Context<'_, '_, '_, 'info, StartDutchAuction<'info>>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let payee = ctx.accounts.order.payee(&ctx.accounts.note_vault)?;
+        open_auction(&ctx.accounts.configuration, &mut ctx.accounts.order, &mut ctx.accounts.auction, constants::DUTCH_AUCTION, ctx.accounts.signer.key(), ctx.remaining_accounts, clock.unix_timestamp as u64)?;
+
+        let payout = auction_bounty(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), payee, &mut ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        Ok(())
+    }
+
+    pub fn buy_dutch_auction(ctx: Context<BuyDutchAuction>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        let auction = &ctx.accounts.auction;
+        if auction.kind != constants::DUTCH_AUCTION {
+            return Err(ErrorCode::InvalidAuctionKind.into());
+        }
+        if now >= auction.end_time {
+            return Err(ErrorCode::AuctionEnded.into());
+        }
+
+        let order = &ctx.accounts.order;
+        let price = auction.current_price(now)?;
+        let split = split_proceeds(auction.debt, price, order.additional_collateral)?;
+
+        // Pay the lender, then the borrower
+        for (to, amount) in [
+            (ctx.accounts.lender_stablecoin_vault.to_account_info(), split.lender_from_proceeds),
+            (ctx.accounts.borrower_stablecoin_vault.to_account_info(), split.borrower_from_proceeds),
+        ] {
+            if amount > 0 {
+                let cpi_ctx = CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.buyer_stablecoin_vault.to_account_info(),
+                        to,
+                        authority: ctx.accounts.signer.to_account_info(),
+                    },
+                );
+                token::transfer(cpi_ctx, amount)?;
+            }
+        }
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        // Transfer additional collateral
+        {
+            let seeds = &[vault_prefix(&ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key), ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            for (to, amount) in [
+                (ctx.accounts.lender_stablecoin_vault.to_account_info(), split.lender_from_collateral),
+                (ctx.accounts.borrower_stablecoin_vault.to_account_info(), split.borrower_from_collateral),
+            ] {
+                if amount > 0 {
+                    let cpi_ctx = CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: ctx.accounts.stablecoin_vault.to_account_info(),
+                            to,
+                            authority: ctx.accounts.stablecoin_vault.to_account_info(),
+                        },
+                        signer
+                    );
+                    token::transfer(cpi_ctx, amount)?;
+                }
+            }
+        }
+
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        // Transfer nft collateral.
+        {
+            let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.nft_vault.to_account_info(),
+                    to: ctx.accounts.user_nft_vault.to_account_info(),
+                    authority: ctx.accounts.nft_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+        }
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn claim_dutch_auction(ctx: Context<ClaimDutchAuction>, _order_id: u64) -> Result<()> {
+        if ctx.accounts.auction.kind != constants::DUTCH_AUCTION {
+            return Err(ErrorCode::InvalidAuctionKind.into());
+        }
+        let clock = clock::Clock::get().unwrap();
+        if (clock.unix_timestamp as u64) < ctx.accounts.auction.end_time {
+            return Err(ErrorCode::AuctionNotEnded.into());
+        }
+
+        let order = &ctx.accounts.order;
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        // Transfer nft collateral.
+        {
+            let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.nft_vault.to_account_info(),
+                    to: ctx.accounts.user_nft_vault.to_account_info(),
+                    authority: ctx.accounts.nft_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+        }
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        // Transfer additional collateral
+        {
+            let seeds = &[vault_prefix(&ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key), ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.lender_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.stablecoin_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, order.additional_collateral)?;
+        }
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    // Anyone can start the auction of a defaulted loan, keepers are paid the bounty up front.
+    pub fn start_english_auction<'info>(ctx: Context<'_, '_, '_, 'info, StartEnglishAuction<'info>>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let payee = ctx.accounts.order.payee(&ctx.accounts.note_vault)?;
+        open_auction(&ctx.accounts.configuration, &mut ctx.accounts.order, &mut ctx.accounts.auction, constants::ENGLISH_AUCTION, ctx.accounts.signer.key(), ctx.remaining_accounts, clock.unix_timestamp as u64)?;
+        ctx.accounts.auction.bid_vault = ctx.accounts.bid_vault.key();
+
+        let payout = auction_bounty(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), payee, &mut ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        Ok(())
+    }
+
+    pub fn bid(ctx: Context<Bid>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        let configuration = &ctx.accounts.configuration;
+        let auction = &mut ctx.accounts.auction;
+        if auction.kind != constants::ENGLISH_AUCTION {
+            return Err(ErrorCode::InvalidAuctionKind.into());
+        }
+        if now >= auction.end_time {
+            return Err(ErrorCode::AuctionEnded.into());
+        }
+        if _amount < auction.min_bid(configuration.min_bid_increment_bps)? {
+            return Err(ErrorCode::BidTooLow.into());
+        }
+
+        // Escrow the bid
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.bid_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, _amount)?;
+        }
+
+        let nonce = *(ctx.bumps.get("bid_vault").unwrap());
+        // Refund the outbid bidder
+        if auction.highest_bid > 0 {
+            let auction_key = auction.key();
+            let seeds = &[auction_key.as_ref(), constants::NFT_COLLATERIZED_LOANS_BID_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.bid_vault.to_account_info(),
+                    to: ctx.accounts.previous_bidder_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.bid_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, auction.highest_bid)?;
+        }
+
+        auction.bidder = ctx.accounts.signer.key();
+        auction.highest_bid = _amount;
+        // Late bids extend the deadline so they can be answered
+        let extended = now.checked_add(configuration.auction_extension).ok_or(ErrorCode::MathOverflow)?;
+        auction.end_time = auction.end_time.max(extended);
+        Ok(())
+    }
+
+    pub fn settle_english_auction(ctx: Context<SettleEnglishAuction>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        let auction = &ctx.accounts.auction;
+        if auction.kind != constants::ENGLISH_AUCTION {
+            return Err(ErrorCode::InvalidAuctionKind.into());
+        }
+        if (clock.unix_timestamp as u64) < auction.end_time {
+            return Err(ErrorCode::AuctionNotEnded.into());
+        }
+
+        // Without bids the lender takes the nft and the additional collateral
+        let order = &ctx.accounts.order;
+        let split = split_proceeds(auction.debt, auction.highest_bid, order.additional_collateral)?;
+
+        let nonce = *(ctx.bumps.get("bid_vault").unwrap());
+        // Pay the lender, then the borrower, and close the bid vault
+        {
+            let auction_key = auction.key();
+            let seeds = &[auction_key.as_ref(), constants::NFT_COLLATERIZED_LOANS_BID_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            for (to, amount) in [
+                (ctx.accounts.lender_stablecoin_vault.to_account_info(), split.lender_from_proceeds),
+                (ctx.accounts.borrower_stablecoin_vault.to_account_info(), split.borrower_from_proceeds),
+            ] {
+                if amount > 0 {
+                    let cpi_ctx = CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: ctx.accounts.bid_vault.to_account_info(),
+                            to,
+                            authority: ctx.accounts.bid_vault.to_account_info(),
+                        },
+                        signer
+                    );
+                    token::transfer(cpi_ctx, amount)?;
+                }
+            }
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: ctx.accounts.bid_vault.to_account_info(),
+                    destination: ctx.accounts.keeper.to_account_info(),
+                    authority: ctx.accounts.bid_vault.to_account_info(),
+                },
+                signer
+            );
+            token::close_account(cpi_ctx)?;
+        }
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        // Transfer additional collateral
+        {
+            let seeds = &[vault_prefix(&ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key), ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            for (to, amount) in [
+                (ctx.accounts.lender_stablecoin_vault.to_account_info(), split.lender_from_collateral),
+                (ctx.accounts.borrower_stablecoin_vault.to_account_info(), split.borrower_from_collateral),
+            ] {
+                if amount > 0 {
+                    let cpi_ctx = CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: ctx.accounts.stablecoin_vault.to_account_info(),
+                            to,
+                            authority: ctx.accounts.stablecoin_vault.to_account_info(),
+                        },
+                        signer
+                    );
+                    token::transfer(cpi_ctx, amount)?;
+                }
+            }
+        }
+
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        // Transfer nft collateral to the winner
+        {
+            let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.nft_vault.to_account_info(),
+                    to: ctx.accounts.user_nft_vault.to_account_info(),
+                    authority: ctx.accounts.nft_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+        }
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    // Pays back several loans of the signer. Invalid entries are skipped unless the batch is atomic.
+    pub fn payback_batch<'info>(ctx: Context<'_, '_, '_, 'info, PaybackBatch<'info>>, _atomic: bool) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        BatchOrder::check_entries(remaining_accounts, BatchOrder::PAYBACK_STRIDE, ctx.program_id)?;
+
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        let configuration_key = ctx.accounts.configuration.key();
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let stablecoin_mint_key = ctx.accounts.stablecoin_mint.key();
+        let seeds = &[vault_prefix(&ctx.accounts.configuration, &stablecoin_mint_key), stablecoin_mint_key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+        let signer = &[&seeds[..]];
+
+        for (index, accounts) in remaining_accounts.chunks_exact(BatchOrder::PAYBACK_STRIDE).enumerate() {
+            let loaded = BatchOrder::load(accounts, configuration_key, ctx.accounts.stablecoin_vault.key(), ctx.program_id).and_then(|entry| {
+                entry.check_payback(&ctx.accounts.signer.key(), now).map_err(Into::into).map(|_| entry)
+            });
+            let mut entry = match skip_or_fail(loaded, index, _atomic)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            // Pay Loan
+            {
+                let cpi_ctx = CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.user_stablecoin_vault.to_account_info(),
+                        to: entry.lender_stablecoin_vault.clone(),
+                        authority: ctx.accounts.signer.to_account_info(),
+                    },
+                );
+                token::transfer(cpi_ctx, entry.order.repayment_amount()?)?;
+            }
+
+            // Transfer back nft collateral.
+            entry.transfer_nft(entry.user_nft_vault.clone(), ctx.accounts.token_program.to_account_info())?;
+
+            // Transfer back additional collateral
+            if entry.order.additional_collateral > 0 {
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.stablecoin_vault.to_account_info(),
+                        to: ctx.accounts.user_stablecoin_vault.to_account_info(),
+                        authority: ctx.accounts.stablecoin_vault.to_account_info(),
+                    },
+                    signer
+                );
+                token::transfer(cpi_ctx, entry.order.additional_collateral)?;
+            }
+
+            entry.order.paid_back_at = now;
+            settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut entry.borrower_stats, &mut entry.lender_stats, &entry.order)?;
+            entry.finish(ctx.accounts.signer.to_account_info(), ctx.program_id)?;
+        }
+
+        Ok(())
+    }
+
+    // Liquidates several due loans, paying the keeper bounty for each. Loans can only be
+    // liquidated early through `liquidate` with a price feed. While auctions are enabled the
+    // whole batch fails with `AuctionRequired`. A batch that is not atomic only skips entries
+    // failing validation, a failed transfer or an unsupported order still reverts all of it.
+    pub fn liquidate_batch<'info>(ctx: Context<'_, '_, '_, 'info, LiquidateBatch<'info>>, _atomic: bool) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        BatchOrder::check_entries(remaining_accounts, BatchOrder::LIQUIDATE_STRIDE, ctx.program_id)?;
+        // Batches only hold auctionable orders
+        if ctx.accounts.configuration.auction_duration > 0 {
+            return Err(ErrorCode::AuctionRequired.into());
+        }
+
+        let clock = clock::Clock::get().unwrap();
+        let now = clock.unix_timestamp as u64;
+        let configuration_key = ctx.accounts.configuration.key();
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        let stablecoin_mint_key = ctx.accounts.stablecoin_mint.key();
+        let mut fee_vault_balance = ctx.accounts.fee_vault.amount;
+
+        for (index, accounts) in remaining_accounts.chunks_exact(BatchOrder::LIQUIDATE_STRIDE).enumerate() {
+            let loaded = BatchOrder::load(accounts, configuration_key, ctx.accounts.stablecoin_vault.key(), ctx.program_id).and_then(|entry| {
+                entry.check_liquidation(&accounts[8].key(), now).map_err(Into::into).map(|_| entry)
+            });
+            let mut entry = match skip_or_fail(loaded, index, _atomic)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            // Transfer nft collateral.
+            entry.transfer_nft(entry.user_nft_vault.clone(), ctx.accounts.token_program.to_account_info())?;
+
+            // Transfer additional collateral and the bounty
+            let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, fee_vault_balance, ctx.accounts.signer.key(), entry.payee, &entry.order)?;
+            pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, &stablecoin_mint_key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &entry.lender_stablecoin_vault, &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+            fee_vault_balance = fee_vault_balance.checked_sub(payout.bounty_from_reserve).ok_or(ErrorCode::MathOverflow)?;
+
+            entry.order.withdrew_at = now;
+            settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut entry.borrower_stats, &mut entry.lender_stats, &entry.order)?;
+            entry.finish(accounts[8].clone(), ctx.program_id)?;
+        }
+
+        Ok(())
+    }
+
+    // Pools lend a currency against nfts of a verified collection on behalf of their depositors.
+    pub fn create_pool(ctx: Context<CreatePool>, _collection: Pubkey, _manager: Pubkey, _max_loan_amount: u64, _max_period: u64, _max_utilization_bps: u64) -> Result<()> {
+        if _max_utilization_bps > constants::BASIS_POINTS {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.configuration = ctx.accounts.configuration.key();
+        pool.collection = _collection;
+        pool.stablecoin_mint = ctx.accounts.stablecoin_mint.key();
+        pool.manager = _manager;
+        pool.liquidity_vault = ctx.accounts.liquidity_vault.key();
+        pool.share_mint = ctx.accounts.share_mint.key();
+        pool.share_escrow = ctx.accounts.share_escrow.key();
+        pool.max_loan_amount = _max_loan_amount;
+        pool.max_period = _max_period;
+        pool.max_utilization_bps = _max_utilization_bps;
+        pool.borrow_index = constants::BORROW_INDEX_ONE;
+        pool.last_accrued_at = clock::Clock::get().unwrap().unix_timestamp as u64;
+        Ok(())
+    }
+
+    pub fn update_pool_limits(ctx: Context<UpdatePoolLimits>, _max_loan_amount: u64, _max_period: u64, _max_utilization_bps: u64) -> Result<()> {
+        if _max_utilization_bps > constants::BASIS_POINTS {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.max_loan_amount = _max_loan_amount;
+        pool.max_period = _max_period;
+        pool.max_utilization_bps = _max_utilization_bps;
+        Ok(())
+    }
+
+    // Share of the pool value new loans must leave liquid, in basis points.
+    pub fn update_pool_reserve(ctx: Context<UpdatePoolLimits>, _reserve_bps: u64) -> Result<()> {
+        if _reserve_bps > constants::BASIS_POINTS {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        ctx.accounts.pool.reserve_bps = _reserve_bps;
+        Ok(())
+    }
+
+    // Yearly borrow rate curve, in basis points, kinked at the optimal utilization.
+    pub fn update_pool_rates(ctx: Context<UpdatePoolRates>, _base_rate_bps: u64, _slope1_bps: u64, _optimal_utilization_bps: u64, _slope2_bps: u64) -> Result<()> {
+        if _optimal_utilization_bps == 0 || _optimal_utilization_bps > constants::BASIS_POINTS {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+        let max_rate_bps = _base_rate_bps
+            .checked_add(_slope1_bps)
+            .and_then(|rate| rate.checked_add(_slope2_bps))
+            .ok_or(ErrorCode::MathOverflow)?;
+        if max_rate_bps > constants::MAX_BORROW_RATE_BPS {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        // Interest so far accrues at the previous rates
+        pool.accrue(ctx.accounts.liquidity_vault.amount, clock::Clock::get().unwrap().unix_timestamp as u64)?;
+        pool.base_rate_bps = _base_rate_bps;
+        pool.slope1_bps = _slope1_bps;
+        pool.optimal_utilization_bps = _optimal_utilization_bps;
+        pool.slope2_bps = _slope2_bps;
+        Ok(())
+    }
+
+    pub fn deposit_pool(ctx: Context<DepositPool>, _amount: u64) -> Result<()> {
+        if _amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+        ctx.accounts.pool.accrue(ctx.accounts.liquidity_vault.amount, clock::Clock::get().unwrap().unix_timestamp as u64)?;
+        let shares = ctx.accounts.pool.shares_for_deposit(_amount, ctx.accounts.liquidity_vault.amount, ctx.accounts.share_mint.supply)?;
+        if shares == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+
+        // Transfer the deposit to the pool
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.liquidity_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, _amount)?;
+        }
+
+        let nonce = *(ctx.bumps.get("pool").unwrap());
+        // Mint the shares
+        {
+            let pool = &ctx.accounts.pool;
+            let seeds = &[pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    to: ctx.accounts.user_share_vault.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                signer
+            );
+            token::mint_to(cpi_ctx, shares)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn withdraw_pool(ctx: Context<WithdrawPool>, _shares: u64) -> Result<()> {
+        if _shares == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+        ctx.accounts.pool.accrue(ctx.accounts.liquidity_vault.amount, clock::Clock::get().unwrap().unix_timestamp as u64)?;
+        let amount = ctx.accounts.pool.amount_for_shares(_shares, ctx.accounts.liquidity_vault.amount, ctx.accounts.share_mint.supply)?;
+        // Queued withdrawals are paid first
+        let queued = ctx.accounts.pool.queued_value(ctx.accounts.liquidity_vault.amount, ctx.accounts.share_mint.supply)?;
+        if amount.checked_add(queued).ok_or(ErrorCode::MathOverflow)? > ctx.accounts.liquidity_vault.amount {
+            return Err(ErrorCode::InsufficientPoolLiquidity.into());
+        }
+
+        // Burn the shares
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.user_share_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::burn(cpi_ctx, _shares)?;
+        }
+
+        let nonce = *(ctx.bumps.get("pool").unwrap());
+        // Transfer the withdrawal
+        {
+            let pool = &ctx.accounts.pool;
+            let seeds = &[pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.liquidity_vault.to_account_info(),
+                    to: ctx.accounts.user_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        Ok(())
+    }
+
+    // Escrows shares at the back of the withdrawal queue, they keep earning until claimed.
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, _shares: u64) -> Result<()> {
+        if _shares == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+
+        // Transfer the shares to the escrow
+        {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_share_vault.to_account_info(),
+                    to: ctx.accounts.share_escrow.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, _shares)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let request = &mut ctx.accounts.withdrawal;
+        request.pool = pool.key();
+        request.owner = ctx.accounts.signer.key();
+        request.request_id = pool.queue_tail;
+        request.shares = _shares;
+        request.requested_at = clock::Clock::get().unwrap().unix_timestamp as u64;
+
+        pool.queue_tail = pool.queue_tail.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        pool.queued_shares = pool.queued_shares.checked_add(_shares).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // Hands the shares back, the empty request keeps its place until it reaches the head.
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>, _request_id: u64) -> Result<()> {
+        let shares = ctx.accounts.withdrawal.shares;
+        if shares == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+
+        let nonce = *(ctx.bumps.get("pool").unwrap());
+        // Transfer back the shares
+        {
+            let pool = &ctx.accounts.pool;
+            let seeds = &[pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.share_escrow.to_account_info(),
+                    to: ctx.accounts.user_share_vault.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, shares)?;
+        }
+
+        ctx.accounts.withdrawal.shares = 0;
+        let pool = &mut ctx.accounts.pool;
+        pool.queued_shares = pool.queued_shares.checked_sub(shares).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // Anyone can pay out the request at the head of the queue once the pool holds enough liquidity.
+    pub fn claim_withdrawal(ctx: Context<ClaimWithdrawal>, _request_id: u64) -> Result<()> {
+        if _request_id != ctx.accounts.pool.queue_head {
+            return Err(ErrorCode::WithdrawalNotNext.into());
+        }
+
+        let shares = ctx.accounts.withdrawal.shares;
+        if shares > 0 {
+            ctx.accounts.pool.accrue(ctx.accounts.liquidity_vault.amount, clock::Clock::get().unwrap().unix_timestamp as u64)?;
+            let amount = ctx.accounts.pool.amount_for_shares(shares, ctx.accounts.liquidity_vault.amount, ctx.accounts.share_mint.supply)?;
+            if amount > ctx.accounts.liquidity_vault.amount {
+                return Err(ErrorCode::InsufficientPoolLiquidity.into());
+            }
+
+            let nonce = *(ctx.bumps.get("pool").unwrap());
+            let pool = &ctx.accounts.pool;
+            let seeds = &[pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            // Burn the escrowed shares
+            {
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Burn {
+                        mint: ctx.accounts.share_mint.to_account_info(),
+                        from: ctx.accounts.share_escrow.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    signer
+                );
+                token::burn(cpi_ctx, shares)?;
+            }
+
+            // Transfer the withdrawal
+            {
+                let cpi_ctx = CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.liquidity_vault.to_account_info(),
+                        to: ctx.accounts.owner_stablecoin_vault.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    },
+                    signer
+                );
+                token::transfer(cpi_ctx, amount)?;
+            }
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.queued_shares = pool.queued_shares.checked_sub(shares).ok_or(ErrorCode::MathOverflow)?;
+        pool.queue_head = pool.queue_head.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // Anyone can have a pool fund an open order that fits its limits.
+    pub fn fund_order_from_pool(ctx: Context<FundOrderFromPool>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+        // Pool loans are repaid and liquidated from the nft vault PDA only
+        if !order.escrowed(ctx.program_id) {
+            return Err(ErrorCode::OrderNotQualified.into());
+        }
+        if metaplex::verified_collection(&ctx.accounts.nft_metadata, &order.nft_mint)? != Some(ctx.accounts.pool.collection) {
+            return Err(ErrorCode::OrderNotQualified.into());
+        }
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        ctx.accounts.pool.accrue(ctx.accounts.liquidity_vault.amount, now)?;
+        ctx.accounts.pool.check_order(order, ctx.accounts.liquidity_vault.amount, ctx.accounts.share_mint.supply)?;
+        // The pool rate over the whole period has to fit in the interest the borrower offered,
+        // which stays the most the borrower pays back
+        if ctx.accounts.pool.projected_interest(order, ctx.accounts.liquidity_vault.amount)? > order.interest {
+            return Err(ErrorCode::OrderNotQualified.into());
+        }
+
+        let nonce = *(ctx.bumps.get("pool").unwrap());
+        // Transfer the loan to the borrower
+        {
+            let pool = &ctx.accounts.pool;
+            let seeds = &[pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.liquidity_vault.to_account_info(),
+                    to: ctx.accounts.borrower_stablecoin_vault.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, order.request_amount)?;
+        }
+
+        // Save Info. The pool stays the lender, so pool loans mint no note.
+        order.lender = ctx.accounts.pool.key();
+        order.loan_start_time = now;
+        order.order_status = false;
+        order.borrow_index = ctx.accounts.pool.borrow_index;
+
+        ctx.accounts.pool.record_loan(order.request_amount)?;
+        start_borrow(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn payback_pool(ctx: Context<PaybackPool>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(order, clock.unix_timestamp as u64)?;
+
+        // Interest accrued at the pool rates since the loan was funded
+        ctx.accounts.pool.accrue(ctx.accounts.liquidity_vault.amount, clock.unix_timestamp as u64)?;
+        let debt = ctx.accounts.pool.debt_of(order)?;
+        // The borrower never owes more than the terms of the order. When the utilization rose
+        // after the funding the pool bears the rest, the whole debt still leaves its books.
+        let owed = debt.min(order.repayment_amount()?);
+        let interest = owed.checked_sub(order.request_amount).ok_or(ErrorCode::MathOverflow)?;
+
+        // Pay Loan
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.liquidity_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), owed)?;
+
+        // Transfer back nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), order.additional_collateral)?;
+
+        ctx.accounts.pool.record_repayment(debt)?;
+        let configuration = &mut ctx.accounts.configuration;
+        configuration.release_collateral(&mut ctx.accounts.currency, order.additional_collateral)?;
+        configuration.record_repayment(&mut ctx.accounts.currency, order.request_amount, interest)?;
+        settle_borrower_stats(&mut ctx.accounts.borrower_stats, order.order_id)?;
+
+        Ok(())
+    }
+
+    // Defaulted pool loans move the nft into the custody of the pool and the additional
+    // collateral into its liquidity. The rest of the debt is carried in the pool value
+    // until the manager writes it down or sells the nft.
+    pub fn liquidate_pool(ctx: Context<LiquidatePool>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        check_direct_liquidation(&ctx.accounts.configuration, order, ctx.remaining_accounts, clock.unix_timestamp as u64, ctx.program_id)?;
+
+        // Save Info
+        order.withdrew_at = clock.unix_timestamp as u64;
+
+        ctx.accounts.pool.accrue(ctx.accounts.liquidity_vault.amount, clock.unix_timestamp as u64)?;
+        let debt = ctx.accounts.pool.debt_of(order)?;
+
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        // Transfer nft collateral.
+        {
+            let seeds = &[ctx.accounts.nft_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.nft_vault.to_account_info(),
+                    to: ctx.accounts.pool_nft_vault.to_account_info(),
+                    authority: ctx.accounts.nft_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+        }
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        // Transfer additional collateral
+        {
+            let seeds = &[vault_prefix(&ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key), ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.liquidity_vault.to_account_info(),
+                    authority: ctx.accounts.stablecoin_vault.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, order.additional_collateral)?;
+        }
+
+        let cost = ctx.accounts.pool.record_default(debt, order.additional_collateral)?;
+        let defaulted = &mut ctx.accounts.defaulted;
+        defaulted.pool = ctx.accounts.pool.key();
+        defaulted.nft_mint = order.nft_mint;
+        defaulted.nft_vault = ctx.accounts.pool_nft_vault.key();
+        defaulted.keeper = ctx.accounts.signer.key();
+        defaulted.order_id = order.order_id;
+        defaulted.cost = cost;
+        defaulted.carrying_value = cost;
+        defaulted.defaulted_at = clock.unix_timestamp as u64;
+
+        let configuration = &mut ctx.accounts.configuration;
+        configuration.release_collateral(&mut ctx.accounts.currency, order.additional_collateral)?;
+        configuration.record_liquidation(&mut ctx.accounts.currency, order.request_amount)?;
+        settle_borrower_stats(&mut ctx.accounts.borrower_stats, order.order_id)?;
+
+        Ok(())
+    }
+
+    // Marks a defaulted nft down, the loss comes out of the value of every share.
+    pub fn write_down_defaulted_nft(ctx: Context<ManageDefaultedNft>, _carrying_value: u64) -> Result<()> {
+        let defaulted = &mut ctx.accounts.defaulted;
+        let amount = defaulted.carrying_value.checked_sub(_carrying_value).ok_or(ErrorCode::CarryingValueIncrease)?;
+
+        ctx.accounts.pool.write_down(amount)?;
+        defaulted.carrying_value = _carrying_value;
+        Ok(())
+    }
+
+    // Puts a defaulted nft up for sale, at a fixed price when both prices match or as a
+    // Dutch auction descending over the duration otherwise.
+    pub fn list_defaulted_nft(ctx: Context<ManageDefaultedNft>, _start_price: u64, _end_price: u64, _duration: u64) -> Result<()> {
+        if _start_price < _end_price {
+            return Err(ErrorCode::InvalidLoanTerms.into());
+        }
+
+        let now = clock::Clock::get().unwrap().unix_timestamp as u64;
+        let defaulted = &mut ctx.accounts.defaulted;
+        defaulted.start_price = _start_price;
+        defaulted.end_price = _end_price;
+        defaulted.start_time = now;
+        defaulted.end_time = now.checked_add(_duration).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn buy_defaulted_nft(ctx: Context<BuyDefaultedNft>) -> Result<()> {
+        let defaulted = &ctx.accounts.defaulted;
+        if defaulted.start_time == 0 {
+            return Err(ErrorCode::NotForSale.into());
+        }
+        let price = dutch_price(defaulted.start_price, defaulted.end_price, defaulted.start_time, defaulted.end_time, clock::Clock::get().unwrap().unix_timestamp as u64)?;
+
+        // Transfer the price to the pool
+        if price > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.buyer_stablecoin_vault.to_account_info(),
+                    to: ctx.accounts.liquidity_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, price)?;
+        }
+
+        let nonce = *(ctx.bumps.get("pool").unwrap());
+        // Transfer the nft and close its custody
+        {
+            let pool = &ctx.accounts.pool;
+            let seeds = &[pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.pool_nft_vault.to_account_info(),
+                    to: ctx.accounts.buyer_nft_vault.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: ctx.accounts.pool_nft_vault.to_account_info(),
+                    destination: ctx.accounts.keeper.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                signer
+            );
+            token::close_account(cpi_ctx)?;
+        }
+
+        let (carrying_value, cost) = (ctx.accounts.defaulted.carrying_value, ctx.accounts.defaulted.cost);
+        ctx.accounts.pool.record_sale(carrying_value, cost, price)?;
+
+        Ok(())
+    }
+
+    pub fn add_collateral(ctx: Context<AddCollateral>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        add_additional_collateral(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.order, _amount, clock.unix_timestamp as u64)?;
+
+        // Transfer additional collateral to vault
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _amount)?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        remove_additional_collateral(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.order, _amount, clock.unix_timestamp as u64)?;
+        if !ctx.accounts.order.order_status {
+            check_within_ltv(ctx.accounts.configuration.key(), &ctx.accounts.order, ctx.remaining_accounts, clock.unix_timestamp as u64)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), _amount)?;
+
+        Ok(())
+    }
+
+    pub fn add_native_currency(ctx: Context<AddNativeCurrency>, _min_additional_collateral: u64) -> Result<()> {
+        // Keep the vaults rent exempt so they can receive any amount of collateral and fees
+        for vault in [ctx.accounts.sol_vault.to_account_info(), ctx.accounts.fee_vault.to_account_info()] {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.authority.to_account_info(),
+                    to: vault,
+                },
+            );
+            system_program::transfer(cpi_ctx, ctx.accounts.rent.minimum_balance(0))?;
+        }
+
+        let currency = &mut ctx.accounts.currency;
+        currency.configuration = ctx.accounts.configuration.key();
+        currency.stablecoin_mint = constants::NATIVE_SOL_MINT;
+        currency.stablecoin_vault = ctx.accounts.sol_vault.key();
+        currency.fee_vault = ctx.accounts.fee_vault.key();
+        currency.enabled = true;
+        currency.min_additional_collateral = _min_additional_collateral;
+        Ok(())
+    }
+
+    pub fn create_order_sol(ctx: Context<CreateOrderSol>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        // Transfer collateral to vault.
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_nft_vault.to_account_info(), &ctx.accounts.nft_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), 1)?;
+
+        // Transfer additional collateral to vault
+        pay_sol(&ctx.accounts.system_program.to_account_info(), &ctx.accounts.signer.to_account_info(), &ctx.accounts.sol_vault.to_account_info(), _additional_collateral)?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = ctx.accounts.nft_mint.key();
+        order.nft_vault = ctx.accounts.nft_vault.key();
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn cancel_order_sol(ctx: Context<CancelOrderSol>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+
+        // Transfer back nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("sol_vault").unwrap());
+        withdraw_sol(&ctx.accounts.system_program.to_account_info(), ctx.accounts.configuration.to_account_info().key, &ctx.accounts.sol_vault.to_account_info(), nonce, &ctx.accounts.signer.to_account_info(), order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order)?;
+
+        Ok(())
+    }
+
+    pub fn give_loan_sol(ctx: Context<GiveLoanSol>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+
+        if order.borrower == ctx.accounts.signer.key() {
+            return Err(ErrorCode::CannotLendToSelf.into());
+        }
+
+        // Transfer loan to the borrower
+        pay_sol(&ctx.accounts.system_program.to_account_info(), &ctx.accounts.signer.to_account_info(), &ctx.accounts.borrower.to_account_info(), order.request_amount)?;
+
+        // Save Info
+        order.lender = ctx.accounts.signer.key();
+        order.loan_start_time = clock::Clock::get().unwrap().unix_timestamp as u64;
+        order.order_status = false;
+
+        ctx.accounts.lender_stats.open(ctx.accounts.signer.key(), ctx.accounts.configuration.key());
+        start_loan(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+        fit_user_stats(&ctx.accounts.lender_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn payback_sol(ctx: Context<PaybackSol>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_sol(&ctx.accounts.system_program.to_account_info(), &ctx.accounts.signer.to_account_info(), &ctx.accounts.lender.to_account_info(), order.repayment_amount()?)?;
+
+        // Transfer back nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("sol_vault").unwrap());
+        withdraw_sol(&ctx.accounts.system_program.to_account_info(), ctx.accounts.configuration.to_account_info().key, &ctx.accounts.sol_vault.to_account_info(), nonce, &ctx.accounts.signer.to_account_info(), order.additional_collateral)?;
+
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    pub fn liquidate_sol(ctx: Context<LiquidateSol>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_liquidated(&ctx.accounts.configuration, order, ctx.remaining_accounts, clock.unix_timestamp as u64, ctx.program_id)?;
+
+        // Transfer nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Keepers are paid for settling loans of offline lenders
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, sol_reserve(&ctx.accounts.fee_vault)?, ctx.accounts.signer.key(), order.lender, order)?;
+
+        let nonce = *(ctx.bumps.get("sol_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer additional collateral and the bounty
+        pay_liquidation_sol(&ctx.accounts.system_program.to_account_info(), ctx.accounts.configuration.to_account_info().key, (&ctx.accounts.sol_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.lender.to_account_info(), &ctx.accounts.signer.to_account_info(), &payout)?;
+
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    pub fn add_collateral_sol(ctx: Context<AddCollateralSol>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        add_additional_collateral(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.order, _amount, clock.unix_timestamp as u64)?;
+
+        // Transfer additional collateral to vault
+        pay_sol(&ctx.accounts.system_program.to_account_info(), &ctx.accounts.signer.to_account_info(), &ctx.accounts.sol_vault.to_account_info(), _amount)?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_collateral_sol(ctx: Context<WithdrawCollateralSol>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        remove_additional_collateral(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.order, _amount, clock.unix_timestamp as u64)?;
+        if !ctx.accounts.order.order_status {
+            check_within_ltv(ctx.accounts.configuration.key(), &ctx.accounts.order, ctx.remaining_accounts, clock.unix_timestamp as u64)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("sol_vault").unwrap());
+        withdraw_sol(&ctx.accounts.system_program.to_account_info(), ctx.accounts.configuration.to_account_info().key, &ctx.accounts.sol_vault.to_account_info(), nonce, &ctx.accounts.signer.to_account_info(), _amount)?;
+
+        Ok(())
+    }
+
+    // Token-2022 loan currencies are held in associated token accounts of the currency, the fee
+    // vault in one of the configuration.
+    pub fn add_token_2022_currency(ctx: Context<AddToken2022Currency>, _min_additional_collateral: u64) -> Result<()> {
+        // The vault holds the additional collateral of every order, nobody else may move it out
+        token_2022::mint(&ctx.accounts.stablecoin_mint)?.check_collateral()?;
+
+        for (vault, authority) in [
+            (ctx.accounts.stablecoin_vault.to_account_info(), ctx.accounts.currency.to_account_info()),
+            (ctx.accounts.fee_vault.to_account_info(), ctx.accounts.configuration.to_account_info()),
+        ] {
+            let accounts = token_2022::CreateAssociatedAccount {
+                payer: ctx.accounts.authority.to_account_info(),
+                associated_token: vault,
+                authority,
+                mint: ctx.accounts.stablecoin_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_2022_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            };
+            token_2022::create_associated_account(ctx.accounts.associated_token_program.to_account_info(), accounts)?;
+        }
+
+        let currency = &mut ctx.accounts.currency;
+        currency.configuration = ctx.accounts.configuration.key();
+        currency.stablecoin_mint = ctx.accounts.stablecoin_mint.key();
+        currency.stablecoin_vault = ctx.accounts.stablecoin_vault.key();
+        currency.fee_vault = ctx.accounts.fee_vault.key();
+        currency.enabled = true;
+        currency.min_additional_collateral = _min_additional_collateral;
+        Ok(())
+    }
+
+    // create_order for Token-2022 loan currencies. The borrower pays the transfer fee into the
+    // vault, so the order holds the whole additional collateral.
+    pub fn create_order_2022(ctx: Context<CreateOrder2022>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        // Transfer collateral to vault.
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_nft_vault.to_account_info(), &ctx.accounts.nft_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), 1)?;
+
+        // Transfer additional collateral to vault
+        pay_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _additional_collateral, clock.epoch)?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = ctx.accounts.nft_mint.key();
+        order.nft_vault = ctx.accounts.nft_vault.key();
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn cancel_order_2022(ctx: Context<CancelOrder2022>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+
+        // Transfer back nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("currency").unwrap());
+        withdraw_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.currency, nonce, &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order)?;
+
+        Ok(())
+    }
+
+    // The lender pays the transfer fee, so the borrower receives the whole request amount.
+    pub fn give_loan_2022(ctx: Context<GiveLoan2022>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+        check_unfunded(order)?;
+
+        if order.borrower == ctx.accounts.signer.key() {
+            return Err(ErrorCode::CannotLendToSelf.into());
+        }
+
+        let clock = clock::Clock::get().unwrap();
+        // Transfer loan to the borrower
+        pay_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.borrower_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), order.request_amount, clock.epoch)?;
+
+        // Save Info
+        order.lender = ctx.accounts.signer.key();
+        order.loan_start_time = clock.unix_timestamp as u64;
+        order.order_status = false;
+
+        ctx.accounts.lender_stats.open(ctx.accounts.signer.key(), ctx.accounts.configuration.key());
+        start_loan(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+        fit_user_stats(&ctx.accounts.lender_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    // The borrower pays the transfer fee, so the lender nets the principal plus interest.
+    pub fn payback_2022(ctx: Context<Payback2022>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), order.repayment_amount()?, clock.epoch)?;
+
+        // Transfer back nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("currency").unwrap());
+        withdraw_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.currency, nonce, &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), order.additional_collateral)?;
+
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    pub fn liquidate_2022(ctx: Context<Liquidate2022>, _order_id: u64) -> Result<()> {
+        let order = &mut ctx.accounts.order;
+
+        let clock = clock::Clock::get().unwrap();
+        mark_liquidated(&ctx.accounts.configuration, order, ctx.remaining_accounts, clock.unix_timestamp as u64, ctx.program_id)?;
+
+        // Transfer nft collateral.
+        let nonce = *(ctx.bumps.get("nft_vault").unwrap());
+        release_nft(&ctx.accounts.token_program.to_account_info(), ctx.accounts.nft_mint.to_account_info().key, &ctx.accounts.nft_vault.to_account_info(), nonce, &ctx.accounts.user_nft_vault.to_account_info())?;
+
+        // Keepers are paid for settling loans of offline lenders
+        let reserve = token_2022::account(&ctx.accounts.fee_vault)?.amount;
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, reserve, ctx.accounts.signer.key(), order.lender, order)?;
+
+        let nonce = *(ctx.bumps.get("currency").unwrap());
+        let configuration_nonce = *(ctx.bumps.get("configuration").unwrap());
+        // Transfer additional collateral and the bounty
+        {
+            let seeds = &[ctx.accounts.configuration.to_account_info().key.as_ref(), ctx.accounts.stablecoin_mint.to_account_info().key.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref(), &[nonce]];
+            let configuration_seeds = &[ctx.accounts.configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref(), &[configuration_nonce]];
+
+            pay_liquidation_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.stablecoin_mint.to_account_info(), (&ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.currency.to_account_info(), &[&seeds[..]]), (&ctx.accounts.fee_vault.to_account_info(), &ctx.accounts.configuration.to_account_info(), &[&configuration_seeds[..]]), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+        }
+
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, order)?;
+
+        Ok(())
+    }
+
+    pub fn add_collateral_2022(ctx: Context<AddCollateral2022>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        add_additional_collateral(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.order, _amount, clock.unix_timestamp as u64)?;
+
+        // Transfer additional collateral to vault
+        pay_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _amount, clock.epoch)?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_collateral_2022(ctx: Context<WithdrawCollateral2022>, _order_id: u64, _amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        remove_additional_collateral(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.order, _amount, clock.unix_timestamp as u64)?;
+        if !ctx.accounts.order.order_status {
+            check_within_ltv(ctx.accounts.configuration.key(), &ctx.accounts.order, ctx.remaining_accounts, clock.unix_timestamp as u64)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("currency").unwrap());
+        withdraw_2022(&ctx.accounts.token_2022_program.to_account_info(), &ctx.accounts.currency, nonce, &ctx.accounts.stablecoin_mint.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), _amount)?;
+
+        Ok(())
+    }
+
+    // create_order for programmable NFTs, escrowed in an associated token account of the order
+    pub fn create_order_pnft(ctx: Context<CreateOrderPnft>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        // Transfer collateral to vault.
+        {
+            let accounts = metaplex::Transfer {
+                token: ctx.accounts.user_nft_vault.to_account_info(),
+                token_owner: ctx.accounts.signer.to_account_info(),
+                destination: ctx.accounts.nft_vault.to_account_info(),
+                destination_owner: ctx.accounts.order.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                metadata: ctx.accounts.nft_metadata.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                owner_token_record: ctx.accounts.user_token_record.to_account_info(),
+                destination_token_record: ctx.accounts.vault_token_record.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
+                payer: ctx.accounts.signer.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: ctx.accounts.sysvar_instructions.to_account_info(),
+                spl_token_program: ctx.accounts.token_program.to_account_info(),
+                spl_ata_program: ctx.accounts.associated_token_program.to_account_info(),
+                authorization_rules_program: ctx.accounts.authorization_rules_program.to_account_info(),
+                authorization_rules: ctx.accounts.authorization_rules.to_account_info(),
+            };
+            metaplex::transfer(ctx.accounts.token_metadata_program.to_account_info(), accounts, 1, &[])?;
+        }
+
+        // Transfer additional collateral to vault
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _additional_collateral)?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = ctx.accounts.nft_mint.key();
+        order.nft_vault = ctx.accounts.nft_vault.key();
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn cancel_order_pnft(ctx: Context<CancelOrderPnft>, _order_id: u64) -> Result<()> {
+        check_unfunded(&ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer back nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let accounts = metaplex::Transfer {
+                token: ctx.accounts.nft_vault.to_account_info(),
+                token_owner: ctx.accounts.order.to_account_info(),
+                destination: ctx.accounts.user_nft_vault.to_account_info(),
+                destination_owner: ctx.accounts.signer.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                metadata: ctx.accounts.nft_metadata.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                owner_token_record: ctx.accounts.vault_token_record.to_account_info(),
+                destination_token_record: ctx.accounts.user_token_record.to_account_info(),
+                authority: ctx.accounts.order.to_account_info(),
+                payer: ctx.accounts.signer.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: ctx.accounts.sysvar_instructions.to_account_info(),
+                spl_token_program: ctx.accounts.token_program.to_account_info(),
+                spl_ata_program: ctx.accounts.associated_token_program.to_account_info(),
+                authorization_rules_program: ctx.accounts.authorization_rules_program.to_account_info(),
+                authorization_rules: ctx.accounts.authorization_rules.to_account_info(),
+            };
+            release_pnft(&ctx.accounts.token_metadata_program.to_account_info(), accounts, &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn payback_pnft(ctx: Context<PaybackPnft>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(&mut ctx.accounts.order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), ctx.accounts.order.repayment_amount()?)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer back nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let accounts = metaplex::Transfer {
+                token: ctx.accounts.nft_vault.to_account_info(),
+                token_owner: ctx.accounts.order.to_account_info(),
+                destination: ctx.accounts.user_nft_vault.to_account_info(),
+                destination_owner: ctx.accounts.signer.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                metadata: ctx.accounts.nft_metadata.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                owner_token_record: ctx.accounts.vault_token_record.to_account_info(),
+                destination_token_record: ctx.accounts.user_token_record.to_account_info(),
+                authority: ctx.accounts.order.to_account_info(),
+                payer: ctx.accounts.signer.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: ctx.accounts.sysvar_instructions.to_account_info(),
+                spl_token_program: ctx.accounts.token_program.to_account_info(),
+                spl_ata_program: ctx.accounts.associated_token_program.to_account_info(),
+                authorization_rules_program: ctx.accounts.authorization_rules_program.to_account_info(),
+                authorization_rules: ctx.accounts.authorization_rules.to_account_info(),
+            };
+            release_pnft(&ctx.accounts.token_metadata_program.to_account_info(), accounts, &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn liquidate_pnft(ctx: Context<LiquidatePnft>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        mark_liquidated(&ctx.accounts.configuration, &mut ctx.accounts.order, ctx.remaining_accounts, clock.unix_timestamp as u64, ctx.program_id)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let accounts = metaplex::Transfer {
+                token: ctx.accounts.nft_vault.to_account_info(),
+                token_owner: ctx.accounts.order.to_account_info(),
+                destination: ctx.accounts.user_nft_vault.to_account_info(),
+                destination_owner: ctx.accounts.payee.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                metadata: ctx.accounts.nft_metadata.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                owner_token_record: ctx.accounts.vault_token_record.to_account_info(),
+                destination_token_record: ctx.accounts.user_token_record.to_account_info(),
+                authority: ctx.accounts.order.to_account_info(),
+                payer: ctx.accounts.signer.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: ctx.accounts.sysvar_instructions.to_account_info(),
+                spl_token_program: ctx.accounts.token_program.to_account_info(),
+                spl_ata_program: ctx.accounts.associated_token_program.to_account_info(),
+                authorization_rules_program: ctx.accounts.authorization_rules_program.to_account_info(),
+                authorization_rules: ctx.accounts.authorization_rules.to_account_info(),
+            };
+            release_pnft(&ctx.accounts.token_metadata_program.to_account_info(), accounts, &ctx.accounts.lender.to_account_info(), signer)?;
+        }
+
+        // Keepers are paid for settling loans of offline lenders
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), ctx.accounts.payee.key(), &ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer additional collateral and the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+    // create_order leaving the nft in the borrower's account, delegated to the order and frozen
+    pub fn create_order_delegated(ctx: Context<CreateOrderDelegated>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = ctx.accounts.configuration.order_id.to_string();
+
+        // Lock collateral in place.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Approve {
+                    to: ctx.accounts.user_nft_vault.to_account_info(),
+                    delegate: ctx.accounts.order.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::approve(cpi_ctx, 1)?;
+
+            let accounts = metaplex::DelegatedAccount {
+                delegate: ctx.accounts.order.to_account_info(),
+                token_account: ctx.accounts.user_nft_vault.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            metaplex::freeze_delegated_account(ctx.accounts.token_metadata_program.to_account_info(), accounts, signer)?;
+        }
+
+        // Transfer additional collateral to vault
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _additional_collateral)?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = ctx.accounts.nft_mint.key();
+        order.nft_vault = ctx.accounts.user_nft_vault.key(); // the nft never leaves the borrower
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn cancel_order_delegated(ctx: Context<CancelOrderDelegated>, _order_id: u64) -> Result<()> {
+        check_unfunded(&ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Unlock nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let accounts = metaplex::DelegatedAccount {
+                delegate: ctx.accounts.order.to_account_info(),
+                token_account: ctx.accounts.user_nft_vault.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            unlock_delegated(&ctx.accounts.token_metadata_program.to_account_info(), accounts, &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn payback_delegated(ctx: Context<PaybackDelegated>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(&mut ctx.accounts.order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), ctx.accounts.order.repayment_amount()?)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Unlock nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let accounts = metaplex::DelegatedAccount {
+                delegate: ctx.accounts.order.to_account_info(),
+                token_account: ctx.accounts.user_nft_vault.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            unlock_delegated(&ctx.accounts.token_metadata_program.to_account_info(), accounts, &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn liquidate_delegated(ctx: Context<LiquidateDelegated>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        mark_liquidated(&ctx.accounts.configuration, &mut ctx.accounts.order, ctx.remaining_accounts, clock.unix_timestamp as u64, ctx.program_id)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            let accounts = metaplex::DelegatedAccount {
+                delegate: ctx.accounts.order.to_account_info(),
+                token_account: ctx.accounts.borrower_nft_vault.to_account_info(),
+                edition: ctx.accounts.nft_edition.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            metaplex::thaw_delegated_account(ctx.accounts.token_metadata_program.to_account_info(), accounts, signer)?;
+
+            // The order is still the delegate of the borrower's nft
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.borrower_nft_vault.to_account_info(),
+                    to: ctx.accounts.user_nft_vault.to_account_info(),
+                    authority: ctx.accounts.order.to_account_info(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, 1)?;
+        }
+
+        // Keepers are paid for settling loans of offline lenders
+        let payee = ctx.accounts.order.payee(&ctx.accounts.note_vault)?;
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), payee, &ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer additional collateral and the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+    // create_order with several nfts, remaining accounts are (nft_mint, user_nft_vault, nft_vault) per nft
+    pub fn create_order_bundle<'info>(ctx: Context<'_, '_, '_, 'info, CreateOrderBundle<'info>>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        let nft_count = ctx.remaining_accounts.len() / 3;
+        if ctx.remaining_accounts.len() % 3 != 0 || nft_count == 0 || nft_count > Bundle::MAX_NFTS {
+            return Err(ErrorCode::InvalidBundle.into());
+        }
+
+        // Transfer collateral to vaults owned by the order.
+        let mut nft_mints = Vec::with_capacity(nft_count);
+        for accounts in ctx.remaining_accounts.chunks(3) {
+            let (nft_mint, user_nft_vault, nft_vault) = (&accounts[0], &accounts[1], &accounts[2]);
+
+            let mint: Account<Mint> = Account::try_from(nft_mint)?;
+            if mint.supply != 1 || mint.decimals != 0 || nft_mints.contains(&mint.key()) {
+                return Err(ErrorCode::InvalidBundle.into());
+            }
+            let user_vault: Account<TokenAccount> = Account::try_from(user_nft_vault)?;
+            if user_vault.mint != mint.key() || user_vault.owner != ctx.accounts.signer.key() {
+                return Err(ErrorCode::BundleAccountMismatch.into());
+            }
+            if nft_vault.key() != associated_token::get_associated_token_address(&ctx.accounts.order.key(), &mint.key()) {
+                return Err(ErrorCode::BundleAccountMismatch.into());
+            }
+
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.signer.to_account_info(),
+                    associated_token: nft_vault.clone(),
+                    authority: ctx.accounts.order.to_account_info(),
+                    mint: nft_mint.clone(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+            );
+            associated_token::create(cpi_ctx)?;
+
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: user_nft_vault.clone(),
+                    to: nft_vault.clone(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            );
+            token::transfer(cpi_ctx, 1)?;
+
+            nft_mints.push(mint.key());
+        }
+
+        // Transfer additional collateral to vault
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _additional_collateral)?;
+
+        let bundle = &mut ctx.accounts.bundle;
+        bundle.order = ctx.accounts.order.key();
+        bundle.nft_mints = nft_mints;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = bundle.key(); // bundled orders point at their bundle
+        order.nft_vault = bundle.key();
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    // remaining accounts are (nft_vault, user_nft_vault) per nft, in bundle order
+    pub fn cancel_order_bundle<'info>(ctx: Context<'_, '_, '_, 'info, CancelOrderBundle<'info>>, _order_id: u64) -> Result<()> {
+        check_unfunded(&ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer back nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            release_bundle(&ctx.accounts.bundle.nft_mints, ctx.remaining_accounts, ctx.accounts.order.borrower, &ctx.accounts.order.to_account_info(), &ctx.accounts.token_program.to_account_info(), &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    // remaining accounts are (nft_vault, user_nft_vault) per nft, in bundle order
+    pub fn payback_bundle<'info>(ctx: Context<'_, '_, '_, 'info, PaybackBundle<'info>>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(&mut ctx.accounts.order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), ctx.accounts.order.repayment_amount()?)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer back nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            release_bundle(&ctx.accounts.bundle.nft_mints, ctx.remaining_accounts, ctx.accounts.order.borrower, &ctx.accounts.order.to_account_info(), &ctx.accounts.token_program.to_account_info(), &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    // remaining accounts are (nft_vault, user_nft_vault) per nft, in bundle order
+    pub fn liquidate_bundle<'info>(ctx: Context<'_, '_, '_, 'info, LiquidateBundle<'info>>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        // The remaining accounts carry the bundle, so bundles are only liquidated once due
+        mark_liquidated(&ctx.accounts.configuration, &mut ctx.accounts.order, &[], clock.unix_timestamp as u64, ctx.program_id)?;
+
+        let payee = ctx.accounts.order.payee(&ctx.accounts.note_vault)?;
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer nft collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            release_bundle(&ctx.accounts.bundle.nft_mints, ctx.remaining_accounts, payee, &ctx.accounts.order.to_account_info(), &ctx.accounts.token_program.to_account_info(), &ctx.accounts.lender.to_account_info(), signer)?;
+        }
+
+        // Keepers are paid for settling loans of offline lenders
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), payee, &ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer additional collateral and the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+    // create_order backed by an amount of an approved fungible token instead of an nft
+    pub fn create_order_token(ctx: Context<CreateOrderToken>, _request_amount: u64, _interest: u64, _period: u64, _additional_collateral: u64, _collateral_amount: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        validate_new_order(&ctx.accounts.configuration, &ctx.accounts.currency, clock.unix_timestamp as u64, _request_amount, _interest, _period, _additional_collateral)?;
+
+        if _collateral_amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+        if !ctx.accounts.collateral_token.enabled {
+            return Err(ErrorCode::CollateralNotEnabled.into());
+        }
+
+        // The borrower pays the transfer fees into and out of the vault, so whoever gets the
+        // collateral back receives the full amount
+        let collateral_mint = token_2022::mint(&ctx.accounts.collateral_mint)?;
+        let vault_amount = collateral_mint.gross_up(_collateral_amount, clock.epoch)?;
+        let deposit = collateral_mint.gross_up(vault_amount, clock.epoch)?;
+
+        // Transfer collateral to vault.
+        {
+            let accounts = token_2022::CreateAssociatedAccount {
+                payer: ctx.accounts.signer.to_account_info(),
+                associated_token: ctx.accounts.collateral_vault.to_account_info(),
+                authority: ctx.accounts.order.to_account_info(),
+                mint: ctx.accounts.collateral_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.collateral_token_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            };
+            token_2022::create_associated_account(ctx.accounts.associated_token_program.to_account_info(), accounts)?;
+
+            let accounts = token_2022::TransferChecked {
+                from: ctx.accounts.user_collateral_vault.to_account_info(),
+                mint: ctx.accounts.collateral_mint.to_account_info(),
+                to: ctx.accounts.collateral_vault.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
+            };
+            token_2022::transfer_checked(ctx.accounts.collateral_token_program.to_account_info(), accounts, deposit, collateral_mint.decimals, &[])?;
+        }
+        ctx.accounts.collateral_token.lock_collateral(_collateral_amount)?;
+
+        // Transfer additional collateral to vault
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), _additional_collateral)?;
+
+        // Save Info
+        let order = &mut ctx.accounts.order;
+        order.nft_mint = ctx.accounts.collateral_mint.key();
+        order.nft_vault = ctx.accounts.collateral_vault.key();
+        order.collateral_amount = _collateral_amount;
+
+        let terms = OrderTerms { request_amount: _request_amount, interest: _interest, period: _period, additional_collateral: _additional_collateral };
+        place_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, order, ctx.accounts.signer.key(), &terms, clock.unix_timestamp as u64)?;
+        fit_user_stats(&ctx.accounts.borrower_stats, &ctx.accounts.signer.to_account_info(), &ctx.accounts.system_program.to_account_info())?;
+
+        Ok(())
+    }
+
+    pub fn cancel_order_token(ctx: Context<CancelOrderToken>, _order_id: u64) -> Result<()> {
+        check_unfunded(&ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer back token collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            release_collateral_tokens(&ctx.accounts.collateral_token_program.to_account_info(), &ctx.accounts.collateral_vault.to_account_info(), &ctx.accounts.collateral_mint.to_account_info(), &ctx.accounts.user_collateral_vault.to_account_info(), &ctx.accounts.order.to_account_info(), &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+        ctx.accounts.collateral_token.release_collateral(ctx.accounts.order.collateral_amount)?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        close_open_order(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn payback_token(ctx: Context<PaybackToken>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        mark_repaid(&mut ctx.accounts.order, clock.unix_timestamp as u64)?;
+
+        // Pay Loan
+        pay_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.user_stablecoin_vault.to_account_info(), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.signer.to_account_info(), ctx.accounts.order.repayment_amount()?)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer back token collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            release_collateral_tokens(&ctx.accounts.collateral_token_program.to_account_info(), &ctx.accounts.collateral_vault.to_account_info(), &ctx.accounts.collateral_mint.to_account_info(), &ctx.accounts.user_collateral_vault.to_account_info(), &ctx.accounts.order.to_account_info(), &ctx.accounts.signer.to_account_info(), signer)?;
+        }
+        ctx.accounts.collateral_token.release_collateral(ctx.accounts.order.collateral_amount)?;
+
+        // Transfer back additional collateral
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        withdraw_tokens(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, &ctx.accounts.stablecoin_vault.to_account_info(), nonce, &ctx.accounts.user_stablecoin_vault.to_account_info(), ctx.accounts.order.additional_collateral)?;
+
+        settle_repayment(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+
+    pub fn liquidate_token(ctx: Context<LiquidateToken>, _order_id: u64) -> Result<()> {
+        let clock = clock::Clock::get().unwrap();
+        // Token collateral has no collection to price, so it is only liquidated once due
+        mark_liquidated(&ctx.accounts.configuration, &mut ctx.accounts.order, &[], clock.unix_timestamp as u64, ctx.program_id)?;
+
+        let nonce = *(ctx.bumps.get("order").unwrap());
+        let order_id = _order_id.to_string();
+        // Transfer token collateral.
+        {
+            let seeds = &[order_id.as_ref(), ctx.accounts.configuration.to_account_info().key.as_ref(), &[nonce]];
+            let signer = &[&seeds[..]];
+
+            release_collateral_tokens(&ctx.accounts.collateral_token_program.to_account_info(), &ctx.accounts.collateral_vault.to_account_info(), &ctx.accounts.collateral_mint.to_account_info(), &ctx.accounts.user_collateral_vault.to_account_info(), &ctx.accounts.order.to_account_info(), &ctx.accounts.lender.to_account_info(), signer)?;
+        }
+        ctx.accounts.collateral_token.release_collateral(ctx.accounts.order.collateral_amount)?;
+
+        // Keepers are paid for settling loans of offline lenders
+        let payee = ctx.accounts.order.payee(&ctx.accounts.note_vault)?;
+        let payout = liquidation_payout(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, ctx.accounts.fee_vault.amount, ctx.accounts.signer.key(), payee, &ctx.accounts.order)?;
+
+        let nonce = *(ctx.bumps.get("stablecoin_vault").unwrap());
+        let fee_nonce = *(ctx.bumps.get("fee_vault").unwrap());
+        // Transfer additional collateral and the bounty
+        pay_liquidation(&ctx.accounts.token_program.to_account_info(), &ctx.accounts.configuration, ctx.accounts.stablecoin_mint.to_account_info().key, (&ctx.accounts.stablecoin_vault.to_account_info(), nonce), (&ctx.accounts.fee_vault.to_account_info(), fee_nonce), &ctx.accounts.lender_stablecoin_vault.to_account_info(), &ctx.accounts.keeper_stablecoin_vault.to_account_info(), &payout)?;
+
+        settle_liquidation(&mut ctx.accounts.configuration, &mut ctx.accounts.currency, &mut ctx.accounts.borrower_stats, &mut ctx.accounts.lender_stats, &mut ctx.accounts.order)?;
+
+        Ok(())
+    }
+}
+
+// The vault of the configuration mint keeps the seeds it was deployed with, [mint, "st_vault"].
+// Currencies added later are seeded by the configuration as well.
+fn vault_prefix<'info>(configuration: &Account<'info, Configuration>, mint: &Pubkey) -> &'info [u8] {
+    if *mint == configuration.stablecoin_mint {
+        &[]
+    } else {
+        configuration.to_account_info().key.as_ref()
+    }
+}
+
+// Checks new order terms against the configuration and currency limits.
+fn validate_new_order(configuration: &Configuration, currency: &Currency, now: u64, request_amount: u64, interest: u64, period: u64, additional_collateral: u64) -> Result<()> {
+    if request_amount == 0 {
+        return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+    }
+
+    // Reject terms that could never be repaid or liquidated
+    request_amount.checked_add(interest).ok_or(ErrorCode::MathOverflow)?;
+    now.checked_add(period).ok_or(ErrorCode::MathOverflow)?;
+
+    if !currency.enabled {
+        return Err(ErrorCode::CurrencyNotEnabled.into());
+    }
+
+    configuration.validate_loan_terms(request_amount, interest, period)?;
+    if additional_collateral < configuration.min_collateral(currency) {
+        return Err(ErrorCode::AdditionalCollateralTooLow.into());
+    }
+    Ok(())
+}
+
+// Bookkeeping for an order whose collateral has been escrowed.
+fn open_order(configuration: &mut Configuration, currency: &mut Currency, borrower_stats: &mut UserStats, order: &mut Order) -> Result<()> {
+    configuration.lock_collateral(currency, order.additional_collateral)?;
+    configuration.order_id = configuration.order_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+    order.order_status = true;
+
+    // Track the order for the borrower
+    borrower_stats.open_orders = borrower_stats.open_orders.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    borrower_stats.track_order(order.order_id)
+}
+
+// Terms a borrower asks for in a new order.
+struct OrderTerms {
+    request_amount: u64,
+    interest: u64,
+    period: u64,
+    additional_collateral: u64,
+}
+
+// Fills in a new order once its collateral is escrowed and opens it. The variants record where
+// they hold the collateral in `nft_mint` and `nft_vault` first.
+fn place_order<'info>(configuration: &mut Account<'info, Configuration>, currency: &mut Currency, borrower_stats: &mut UserStats, order: &mut Account<'info, Order>, borrower: Pubkey, terms: &OrderTerms, now: u64) -> Result<()> {
+    order.order_id = configuration.order_id;
+    order.borrower = borrower;
+    order.stablecoin_mint = currency.stablecoin_mint;
+    order.stablecoin_vault = currency.stablecoin_vault;
+    order.request_amount = terms.request_amount;
+    order.interest = terms.interest;
+    order.period = terms.period;
+    order.additional_collateral = terms.additional_collateral;
+    order.lender = order.key(); // just a placeholder
+    order.created_at = now;
+    order.loan_start_time = 0; // placeholder
+    order.paid_back_at = 0;
+    order.withdrew_at = 0;
+
+    borrower_stats.open(borrower, configuration.key());
+    open_order(configuration, currency, borrower_stats, order)
+}
+
+// Bookkeeping for an unfilled order whose collateral has been returned.
+fn close_open_order(configuration: &mut Configuration, currency: &mut Currency, borrower_stats: &mut UserStats, order: &Order) -> Result<()> {
+    configuration.release_collateral(currency, order.additional_collateral)?;
+
+    if borrower_stats.untrack_order(order.order_id) {
+        borrower_stats.open_orders = borrower_stats.open_orders.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(())
+}
+
+// Bookkeeping for an order that has just been funded.
+fn start_loan(configuration: &mut Configuration, currency: &mut Currency, borrower_stats: &mut UserStats, lender_stats: &mut UserStats, order: &mut Order) -> Result<()> {
+    lender_stats.active_loans = lender_stats.active_loans.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    if configuration.totals(currency) {
+        lender_stats.total_lent = lender_stats.total_lent.checked_add(order.request_amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+    lender_stats.track_order(order.order_id)?;
+
+    start_borrow(configuration, currency, borrower_stats, order)
+}
+
+// Bookkeeping shared by peer-to-peer and pool loans.
+fn start_borrow(configuration: &mut Configuration, currency: &mut Currency, borrower_stats: &mut UserStats, order: &mut Order) -> Result<()> {
+    // The borrower cannot withdraw below what the lender funded against
+    order.funded_collateral = order.additional_collateral;
+
+    // Orders from before the index existed were never counted as open
+    if borrower_stats.order_ids.contains(&order.order_id) {
+        borrower_stats.open_orders = borrower_stats.open_orders.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+    } else {
+        borrower_stats.track_order(order.order_id)?;
+    }
+    borrower_stats.active_borrows = borrower_stats.active_borrows.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    if configuration.totals(currency) {
+        borrower_stats.total_borrowed = borrower_stats.total_borrowed.checked_add(order.request_amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    configuration.record_loan(currency, order.request_amount)
+}
+
+// Bookkeeping for additional collateral posted on an open order or active loan.
+fn add_additional_collateral(configuration: &mut Configuration, currency: &mut Currency, order: &mut Order, amount: u64, now: u64) -> Result<()> {
+    if amount == 0 {
+        return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+    }
+    if order.withdrew_at != 0 {
+        return Err(ErrorCode::AlreadyLiquidated.into());
+    }
+    if !order.order_status && order.due_at()? < now {
+        return Err(ErrorCode::RepaymentPeriodExceeded.into());
+    }
+
+    order.additional_collateral = order.additional_collateral.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    configuration.lock_collateral(currency, amount)
+}
+
+// Bookkeeping for additional collateral taken back by the borrower.
+fn remove_additional_collateral(configuration: &mut Configuration, currency: &mut Currency, order: &mut Order, amount: u64, now: u64) -> Result<()> {
+    if amount == 0 {
+        return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+    }
+    if order.withdrew_at != 0 {
+        return Err(ErrorCode::AlreadyLiquidated.into());
+    }
+
+    // Open orders only need the currency minimum, active loans keep what the lender funded against
+    let floor = if order.order_status {
+        configuration.min_collateral(currency)
+    } else {
+        if order.due_at()? < now {
+            return Err(ErrorCode::RepaymentPeriodExceeded.into());
+        }
+        order.funded_collateral.max(configuration.min_collateral(currency))
+    };
+    let remaining = order.additional_collateral.checked_sub(amount).ok_or(ErrorCode::AdditionalCollateralTooLow)?;
+    if remaining < floor {
+        return Err(ErrorCode::AdditionalCollateralTooLow.into());
+    }
+
+    order.additional_collateral = remaining;
+    configuration.release_collateral(currency, amount)
+}
+
+// Bookkeeping for a loan paid back by the borrower.
+fn settle_repayment(configuration: &mut Configuration, currency: &mut Currency, borrower_stats: &mut UserStats, lender_stats: &mut UserStats, order: &Order) -> Result<()> {
+    configuration.release_collateral(currency, order.additional_collateral)?;
+    configuration.record_repayment(currency, order.request_amount, order.interest)?;
+
+    settle_user_stats(borrower_stats, lender_stats, order.order_id)
+}
+
+// Price descending linearly from the start price to the end price.
+fn dutch_price(start_price: u64, end_price: u64, start_time: u64, end_time: u64, now: u64) -> Result<u64> {
+    if now >= end_time {
+        return Ok(end_price);
+    }
+    let elapsed = now.saturating_sub(start_time) as u128;
+    let duration = end_time.checked_sub(start_time).ok_or(ErrorCode::MathOverflow)? as u128;
+    let drop = (start_price.checked_sub(end_price).ok_or(ErrorCode::MathOverflow)? as u128)
+        .checked_mul(elapsed)
+        .and_then(|drop| drop.checked_div(duration))
+        .ok_or(ErrorCode::MathOverflow)?;
+    let price = (start_price as u128).checked_sub(drop).ok_or(ErrorCode::MathOverflow)?;
+    u64::try_from(price).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+// Pools keep no position index, only the borrower's is closed out.
+fn settle_borrower_stats(borrower_stats: &mut UserStats, order_id: u64) -> Result<()> {
+    if borrower_stats.untrack_order(order_id) {
+        borrower_stats.active_borrows = borrower_stats.active_borrows.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(())
+}
+
+// Moves an active borrow to the position index of a new borrower.
+fn move_borrow(borrower_stats: &mut UserStats, new_borrower_stats: &mut UserStats, order_id: u64) -> Result<()> {
+    settle_borrower_stats(borrower_stats, order_id)?;
+
+    new_borrower_stats.active_borrows = new_borrower_stats.active_borrows.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    new_borrower_stats.track_order(order_id)
+}
+
+// Closes the listing of a settled loan and gives its rent back to the seller when the seller is
+// the payee. A listing the seller can no longer deliver is left to `close_loan_listing`.
+fn close_listing<'info>(listing: &AccountInfo<'info>, payee: &AccountInfo<'info>, program_id: &Pubkey) -> Result<()> {
+    if listing.owner != program_id {
+        return Ok(());
+    }
+    let listing: Account<LoanListing> = Account::try_from(listing)?;
+    if listing.seller != payee.key() {
+        return Ok(());
+    }
+    anchor_lang::AccountsClose::close(&listing, payee.clone())
+}
+
+// Moves an active loan to the position index of the new note holder, who becomes its lender.
+// The cumulative amount lent stays with whoever funded the loan.
+fn move_loan(order: &mut Order, lender_stats: &mut UserStats, holder_stats: &mut UserStats, holder: Pubkey, configuration: Pubkey) -> Result<()> {
+    if order.lender == holder {
+        return Ok(());
+    }
+    holder_stats.open(holder, configuration);
+    if lender_stats.untrack_order(order.order_id) {
+        lender_stats.active_loans = lender_stats.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        holder_stats.active_loans = holder_stats.active_loans.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        holder_stats.track_order(order.order_id)?;
+    }
+
+    order.lender = holder;
+    Ok(())
+}
+
+// Bookkeeping for a loan whose collateral went to the lender.
+fn settle_liquidation(configuration: &mut Configuration, currency: &mut Currency, borrower_stats: &mut UserStats, lender_stats: &mut UserStats, order: &Order) -> Result<()> {
+    configuration.release_collateral(currency, order.additional_collateral)?;
+    configuration.record_liquidation(currency, order.request_amount)?;
+
+    settle_user_stats(borrower_stats, lender_stats, order.order_id)
+}
+
+// Starts the liquidation auction of a defaulted loan with the configured terms.
+fn open_auction<'info>(configuration: &Account<'info, Configuration>, order: &mut Account<'info, Order>, auction: &mut Auction, kind: u8, keeper: Pubkey, remaining_accounts: &[AccountInfo<'info>], now: u64) -> Result<()> {
+    if order.loan_start_time == 0 && order.order_status == true {
+        return Err(ErrorCode::LoanNotProvided.into());
+    }
+    if configuration.auction_duration == 0 {
+        return Err(ErrorCode::AuctionNotEnabled.into());
+    }
+    if configuration.auction_kind != kind {
+        return Err(ErrorCode::InvalidAuctionKind.into());
+    }
+
+    // Due loans, or earlier when a price feed shows the loan is under-collateralized
+    check_liquidation(configuration.key(), order, remaining_accounts, now)?;
+
+    if order.withdrew_at != 0 {
+        return Err(ErrorCode::AlreadyLiquidated.into());
+    }
+
+    // Save Info
+    order.withdrew_at = now;
+
+    let debt = order.request_amount.checked_add(order.accrued_interest(now)?).ok_or(ErrorCode::MathOverflow)?;
+    auction.order = order.key();
+    auction.kind = kind;
+    auction.keeper = keeper;
+    auction.debt = debt;
+    auction.start_price = bps_of(debt, configuration.auction_start_bps)?;
+    auction.end_price = bps_of(debt, configuration.auction_end_bps)?;
+    auction.start_time = now;
+    auction.end_time = now.checked_add(configuration.auction_duration).ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+// How the additional collateral of a liquidated loan is paid out. Every liquidation closes the
+// order and its vaults to the lender, whoever signs.
+struct LiquidationPayout {
+    // to the payee of the loan
+    lender_collateral: u64,
+    // to the keeper, out of the additional collateral
+    bounty_from_collateral: u64,
+    // to the keeper, out of the fee vault
+    bounty_from_reserve: u64,
+}
+
+// Keepers who are not the payee get the configured bounty, capped at what its source holds.
+// The reserve is paid out of the fee vault and never out of the collateral.
+fn liquidation_payout(configuration: &mut Configuration, currency: &mut Currency, reserve: u64, keeper: Pubkey, payee: Pubkey, order: &Order) -> Result<LiquidationPayout> {
+    let bounty = if keeper == payee { 0 } else { bps_of(order.request_amount, configuration.keeper_bounty_bps)? };
+    let (bounty_from_collateral, bounty_from_reserve) = if configuration.bounty_from_reserve {
+        (0, bounty.min(reserve))
+    } else {
+        (bounty.min(order.additional_collateral), 0)
+    };
+    configuration.record_bounty(currency, bounty_from_collateral, bounty_from_reserve)?;
+
+    Ok(LiquidationPayout {
+        lender_collateral: order.additional_collateral.checked_sub(bounty_from_collateral).ok_or(ErrorCode::MathOverflow)?,
+        bounty_from_collateral,
+        bounty_from_reserve,
+    })
+}
+
+// Bounty of the keeper starting an auction. What it takes out of the additional collateral no
+// longer goes to the auction, nothing is left for the payee until the auction settles.
+fn auction_bounty(configuration: &mut Configuration, currency: &mut Currency, reserve: u64, keeper: Pubkey, payee: Pubkey, order: &mut Order) -> Result<LiquidationPayout> {
+    let payout = liquidation_payout(configuration, currency, reserve, keeper, payee, order)?;
+    configuration.release_collateral(currency, payout.bounty_from_collateral)?;
+    order.additional_collateral = payout.lender_collateral;
+
+    Ok(LiquidationPayout { lender_collateral: 0, ..payout })
+}
+
+// Moves tokens out of an account of the signer.
+fn pay_tokens<'info>(token_program: &AccountInfo<'info>, from: &AccountInfo<'info>, to: &AccountInfo<'info>, authority: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    let cpi_ctx = CpiContext::new(
+        token_program.clone(),
+        token::Transfer {
+            from: from.clone(),
+            to: to.clone(),
+            authority: authority.clone(),
+        },
+    );
+    token::transfer(cpi_ctx, amount)
+}
+
+// Pays out of the collateral vault of a token currency, which signs for itself.
+fn withdraw_tokens<'info>(token_program: &AccountInfo<'info>, configuration: &Account<'info, Configuration>, stablecoin_mint: &Pubkey, stablecoin_vault: &AccountInfo<'info>, bump: u8, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    let seeds = &[vault_prefix(configuration, stablecoin_mint), stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[bump]];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.clone(),
+        token::Transfer {
+            from: stablecoin_vault.clone(),
+            to: to.clone(),
+            authority: stablecoin_vault.clone(),
+        },
+        signer
+    );
+    token::transfer(cpi_ctx, amount)
+}
+
+// Sends an nft out of its escrow vault, which signs for itself.
+fn release_nft<'info>(token_program: &AccountInfo<'info>, nft_mint: &Pubkey, nft_vault: &AccountInfo<'info>, bump: u8, to: &AccountInfo<'info>) -> Result<()> {
+    let seeds = &[nft_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[bump]];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.clone(),
+        token::Transfer {
+            from: nft_vault.clone(),
+            to: to.clone(),
+            authority: nft_vault.clone(),
+        },
+        signer
+    );
+    token::transfer(cpi_ctx, 1)
+}
+
+// Sends a programmable nft out of the order's escrow and closes the emptied vault, both signed
+// by the order.
+fn release_pnft<'info>(token_metadata_program: &AccountInfo<'info>, accounts: metaplex::Transfer<'info>, rent_destination: &AccountInfo<'info>, signer: &[&[&[u8]]]) -> Result<()> {
+    let token_program = accounts.spl_token_program.clone();
+    let nft_vault = accounts.token.clone();
+    let order = accounts.token_owner.clone();
+    metaplex::transfer(token_metadata_program.clone(), accounts, 1, signer)?;
+
+    // The emptied vault belongs to the order, which is closed afterwards
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program,
+        token::CloseAccount {
+            account: nft_vault,
+            destination: rent_destination.clone(),
+            authority: order,
+        },
+        signer
+    );
+    token::close_account(cpi_ctx)
+}
+
+// Thaws a delegated nft and revokes the order's delegation, leaving the nft free in its
+// owner's account.
+fn unlock_delegated<'info>(token_metadata_program: &AccountInfo<'info>, accounts: metaplex::DelegatedAccount<'info>, owner: &AccountInfo<'info>, signer: &[&[&[u8]]]) -> Result<()> {
+    let token_program = accounts.token_program.clone();
+    let nft_account = accounts.token_account.clone();
+    metaplex::thaw_delegated_account(token_metadata_program.clone(), accounts, signer)?;
+
+    let cpi_ctx = CpiContext::new(
+        token_program,
+        token::Revoke {
+            source: nft_account,
+            authority: owner.clone(),
+        },
+    );
+    token::revoke(cpi_ctx)
+}
+
+// Moves lamports out of the signer's account.
+fn pay_sol<'info>(system_program: &AccountInfo<'info>, from: &AccountInfo<'info>, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    let cpi_ctx = CpiContext::new(
+        system_program.clone(),
+        system_program::Transfer {
+            from: from.clone(),
+            to: to.clone(),
+        },
+    );
+    system_program::transfer(cpi_ctx, amount)
+}
+
+// Pays out of the SOL collateral vault, which signs for itself.
+fn withdraw_sol<'info>(system_program: &AccountInfo<'info>, configuration: &Pubkey, sol_vault: &AccountInfo<'info>, bump: u8, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    let seeds = &[configuration.as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[bump]];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        system_program.clone(),
+        system_program::Transfer {
+            from: sol_vault.clone(),
+            to: to.clone(),
+        },
+        signer
+    );
+    system_program::transfer(cpi_ctx, amount)
+}
+
+// Pays into a Token-2022 account, grossed up so that the recipient nets `amount` after the
+// transfer fee.
+fn pay_2022<'info>(token_program: &AccountInfo<'info>, stablecoin_mint: &AccountInfo<'info>, from: &AccountInfo<'info>, to: &AccountInfo<'info>, authority: &AccountInfo<'info>, amount: u64, epoch: u64) -> Result<()> {
+    let mint = token_2022::mint(stablecoin_mint)?;
+    let accounts = token_2022::TransferChecked {
+        from: from.clone(),
+        mint: stablecoin_mint.clone(),
+        to: to.clone(),
+        authority: authority.clone(),
+    };
+    token_2022::transfer_checked(token_program.clone(), accounts, mint.gross_up(amount, epoch)?, mint.decimals, &[])
+}
+
+// Pays out of the collateral vault of a Token-2022 currency, which the currency owns. The
+// recipient bears the transfer fee.
+fn withdraw_2022<'info>(token_program: &AccountInfo<'info>, currency: &Account<'info, Currency>, bump: u8, stablecoin_mint: &AccountInfo<'info>, stablecoin_vault: &AccountInfo<'info>, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    let seeds = &[currency.configuration.as_ref(), currency.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref(), &[bump]];
+    let signer = &[&seeds[..]];
+
+    let accounts = token_2022::TransferChecked {
+        from: stablecoin_vault.clone(),
+        mint: stablecoin_mint.clone(),
+        to: to.clone(),
+        authority: currency.to_account_info(),
+    };
+    token_2022::transfer_checked(token_program.clone(), accounts, amount, token_2022::mint(stablecoin_mint)?.decimals, signer)
+}
+
+// A vault of the program with the bump of its seeds.
+type VaultBump<'a, 'info> = (&'a AccountInfo<'info>, u8);
+
+// Pays out a liquidation lent in a token from the collateral vault and the fee vault.
+fn pay_liquidation<'info>(token_program: &AccountInfo<'info>, configuration: &Account<'info, Configuration>, stablecoin_mint: &Pubkey, stablecoin_vault: VaultBump<'_, 'info>, fee_vault: VaultBump<'_, 'info>, lender_vault: &AccountInfo<'info>, keeper_vault: &AccountInfo<'info>, payout: &LiquidationPayout) -> Result<()> {
+    let configuration_key = configuration.key();
+    let seeds = &[vault_prefix(configuration, stablecoin_mint), stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[stablecoin_vault.1]];
+    let fee_seeds = &[configuration_key.as_ref(), stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref(), &[fee_vault.1]];
+    let vault_seeds: &[&[&[u8]]] = &[&seeds[..]];
+    let fee_vault_seeds: &[&[&[u8]]] = &[&fee_seeds[..]];
+    let (stablecoin_vault, fee_vault) = (stablecoin_vault.0, fee_vault.0);
+
+    for (from, to, amount, signer) in [
+        (stablecoin_vault, lender_vault, payout.lender_collateral, vault_seeds),
+        (stablecoin_vault, keeper_vault, payout.bounty_from_collateral, vault_seeds),
+        (fee_vault, keeper_vault, payout.bounty_from_reserve, fee_vault_seeds),
+    ] {
+        if amount > 0 {
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program.clone(),
+                token::Transfer {
+                    from: from.clone(),
+                    to: to.clone(),
+                    authority: from.clone(),
+                },
+                signer
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+    }
+    Ok(())
+}
+
+// Pays out a liquidation lent in SOL from the collateral vault and the fee vault.
+fn pay_liquidation_sol<'info>(system_program: &AccountInfo<'info>, configuration: &Pubkey, sol_vault: VaultBump<'_, 'info>, fee_vault: VaultBump<'_, 'info>, lender: &AccountInfo<'info>, keeper: &AccountInfo<'info>, payout: &LiquidationPayout) -> Result<()> {
+    let seeds = &[configuration.as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref(), &[sol_vault.1]];
+    let fee_seeds = &[configuration.as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref(), &[fee_vault.1]];
+    let vault_seeds: &[&[&[u8]]] = &[&seeds[..]];
+    let fee_vault_seeds: &[&[&[u8]]] = &[&fee_seeds[..]];
+    let (sol_vault, fee_vault) = (sol_vault.0, fee_vault.0);
+
+    for (from, to, amount, signer) in [
+        (sol_vault, lender, payout.lender_collateral, vault_seeds),
+        (sol_vault, keeper, payout.bounty_from_collateral, vault_seeds),
+        (fee_vault, keeper, payout.bounty_from_reserve, fee_vault_seeds),
+    ] {
+        if amount > 0 {
+            let cpi_ctx = CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: from.clone(),
+                    to: to.clone(),
+                },
+                signer
+            );
+            system_program::transfer(cpi_ctx, amount)?;
+        }
+    }
+    Ok(())
+}
+
+// A Token-2022 vault with its owner and the seeds the owner signs with.
+type Vault2022<'a, 'info> = (&'a AccountInfo<'info>, &'a AccountInfo<'info>, &'a [&'a [&'a [u8]]]);
+
+// Pays out a liquidation lent in a Token-2022 currency from the collateral vault and the fee
+// vault. The recipients bear the transfer fee of what they are paid.
+fn pay_liquidation_2022<'info>(token_program: &AccountInfo<'info>, stablecoin_mint: &AccountInfo<'info>, stablecoin_vault: Vault2022<'_, 'info>, fee_vault: Vault2022<'_, 'info>, lender_vault: &AccountInfo<'info>, keeper_vault: &AccountInfo<'info>, payout: &LiquidationPayout) -> Result<()> {
+    let decimals = token_2022::mint(stablecoin_mint)?.decimals;
+    for ((from, authority, signer), to, amount) in [
+        (stablecoin_vault, lender_vault, payout.lender_collateral),
+        (stablecoin_vault, keeper_vault, payout.bounty_from_collateral),
+        (fee_vault, keeper_vault, payout.bounty_from_reserve),
+    ] {
+        if amount > 0 {
+            let accounts = token_2022::TransferChecked {
+                from: from.clone(),
+                mint: stablecoin_mint.clone(),
+                to: to.clone(),
+                authority: authority.clone(),
+            };
+            token_2022::transfer_checked(token_program.clone(), accounts, amount, decimals, signer)?;
+        }
+    }
+    Ok(())
+}
+
+// Lamports of the SOL fee vault that can be paid out without leaving it below the rent exemption.
+fn sol_reserve(fee_vault: &AccountInfo) -> Result<u64> {
+    let rent = Rent::get()?.minimum_balance(0);
+    Ok(fee_vault.lamports().saturating_sub(rent))
+}
+
+// An order of a batch with its accounts, passed as remaining accounts in this order:
+// order, lender stablecoin vault, nft mint, nft vault, user nft vault, borrower stats, lender stats,
+// loan note account of the payee, followed by the lender for liquidations.
+struct BatchOrder<'info> {
+    order: Account<'info, Order>,
+    // holder of the loan note, or the lender
+    payee: Pubkey,
+    lender_stablecoin_vault: AccountInfo<'info>,
+    nft_mint: Pubkey,
+    nft_vault: AccountInfo<'info>,
+    nft_vault_bump: u8,
+    user_nft_vault: AccountInfo<'info>,
+    borrower_stats: Account<'info, UserStats>,
+    lender_stats: Account<'info, UserStats>,
+}
+
+impl<'info> BatchOrder<'info> {
+    // order, lender stablecoin vault, nft mint, nft vault, user nft vault, borrower stats,
+    // lender stats and loan note account of each entry
+    pub const PAYBACK_STRIDE: usize = 8;
+    // the payback accounts followed by the lender receiving the rent
+    pub const LIQUIDATE_STRIDE: usize = 9;
+
+    // Only plain nft orders lent in a token can be settled in a batch. Pool, bundle, pNFT,
+    // delegated, token and SOL orders fail the whole batch, atomic or not.
+    fn check_entries(remaining_accounts: &[AccountInfo<'info>], stride: usize, program_id: &Pubkey) -> Result<()> {
+        let entries = remaining_accounts.chunks_exact(stride);
+        if remaining_accounts.is_empty() || !entries.remainder().is_empty() {
+            return Err(ErrorCode::BatchAccountMismatch.into());
+        }
+        for accounts in entries {
+            // Entries that are not orders are left to `load`
+            let order: Account<Order> = match Account::try_from(&accounts[0]) {
+                Ok(order) => order,
+                Err(_) => continue,
+            };
+            if order.borrow_index != 0 || order.stablecoin_mint == constants::NATIVE_SOL_MINT || !order.escrowed(program_id) {
+                return Err(ErrorCode::UnsupportedBatchOrder.into());
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the accounts of one order the way the single order instructions do.
+    fn load(accounts: &[AccountInfo<'info>], configuration: Pubkey, stablecoin_vault: Pubkey, program_id: &Pubkey) -> Result<Self> {
+        let order: Account<Order> = Account::try_from(&accounts[0])?;
+        let (order_address, _) = Pubkey::find_program_address(&[order.order_id.to_string().as_ref(), configuration.as_ref()], program_id);
+        if order.key() != order_address || order.stablecoin_vault != stablecoin_vault {
+            return Err(ErrorCode::BatchAccountMismatch.into());
+        }
+        if order.loan_start_time == 0 && order.order_status == true {
+            return Err(ErrorCode::LoanNotProvided.into());
+        }
+        if order.withdrew_at != 0 {
+            return Err(ErrorCode::AlreadyLiquidated.into());
+        }
+
+        let payee = order.payee(&accounts[7])?;
+        let lender_stablecoin_vault: Account<TokenAccount> = Account::try_from(&accounts[1])?;
+        if lender_stablecoin_vault.mint != order.stablecoin_mint || lender_stablecoin_vault.owner != payee {
+            return Err(ErrorCode::BatchAccountMismatch.into());
+        }
+
+        let nft_mint = accounts[2].key();
+        let (nft_vault_address, nft_vault_bump) = Pubkey::find_program_address(&[nft_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()], program_id);
+        if order.nft_mint != nft_mint || order.nft_vault != nft_vault_address || accounts[3].key() != nft_vault_address {
+            return Err(ErrorCode::BatchAccountMismatch.into());
+        }
+
+        let user_stats_address = |user: &Pubkey| Pubkey::find_program_address(&[user.as_ref(), configuration.as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()], program_id).0;
+        if accounts[5].key() != user_stats_address(&order.borrower) || accounts[6].key() != user_stats_address(&order.lender) {
+            return Err(ErrorCode::BatchAccountMismatch.into());
+        }
+
+        Ok(BatchOrder {
+            payee,
+            lender_stablecoin_vault: accounts[1].clone(),
+            nft_mint,
+            nft_vault: accounts[3].clone(),
+            nft_vault_bump,
+            user_nft_vault: accounts[4].clone(),
+            borrower_stats: Account::try_from(&accounts[5])?,
+            lender_stats: Account::try_from(&accounts[6])?,
+            order,
+        })
+    }
+
+    fn check_payback(&self, signer: &Pubkey, now: u64) -> std::result::Result<(), ErrorCode> {
+        if self.order.borrower != *signer {
+            return Err(ErrorCode::BatchAccountMismatch);
+        }
+        if self.order.due_at().map_err(|_| ErrorCode::MathOverflow)? < now {
+            return Err(ErrorCode::RepaymentPeriodExceeded);
+        }
+        let user_nft_vault: Account<TokenAccount> = Account::try_from(&self.user_nft_vault).map_err(|_| ErrorCode::BatchAccountMismatch)?;
+        if user_nft_vault.mint != self.nft_mint || user_nft_vault.owner != *signer {
+            return Err(ErrorCode::BatchAccountMismatch);
+        }
+        Ok(())
+    }
+
+    fn check_liquidation(&self, lender: &Pubkey, now: u64) -> std::result::Result<(), ErrorCode> {
+        if self.order.due_at().map_err(|_| ErrorCode::MathOverflow)? > now {
+            return Err(ErrorCode::RepaymentPeriodNotExceeded);
+        }
+        if *lender != self.order.lender {
+            return Err(ErrorCode::BatchAccountMismatch);
+        }
+        // The nft always goes to the associated token account of the payee
+        if self.user_nft_vault.key() != associated_token::get_associated_token_address(&self.payee, &self.nft_mint) {
+            return Err(ErrorCode::BatchAccountMismatch);
+        }
+        Ok(())
+    }
+
+    fn transfer_nft(&self, to: AccountInfo<'info>, token_program: AccountInfo<'info>) -> Result<()> {
+        let seeds = &[self.nft_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref(), &[self.nft_vault_bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program,
+            token::Transfer {
+                from: self.nft_vault.clone(),
+                to,
+                authority: self.nft_vault.clone(),
+            },
+            signer
+        );
+        token::transfer(cpi_ctx, 1)
+    }
+
+    // Writes back the stats and closes the order.
+    fn finish(&mut self, rent_receiver: AccountInfo<'info>, program_id: &Pubkey) -> Result<()> {
+        self.borrower_stats.exit(program_id)?;
+        self.lender_stats.exit(program_id)?;
+        anchor_lang::AccountsClose::close(&self.order, rent_receiver)
+    }
+}
+
+// Skips entries that fail validation unless the batch is atomic.
+fn skip_or_fail<T>(result: Result<T>, index: usize, atomic: bool) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if !atomic => {
+            msg!("Skipping order {} of the batch: {}", index, err);
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn bps_of(amount: u64, bps: u64) -> Result<u64> {
+    let value = (amount as u128) * (bps as u128) / (constants::BASIS_POINTS as u128);
+    u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+// Where the proceeds of a liquidation auction and the additional collateral go.
+pub struct ProceedsSplit {
+    pub lender_from_proceeds: u64,
+    pub borrower_from_proceeds: u64,
+    pub lender_from_collateral: u64,
+    pub borrower_from_collateral: u64,
+}
+
+// The lender is repaid the debt first, out of the proceeds and then the additional collateral.
+// Whatever is left of either goes to the borrower.
+fn split_proceeds(debt: u64, proceeds: u64, additional_collateral: u64) -> Result<ProceedsSplit> {
+    let lender_from_proceeds = proceeds.min(debt);
+    let shortfall = debt.checked_sub(lender_from_proceeds).ok_or(ErrorCode::MathOverflow)?;
+    let lender_from_collateral = additional_collateral.min(shortfall);
+    Ok(ProceedsSplit {
+        lender_from_proceeds,
+        borrower_from_proceeds: proceeds.checked_sub(lender_from_proceeds).ok_or(ErrorCode::MathOverflow)?,
+        lender_from_collateral,
+        borrower_from_collateral: additional_collateral.checked_sub(lender_from_collateral).ok_or(ErrorCode::MathOverflow)?,
+    })
+}
+
+// Lets a loan be liquidated once due, or earlier when a price feed shows it is under-collateralized.
+// The price feed and the nft metadata are passed as remaining accounts, followed by the
+// Pyth or Switchboard price account when the feed reads from one.
+fn check_liquidation<'info>(configuration: Pubkey, order: &Order, remaining_accounts: &[AccountInfo<'info>], now: u64) -> Result<()> {
+    if order.due_at()? <= now {
+        return Ok(());
+    }
+    if remaining_accounts.len() != 2 && remaining_accounts.len() != 3 {
+        return Err(ErrorCode::RepaymentPeriodNotExceeded.into());
+    }
+
+    let price_feed: Account<PriceFeed> = Account::try_from(&remaining_accounts[0])?;
+    if price_feed.configuration != configuration || price_feed.stablecoin_mint != order.stablecoin_mint {
+        return Err(ErrorCode::PriceFeedMismatch.into());
+    }
+    if metaplex::verified_collection(&remaining_accounts[1], &order.nft_mint)? != Some(price_feed.collection) {
+        return Err(ErrorCode::PriceFeedMismatch.into());
+    }
+
+    let debt = order.request_amount.checked_add(order.accrued_interest(now)?).ok_or(ErrorCode::MathOverflow)?;
+    let price = oracle::read_price(&price_feed, remaining_accounts.get(2))?;
+    if !price_feed.is_liquidatable(&price, debt, order.additional_collateral, now)? {
+        return Err(ErrorCode::LoanWithinLtv.into());
+    }
+    Ok(())
+}
+
+// Keeps an active loan within the liquidation LTV of its collection's price feed once collateral
+// is withdrawn. The nft metadata is passed as a remaining account, followed by the collection's
+// price feed and its Pyth or Switchboard price account when the nft has a verified collection.
+fn check_within_ltv<'info>(configuration: Pubkey, order: &Order, remaining_accounts: &[AccountInfo<'info>], now: u64) -> Result<()> {
+    let metadata = remaining_accounts.get(0).ok_or(ErrorCode::InvalidMetadata)?;
+    let (address, _) = Pubkey::find_program_address(&[b"metadata", metaplex::ID.as_ref(), order.nft_mint.as_ref()], &metaplex::ID);
+    if metadata.key() != address {
+        return Err(ErrorCode::InvalidMetadata.into());
+    }
+    // Only nfts of a verified collection can have a price feed
+    if *metadata.owner != metaplex::ID {
+        return Ok(());
+    }
+    let collection = match metaplex::verified_collection(metadata, &order.nft_mint)? {
+        Some(collection) => collection,
+        None => return Ok(()),
+    };
+
+    let price_feed = remaining_accounts.get(1).ok_or(ErrorCode::PriceFeedMismatch)?;
+    let (address, _) = Pubkey::find_program_address(&[configuration.as_ref(), collection.as_ref(), constants::NFT_COLLATERIZED_LOANS_PRICE_FEED_SEED.as_ref()], &crate::ID);
+    if price_feed.key() != address {
+        return Err(ErrorCode::PriceFeedMismatch.into());
+    }
+    // The collection has no feed, or one quoted in another currency
+    if *price_feed.owner != crate::ID {
+        return Ok(());
+    }
+    let price_feed: Account<PriceFeed> = Account::try_from(price_feed)?;
+    if price_feed.stablecoin_mint != order.stablecoin_mint {
+        return Ok(());
+    }
+
+    let debt = order.request_amount.checked_add(order.accrued_interest(now)?).ok_or(ErrorCode::MathOverflow)?;
+    let price = oracle::read_price(&price_feed, remaining_accounts.get(2))?;
+    if price_feed.is_liquidatable(&price, debt, order.additional_collateral, now)? {
+        return Err(ErrorCode::WithdrawalExceedsLtv.into());
+    }
+    Ok(())
+}
+
+// Checks shared by every direct liquidation: the loan is active, due or under-collateralized,
+// and not liquidated yet. While auctions are enabled the collateral is only sold through them.
+fn check_direct_liquidation<'a, 'info>(configuration: &Account<'a, Configuration>, order: &Order, price_accounts: &[AccountInfo<'info>], now: u64, program_id: &Pubkey) -> Result<()> {
+    if order.loan_start_time == 0 && order.order_status == true {
+        return Err(ErrorCode::LoanNotProvided.into());
+    }
+    // Only orders that can be auctioned have to be
+    if configuration.auction_duration > 0 && order.auctionable(configuration, program_id) {
+        return Err(ErrorCode::AuctionRequired.into());
+    }
+
+    // Due loans, or earlier when a price feed shows the loan is under-collateralized
+    check_liquidation(configuration.key(), order, price_accounts, now)?;
+
+    if order.withdrew_at != 0 {
+        return Err(ErrorCode::AlreadyLiquidated.into());
+    }
+    Ok(())
+}
+
+// Orders can be cancelled or funded until a lender has funded them.
+fn check_unfunded(order: &Order) -> Result<()> {
+    if order.loan_start_time != 0 && order.order_status == false {
+        return Err(ErrorCode::LoanAlreadyStarted.into());
+    }
+    Ok(())
+}
+
+// Checks an active loan can still be paid back and marks it paid. An auction may have started
+// before the loan was due.
+fn mark_repaid(order: &mut Order, now: u64) -> Result<()> {
+    if order.loan_start_time == 0 && order.order_status == true {
+        return Err(ErrorCode::LoanNotProvided.into());
+    }
+    if order.withdrew_at != 0 {
+        return Err(ErrorCode::AlreadyLiquidated.into());
+    }
+    if order.due_at()? < now {
+        return Err(ErrorCode::RepaymentPeriodExceeded.into());
+    }
+
+    order.paid_back_at = now;
+    Ok(())
+}
+
+// Checks a loan can be liquidated without an auction and marks its collateral withdrawn.
+fn mark_liquidated<'a, 'info>(configuration: &Account<'a, Configuration>, order: &mut Order, price_accounts: &[AccountInfo<'info>], now: u64, program_id: &Pubkey) -> Result<()> {
+    check_direct_liquidation(configuration, order, price_accounts, now, program_id)?;
+
+    order.withdrew_at = now;
+    Ok(())
+}
+
+// Sends everything left in an order's collateral vault and closes it. The borrower paid the
+// transfer fee out of the vault up front, so the recipient gets at least the collateral amount.
+fn release_collateral_tokens<'info>(token_program: &AccountInfo<'info>, collateral_vault: &AccountInfo<'info>, collateral_mint: &AccountInfo<'info>, recipient_vault: &AccountInfo<'info>, order: &AccountInfo<'info>, rent_receiver: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+    let decimals = token_2022::mint(collateral_mint)?.decimals;
+    let amount = token_2022::account(collateral_vault)?.amount;
+
+    let accounts = token_2022::TransferChecked {
+        from: collateral_vault.clone(),
+        mint: collateral_mint.clone(),
+        to: recipient_vault.clone(),
+        authority: order.clone(),
+    };
+    token_2022::transfer_checked(token_program.clone(), accounts, amount, decimals, signer_seeds)?;
+
+    // The emptied vault belongs to the order, which is closed by the caller
+    let accounts = token_2022::CloseAccount {
+        account: collateral_vault.clone(),
+        mint: collateral_mint.clone(),
+        destination: rent_receiver.clone(),
+        authority: order.clone(),
+    };
+    token_2022::close_account(token_program.clone(), accounts, signer_seeds)
+}
+
+// Sends every nft of a bundle to the recipient's accounts and closes the order's vaults.
+fn release_bundle<'info>(nft_mints: &[Pubkey], remaining_accounts: &[AccountInfo<'info>], recipient: Pubkey, order: &AccountInfo<'info>, token_program: &AccountInfo<'info>, rent_receiver: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+    if remaining_accounts.len() != nft_mints.len() * 2 {
+        return Err(ErrorCode::BundleAccountMismatch.into());
+    }
+
+    for (nft_mint, accounts) in nft_mints.iter().zip(remaining_accounts.chunks(2)) {
+        let (nft_vault, user_nft_vault) = (&accounts[0], &accounts[1]);
+
+        if nft_vault.key() != associated_token::get_associated_token_address(&order.key(), nft_mint) {
+            return Err(ErrorCode::BundleAccountMismatch.into());
+        }
+        let user_vault: Account<TokenAccount> = Account::try_from(user_nft_vault)?;
+        if user_vault.mint != *nft_mint || user_vault.owner != recipient {
+            return Err(ErrorCode::BundleAccountMismatch.into());
+        }
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.clone(),
+            token::Transfer {
+                from: nft_vault.clone(),
+                to: user_nft_vault.clone(),
+                authority: order.clone(),
+            },
+            signer_seeds
+        );
+        token::transfer(cpi_ctx, 1)?;
+
+        let cpi_ctx = CpiContext::new_with_signer(
+            token_program.clone(),
+            token::CloseAccount {
+                account: nft_vault.clone(),
+                destination: rent_receiver.clone(),
+                authority: order.clone(),
+            },
+            signer_seeds
+        );
+        token::close_account(cpi_ctx)?;
+    }
+    Ok(())
+}
+
+// Grows an account of a deployed layout to len, the signer tops up the rent.
+fn grow_account<'info>(account: &AccountInfo<'info>, payer: &AccountInfo<'info>, system_program: &AccountInfo<'info>, len: usize) -> Result<()> {
+    if account.data_len() >= len {
+        return Err(ErrorCode::AlreadyMigrated.into());
+    }
+
+    let lamports = Rent::get()?.minimum_balance(len).saturating_sub(account.lamports());
+    if lamports > 0 {
+        let cpi_ctx = CpiContext::new(
+            system_program.clone(),
+            system_program::Transfer {
+                from: payer.clone(),
+                to: account.clone(),
+            },
+        );
+        system_program::transfer(cpi_ctx, lamports)?;
+    }
+    account.realloc(len, true)?;
+    Ok(())
+}
+
+// Grows a position index by a page once its ids no longer fit, the payer tops up the rent.
+fn fit_user_stats<'info>(user_stats: &Account<'info, UserStats>, payer: &AccountInfo<'info>, system_program: &AccountInfo<'info>) -> Result<()> {
+    let len = 8 + user_stats.space();
+    let account = user_stats.to_account_info();
+    if account.data_len() >= len {
+        return Ok(());
+    }
+    grow_account(&account, payer, system_program, len)
+}
+
+// Closes out an active loan in both parties' position indexes.
+fn settle_user_stats(borrower_stats: &mut UserStats, lender_stats: &mut UserStats, order_id: u64) -> Result<()> {
+    settle_borrower_stats(borrower_stats, order_id)?;
+
+    if lender_stats.untrack_order(order_id) {
+        lender_stats.active_loans = lender_stats.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + Configuration::LEN,
+        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = stablecoin_vault,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = fee_vault,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + Currency::LEN,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLoanTerms<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+}
+
+#[derive(Accounts)]
+pub struct AddCurrency<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = stablecoin_vault,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = fee_vault,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Currency::LEN,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCurrency<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        has_one = configuration,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+}
+
+#[derive(Accounts)]
+pub struct AddCollateralToken<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    /// CHECK: a mint of either token program, read in the handler
+    pub collateral_mint: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + CollateralToken::LEN,
+        seeds = [configuration.key().as_ref(), collateral_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_COLLATERAL_SEED.as_ref()],
+        bump,
+    )]
+    pub collateral_token: Box<Account<'info, CollateralToken>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCollateralToken<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        has_one = configuration,
+    )]
+    pub collateral_token: Box<Account<'info, CollateralToken>>,
+}
+
+#[derive(Accounts)]
+#[instruction(_collection: Pubkey)]
+pub struct AddPriceFeed<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PriceFeed::LEN,
+        seeds = [configuration.key().as_ref(), _collection.as_ref(), constants::NFT_COLLATERIZED_LOANS_PRICE_FEED_SEED.as_ref()],
+        bump,
+    )]
+    pub price_feed: Box<Account<'info, PriceFeed>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePriceFeed<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        has_one = configuration,
+    )]
+    pub price_feed: Box<Account<'info, PriceFeed>>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitPrice<'info> {
+    pub oracle: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = oracle,
+    )]
+    pub price_feed: Box<Account<'info, PriceFeed>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeUserStats<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateConfiguration<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    // The program data account holds the upgrade authority
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(signer.key()),
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: configuration in the layout it was created with, grown in place
+    #[account(
+        mut,
+        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+        owner = crate::ID,
+    )]
+    pub configuration: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + Currency::LEN,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = fee_vault,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct MigrateOrder<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: order in the layout it was created with, read once it is grown
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        owner = crate::ID,
+    )]
+    pub order: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrder<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer=signer,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump,
+        token::mint=nft_mint,
+        token::authority = nft_vault,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct CancelOrder<'info> {
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut
+    )]
+    pub signer: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct GiveLoan<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == signer.key(),
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = borrower_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = borrower_stablecoin_vault.owner == order.borrower,
+    )]
+    pub borrower_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // 1-of-1 token whose holder is repaid
+    #[account(
+        init,
+        payer = signer,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NOTE_SEED.as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = order,
+    )]
+    pub note_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer = signer,
+        associated_token::mint = note_mint,
+        associated_token::authority = signer,
+    )]
+    pub lender_note_vault: Box<Account<'info, TokenAccount>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct Payback<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.borrower == signer.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+    /// CHECK: the payee of the loan, who gets the rent of its listing back
+    #[account(mut, address = order.payee(&note_vault)?)]
+    pub payee: UncheckedAccount<'info>,
+    /// CHECK: listing of the loan, closed when the payee listed it
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref()],
+        bump,
+    )]
+    pub listing: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct TransferBorrower<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.borrower == signer.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub nft_mint: Box<Account<'info, Mint>>,
+    // Only orders escrowed in the program nft vault move with the position
+    #[account(
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    // signs so nobody can fill the position index of another wallet. The lender cannot
+    // become its own borrower.
+    #[account(
+        constraint = new_borrower.key() != signer.key(),
+        constraint = new_borrower.key() != order.lender,
+    )]
+    pub new_borrower: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [new_borrower.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub new_borrower_stats: Box<Account<'info, UserStats>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct SellAndRepay<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub buyer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.borrower == signer.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = borrower_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = borrower_stablecoin_vault.owner == signer.key(),
+    )]
+    pub borrower_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = buyer_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = buyer_stablecoin_vault.owner == buyer.key(),
+    )]
+    pub buyer_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = buyer_nft_vault.mint == nft_mint.key(),
+        constraint = buyer_nft_vault.owner == buyer.key(),
+    )]
+    pub buyer_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct ListLoan<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        constraint = note_vault.owner == signer.key(),
+    )]
+    pub note_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + LoanListing::LEN,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref()],
+        bump,
+    )]
+    pub listing: Box<Account<'info, LoanListing>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLoanListing<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [listing.order.as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref()],
+        bump,
+        constraint = listing.seller == signer.key(),
+        has_one = note_vault,
+        close = signer,
+    )]
+    pub listing: Box<Account<'info, LoanListing>>,
+
+    #[account(mut)]
+    pub note_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLoanListing<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [listing.order.as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref()],
+        bump,
+        has_one = order,
+        has_one = seller,
+        has_one = note_vault,
+        close = seller,
+    )]
+    pub listing: Box<Account<'info, LoanListing>>,
+
+    /// CHECK: order of the listing, only checked to be closed
+    pub order: UncheckedAccount<'info>,
+
+    /// CHECK: note account of the listing, checked to still hold the note of the seller
+    pub note_vault: UncheckedAccount<'info>,
+
+    /// CHECK: receives the rent of the listing
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct BuyLoan<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // A paid back or liquidated order is closed and fails here
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref()],
+        bump,
+        has_one = seller,
+        has_one = note_vault,
+        close = seller,
+    )]
+    pub listing: Box<Account<'info, LoanListing>>,
+
+    /// CHECK: receives the rent of the listing
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = note_vault.mint == order.note_mint,
+    )]
+    pub note_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = buyer_note_vault.mint == order.note_mint,
+        constraint = buyer_note_vault.owner == signer.key(),
+    )]
+    pub buyer_note_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = seller_stablecoin_vault.mint == order.stablecoin_mint,
+        constraint = seller_stablecoin_vault.owner == listing.seller,
+    )]
+    pub seller_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = buyer_stablecoin_vault.mint == order.stablecoin_mint,
+        constraint = buyer_stablecoin_vault.owner == signer.key(),
+    )]
+    pub buyer_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct ClaimLoanPosition<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: loan note account of the signer, checked to hold the note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub holder_stats: Box<Account<'info, UserStats>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLoanNote<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// CHECK: order of the note, only checked to be closed
+    pub order: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NOTE_SEED.as_ref()],
+        bump,
+    )]
+    pub note_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = note_vault.mint == note_mint.key(),
+        constraint = note_vault.owner == signer.key(),
+    )]
+    pub note_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct Liquidate<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    // receives the bounty, any account of the currency when the lender liquidates
+    #[account(
+        mut,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
+    )]
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: the payee of the loan, who receives the nft and the rent of its listing
+    #[account(mut, address = order.payee(&note_vault)?)]
+    pub payee: UncheckedAccount<'info>,
+    /// CHECK: listing of the loan, closed when the payee listed it
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_LISTING_SEED.as_ref()],
+        bump,
+    )]
+    pub listing: UncheckedAccount<'info>,
+    // associated token account of the payee, created by the signer if missing
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = nft_mint,
+        associated_token::authority = payee,
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct StartDutchAuction<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_AUCTION_SEED.as_ref()],
+        space = 8 + Auction::LEN,
+        bump,
+    )]
+    pub auction: Box<Account<'info, Auction>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    // receives the bounty, any account of the currency when the payee starts the auction
+    #[account(
+        mut,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
+    )]
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct BuyDutchAuction<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_AUCTION_SEED.as_ref()],
+        bump,
+        has_one = order,
+        has_one = keeper,
+        close = keeper,
+    )]
+    pub auction: Box<Account<'info, Auction>>,
+
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
+    /// CHECK: receives the rent of the auction
+    #[account(mut)]
+    pub keeper: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = buyer_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = buyer_stablecoin_vault.owner == signer.key(),
+    )]
+    pub buyer_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = borrower_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = borrower_stablecoin_vault.owner == order.borrower,
+    )]
+    pub borrower_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct ClaimDutchAuction<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_AUCTION_SEED.as_ref()],
+        bump,
+        has_one = order,
+        has_one = keeper,
+        close = keeper,
+    )]
+    pub auction: Box<Account<'info, Auction>>,
+
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
+    /// CHECK: receives the rent of the auction
+    #[account(mut)]
+    pub keeper: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    // associated token account of the payee
+    #[account(
+        mut,
+        constraint = user_nft_vault.key() == associated_token::get_associated_token_address(&order.payee(&note_vault)?, &nft_mint.key()),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct StartEnglishAuction<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_AUCTION_SEED.as_ref()],
+        space = 8 + Auction::LEN,
+        bump,
+    )]
+    pub auction: Box<Account<'info, Auction>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    // receives the bounty, any account of the currency when the payee starts the auction
+    #[account(
+        mut,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
+    )]
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [auction.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BID_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = bid_vault,
+    )]
+    pub bid_vault: Box<Account<'info, TokenAccount>>,
+
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct Bid<'info> {
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_AUCTION_SEED.as_ref()],
+        bump,
+        has_one = order,
+        has_one = bid_vault,
+    )]
+    pub auction: Box<Account<'info, Auction>>,
+
+    #[account(
+        mut,
+        seeds = [auction.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BID_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub bid_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == bid_vault.mint,
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // refunded when outbid, any account of the currency before the first bid
+    #[account(
+        mut,
+        constraint = previous_bidder_stablecoin_vault.mint == bid_vault.mint,
+        constraint = auction.highest_bid == 0 || previous_bidder_stablecoin_vault.owner == auction.bidder,
+    )]
+    pub previous_bidder_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct SettleEnglishAuction<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_AUCTION_SEED.as_ref()],
+        bump,
+        has_one = order,
+        has_one = bid_vault,
+        has_one = keeper,
+        close = keeper,
+    )]
+    pub auction: Box<Account<'info, Auction>>,
+
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
+    /// CHECK: receives the rent of the auction and the bid vault
+    #[account(mut)]
+    pub keeper: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        seeds = [auction.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BID_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub bid_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = borrower_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = borrower_stablecoin_vault.owner == order.borrower,
+    )]
+    pub borrower_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    // any account of the highest bidder, the associated token account of the payee without bids
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == auction.winner(order.payee(&note_vault)?),
+        constraint = auction.highest_bid > 0 || user_nft_vault.key() == associated_token::get_associated_token_address(&order.payee(&note_vault)?, &nft_mint.key()),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct PaybackBatch<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct LiquidateBatch<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    // receives the bounties, any account of the currency when the lender liquidates
+    #[account(
+        mut,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
+    )]
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_collection: Pubkey)]
+pub struct CreatePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + LendingPool::LEN,
+        seeds = [configuration.key().as_ref(), _collection.as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    #[account(
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer = authority,
+        seeds = [pool.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = stablecoin_mint,
+        token::authority = pool,
+    )]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [pool.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_SHARE_MINT_SEED.as_ref()],
+        bump,
+        mint::decimals = stablecoin_mint.decimals,
+        mint::authority = pool,
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [pool.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_SHARE_ESCROW_SEED.as_ref()],
+        bump,
+        token::mint = share_mint,
+        token::authority = pool,
+    )]
+    pub share_escrow: Box<Account<'info, TokenAccount>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolLimits<'info> {
+    pub manager: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = manager,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolRates<'info> {
+    pub manager: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = manager,
+        has_one = liquidity_vault,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositPool<'info> {
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), pool.collection.as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = configuration,
+        has_one = liquidity_vault,
+        has_one = share_mint,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub share_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_share_vault.mint == share_mint.key(),
+    )]
+    pub user_share_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct WithdrawPool<'info> {
+    pub signer: Signer<'info>,
+
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), pool.collection.as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = configuration,
+        has_one = liquidity_vault,
+        has_one = share_mint,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub share_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_share_vault.mint == share_mint.key(),
+        constraint = user_share_vault.owner == signer.key(),
+    )]
+    pub user_share_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = share_escrow,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + WithdrawalRequest::LEN,
+        seeds = [pool.key().as_ref(), pool.queue_tail.to_string().as_ref(), constants::NFT_COLLATERIZED_LOANS_WITHDRAWAL_SEED.as_ref()],
+        bump,
+    )]
+    pub withdrawal: Box<Account<'info, WithdrawalRequest>>,
+
+    #[account(mut)]
+    pub share_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_share_vault.mint == pool.share_mint,
+        constraint = user_share_vault.owner == signer.key(),
+    )]
+    pub user_share_vault: Box<Account<'info, TokenAccount>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_request_id: u64)]
+pub struct CancelWithdrawal<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = share_escrow,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), _request_id.to_string().as_ref(), constants::NFT_COLLATERIZED_LOANS_WITHDRAWAL_SEED.as_ref()],
+        bump,
+        constraint = withdrawal.owner == signer.key(),
+    )]
+    pub withdrawal: Box<Account<'info, WithdrawalRequest>>,
+
+    #[account(mut)]
+    pub share_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_share_vault.mint == pool.share_mint,
+    )]
+    pub user_share_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_request_id: u64)]
+pub struct ClaimWithdrawal<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = liquidity_vault,
+        has_one = share_mint,
+        has_one = share_escrow,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), _request_id.to_string().as_ref(), constants::NFT_COLLATERIZED_LOANS_WITHDRAWAL_SEED.as_ref()],
+        bump,
+        close = owner,
+    )]
+    pub withdrawal: Box<Account<'info, WithdrawalRequest>>,
+
+    /// CHECK: receives the rent of the request
+    #[account(
+        mut,
+        constraint = owner.key() == withdrawal.owner,
+    )]
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub share_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub share_escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = owner_stablecoin_vault.mint == pool.stablecoin_mint,
+        constraint = owner_stablecoin_vault.owner == withdrawal.owner,
+    )]
+    pub owner_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct FundOrderFromPool<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), pool.collection.as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = configuration,
+        has_one = liquidity_vault,
+        has_one = share_mint,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+    pub share_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: metadata of the order nft, validated against the Token Metadata program
+    pub nft_metadata: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = borrower_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = borrower_stablecoin_vault.owner == order.borrower,
+    )]
+    pub borrower_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct PaybackPool<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), pool.collection.as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = configuration,
+        has_one = liquidity_vault,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = order.lender == pool.key(),
+        constraint = order.borrower == signer.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct LiquidatePool<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), pool.collection.as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = configuration,
+        has_one = liquidity_vault,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.lender == pool.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = signer
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    // custody of the defaulted nft, the keeper pays its rent and gets the one of the order
+    #[account(
+        init,
+        payer = signer,
+        seeds = [pool.key().as_ref(), nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_VAULT_SEED.as_ref()],
+        bump,
+        token::mint = nft_mint,
+        token::authority = pool,
+    )]
+    pub pool_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + DefaultedNft::LEN,
+        seeds = [pool.key().as_ref(), nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_DEFAULTED_SEED.as_ref()],
+        bump,
+    )]
+    pub defaulted: Box<Account<'info, DefaultedNft>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ManageDefaultedNft<'info> {
+    pub manager: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = manager,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+
+    #[account(
+        mut,
+        has_one = pool,
+    )]
+    pub defaulted: Box<Account<'info, DefaultedNft>>,
+}
+
+#[derive(Accounts)]
+pub struct BuyDefaultedNft<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [pool.configuration.as_ref(), pool.collection.as_ref(), pool.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_POOL_SEED.as_ref()],
+        bump,
+        has_one = liquidity_vault,
+    )]
+    pub pool: Box<Account<'info, LendingPool>>,
+    #[account(mut)]
+    pub liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        constraint = defaulted.nft_vault == pool_nft_vault.key(),
+        close = keeper,
+    )]
+    pub defaulted: Box<Account<'info, DefaultedNft>>,
+
+    /// CHECK: receives the rent of the custody accounts
+    #[account(
+        mut,
+        constraint = keeper.key() == defaulted.keeper,
+    )]
+    pub keeper: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub pool_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = buyer_stablecoin_vault.mint == pool.stablecoin_mint,
+        constraint = buyer_stablecoin_vault.owner == signer.key(),
+    )]
+    pub buyer_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = buyer_nft_vault.mint == defaulted.nft_mint,
+    )]
+    pub buyer_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct AddCollateral<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.borrower == signer.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // misc
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct WithdrawCollateral<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.borrower == signer.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // misc
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+pub struct AddNativeCurrency<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: SystemAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Currency::LEN,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrderSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        constraint = currency.stablecoin_vault == sol_vault.key(),
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer=signer,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump,
+        token::mint=nft_mint,
+        token::authority = nft_vault,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct CancelOrderSol<'info> {
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == sol_vault.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut
+    )]
+    pub signer: Signer<'info>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct GiveLoanSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.stablecoin_vault == sol_vault.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        address = order.borrower,
+    )]
+    pub borrower: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct PaybackSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.stablecoin_vault == sol_vault.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        address = order.lender,
+    )]
+    pub lender: SystemAccount<'info>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct LiquidateSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == sol_vault.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        address = order.lender,
+    )]
+    pub lender: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == order.lender,
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct AddCollateralSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == sol_vault.key(),
+        constraint = order.borrower == signer.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct WithdrawCollateralSol<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == sol_vault.key(),
+        constraint = order.borrower == signer.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), constants::NATIVE_SOL_MINT.as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AddToken2022Currency<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    /// CHECK: a Token-2022 mint, its extensions are checked in the handler
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: associated token account of the currency, created in the handler
+    #[account(
+        mut,
+        constraint = stablecoin_vault.key() == token_2022::associated_address(&currency.key(), &stablecoin_mint.key(), &token_2022::ID),
+    )]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    /// CHECK: associated token account of the configuration, created in the handler
+    #[account(
+        mut,
+        constraint = fee_vault.key() == token_2022::associated_address(&configuration.key(), &stablecoin_mint.key(), &token_2022::ID),
+    )]
+    pub fee_vault: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Currency::LEN,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    pub system_program: Program<'info, System>,
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrder2022<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the vault of the currency
+    #[account(mut)]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: a token account of the borrower for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub user_stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer=signer,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump,
+        token::mint=nft_mint,
+        token::authority = nft_vault,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct CancelOrder2022<'info> {
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the vault of the currency
+    #[account(mut)]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: a token account of the borrower for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub user_stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut
+    )]
+    pub signer: Signer<'info>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct GiveLoan2022<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.stablecoin_vault == currency.stablecoin_vault,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: a token account of the lender for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&lender_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub lender_stablecoin_vault: UncheckedAccount<'info>,
+
+    /// CHECK: a token account of the borrower for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&borrower_stablecoin_vault, &stablecoin_mint, &order.borrower),
+    )]
+    pub borrower_stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct Payback2022<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the vault of the currency
+    #[account(mut)]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: a token account of the borrower for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub user_stablecoin_vault: UncheckedAccount<'info>,
+
+    /// CHECK: a token account of the lender for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&lender_stablecoin_vault, &stablecoin_mint, &order.lender),
+    )]
+    pub lender_stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct Liquidate2022<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    // signs for the fee vault
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the vault of the currency
+    #[account(mut)]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    /// CHECK: the fee vault of the currency, pays the bounty when it comes out of the reserve
+    #[account(mut)]
+    pub fee_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+        has_one = fee_vault,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        address = order.lender,
+    )]
+    pub lender: SystemAccount<'info>,
+
+    /// CHECK: a token account of the lender for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&lender_stablecoin_vault, &stablecoin_mint, &order.lender),
+    )]
+    pub lender_stablecoin_vault: UncheckedAccount<'info>,
+
+    /// CHECK: a token account of the keeper for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&keeper_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub keeper_stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        bump
+    )]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == order.lender,
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct AddCollateral2022<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.borrower == signer.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the vault of the currency
+    #[account(mut)]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: a token account of the borrower for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub user_stablecoin_vault: UncheckedAccount<'info>,
+
+    // misc
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct WithdrawCollateral2022<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.borrower == signer.key(),
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: the Token-2022 mint of the currency
+    #[account(owner = token_2022::ID)]
+    pub stablecoin_mint: UncheckedAccount<'info>,
+
+    /// CHECK: the vault of the currency
+    #[account(mut)]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    /// CHECK: a token account of the borrower for the currency
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_stablecoin_vault, &stablecoin_mint, &signer.key()),
+    )]
+    pub user_stablecoin_vault: UncheckedAccount<'info>,
+
+    // misc
+    /// CHECK: the Token-2022 program
+    #[account(address = token_2022::ID)]
+    pub token_2022_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrderPnft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: associated token account of the order, created by the Token Metadata program
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address(&order.key(), &nft_mint.key()),
+    )]
+    pub nft_vault: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: UncheckedAccount<'info>,
+    /// CHECK: validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+    /// CHECK: token record of user_nft_vault, validated by the Token Metadata program
+    #[account(mut)]
+    pub user_token_record: UncheckedAccount<'info>,
+    /// CHECK: token record of nft_vault, created by the Token Metadata program, validated by the Token Metadata program
+    #[account(mut)]
+    pub vault_token_record: UncheckedAccount<'info>,
+    /// CHECK: rule set program, or the Token Metadata program when the nft has no rule set
+    pub authorization_rules_program: UncheckedAccount<'info>,
+    /// CHECK: rule set of the nft, or the Token Metadata program when it has none
+    pub authorization_rules: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    /// CHECK: checked against the sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct CancelOrderPnft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: associated token account the nft is returned to, created by the Token Metadata program if missing
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address(&signer.key(), &nft_mint.key()),
+    )]
+    pub user_nft_vault: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: UncheckedAccount<'info>,
+    /// CHECK: validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+    /// CHECK: token record of user_nft_vault, created by the Token Metadata program, validated by the Token Metadata program
+    #[account(mut)]
+    pub user_token_record: UncheckedAccount<'info>,
+    /// CHECK: token record of nft_vault, validated by the Token Metadata program
+    #[account(mut)]
+    pub vault_token_record: UncheckedAccount<'info>,
+    /// CHECK: rule set program, or the Token Metadata program when the nft has no rule set
+    pub authorization_rules_program: UncheckedAccount<'info>,
+    /// CHECK: rule set of the nft, or the Token Metadata program when it has none
+    pub authorization_rules: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    /// CHECK: checked against the sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct PaybackPnft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: associated token account the nft is returned to, created by the Token Metadata program if missing
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address(&signer.key(), &nft_mint.key()),
+    )]
+    pub user_nft_vault: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: UncheckedAccount<'info>,
+    /// CHECK: validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+    /// CHECK: token record of user_nft_vault, created by the Token Metadata program, validated by the Token Metadata program
+    #[account(mut)]
+    pub user_token_record: UncheckedAccount<'info>,
+    /// CHECK: token record of nft_vault, validated by the Token Metadata program
+    #[account(mut)]
+    pub vault_token_record: UncheckedAccount<'info>,
+    /// CHECK: rule set program, or the Token Metadata program when the nft has no rule set
+    pub authorization_rules_program: UncheckedAccount<'info>,
+    /// CHECK: rule set of the nft, or the Token Metadata program when it has none
+    pub authorization_rules: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    /// CHECK: checked against the sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct LiquidatePnft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_vault == nft_vault.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    /// CHECK: the payee of the loan, who receives the nft
+    #[account(address = order.payee(&note_vault)?)]
+    pub payee: UncheckedAccount<'info>,
+
+    // receives the bounty, any account of the currency when the payee liquidates
+    #[account(
+        mut,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
+    )]
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: associated token account of the payee the nft goes to, created by the Token Metadata program if missing
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address(&payee.key(), &nft_mint.key()),
+    )]
+    pub user_nft_vault: UncheckedAccount<'info>,
+
+    /// CHECK: validated by the Token Metadata program
+    #[account(mut)]
+    pub nft_metadata: UncheckedAccount<'info>,
+    /// CHECK: validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+    /// CHECK: token record of user_nft_vault, created by the Token Metadata program, validated by the Token Metadata program
+    #[account(mut)]
+    pub user_token_record: UncheckedAccount<'info>,
+    /// CHECK: token record of nft_vault, validated by the Token Metadata program
+    #[account(mut)]
+    pub vault_token_record: UncheckedAccount<'info>,
+    /// CHECK: rule set program, or the Token Metadata program when the nft has no rule set
+    pub authorization_rules_program: UncheckedAccount<'info>,
+    /// CHECK: rule set of the nft, or the Token Metadata program when it has none
+    pub authorization_rules: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    /// CHECK: checked against the sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrderDelegated<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == signer.key(),
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: master edition of the nft, validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct CancelOrderDelegated<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.key() == order.nft_vault,
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: master edition of the nft, validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct PaybackDelegated<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.key() == order.nft_vault,
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: master edition of the nft, validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct LiquidateDelegated<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == nft_mint.key(),
+        close = lender,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    // receives the bounty, any account of the currency when the payee liquidates
+    #[account(
+        mut,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
+    )]
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = nft_mint.supply == 1,
+        constraint = nft_mint.decimals == 0,
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = borrower_nft_vault.key() == order.nft_vault,
+    )]
+    pub borrower_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_nft_vault.mint == nft_mint.key(),
+        constraint = user_nft_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: master edition of the nft, validated by the Token Metadata program
+    pub nft_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    /// CHECK: checked against the program id
+    #[account(address = metaplex::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOrderBundle<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
+    )]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BUNDLE_SEED.as_ref()],
+        space = 8 + Bundle::LEN,
+        bump,
+    )]
+    pub bundle: Box<Account<'info, Bundle>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct CancelOrderBundle<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == bundle.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BUNDLE_SEED.as_ref()],
+        bump,
+        has_one = order,
+        close = signer,
+    )]
+    pub bundle: Box<Account<'info, Bundle>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
+    #[account(
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    // misc
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(_order_id: u64)]
+pub struct PaybackBundle<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
+    #[account(
+        mut,
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
+        bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == bundle.key(),
+        constraint = order.borrower == signer.key(),
+        close = signer,
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BUNDLE_SEED.as_ref()],
+        bump,
+        has_one = order,
+        close = signer,
+    )]
+    pub bundle: Box<Account<'info, Bundle>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
+    )]
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
 
-        Ok(())
-    }
-}
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(mut)]
-    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
+    )]
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
-    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
 
     #[account(
-        init,
-        payer = signer,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        mut,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
+    )]
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
         bump,
-        token::mint = stablecoin_mint,
-        token::authority = stablecoin_vault,
     )]
-    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    pub borrower_stats: Box<Account<'info, UserStats>>,
 
     #[account(
-        init,
-        payer = signer,
-        space = 8 + Configuration::LEN,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
         bump,
     )]
-    pub configuration: Box<Account<'info, Configuration>>,
+    pub lender_stats: Box<Account<'info, UserStats>>,
 
+    // misc
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct CreateOrder<'info> {
+#[instruction(_order_id: u64)]
+pub struct LiquidateBundle<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    #[account(mut)]
+    pub configuration: Box<Account<'info, Configuration>>,
+
+    // Order.
     #[account(
         mut,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        seeds = [
+            _order_id.to_string().as_ref(),
+            configuration.to_account_info().key().as_ref()
+        ],
         bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == bundle.key(),
+        close = lender,
     )]
-    pub configuration: Box<Account<'info, Configuration>>,
+    pub order: Box<Account<'info, Order>>,
 
-    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        seeds = [order.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_BUNDLE_SEED.as_ref()],
+        bump,
+        has_one = order,
+        close = lender,
+    )]
+    pub bundle: Box<Account<'info, Bundle>>,
+
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
         bump,
     )]
     pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
-        constraint = user_stablecoin_vault.owner == signer.key(),
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
     )]
-    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    pub currency: Box<Account<'info, Currency>>,
 
     #[account(
-        constraint = nft_mint.supply == 1,
-        constraint = nft_mint.decimals == 0,
+        mut,
+        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
     )]
-    pub nft_mint: Box<Account<'info, Mint>>,
+    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
 
+    // receives the bounty, any account of the currency when the payee liquidates
     #[account(
         mut,
-        constraint = user_nft_vault.mint == nft_mint.key(),
-        constraint = user_nft_vault.owner == signer.key(),
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
     )]
-    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+    // pays the bounty when it comes out of the reserve
     #[account(
-        init,
-        payer=signer,
-        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
         bump,
-        token::mint=nft_mint,
-        token::authority = nft_vault,
     )]
-    pub nft_vault: Box<Account<'info, TokenAccount>>,
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
-        init,
-        payer = signer,
-        seeds = [
-        configuration.order_id.to_string().as_ref(),
-        configuration.to_account_info().key().as_ref()
-        ],
-        space = 8 + Order::LEN,
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
         bump,
     )]
-    pub order: Box<Account<'info, Order>>,
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
 
     // misc
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(_order_id: u64)]
-pub struct CancelOrder<'info> {
+pub struct CreateOrderToken<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
     #[account(
         mut,
-        has_one = stablecoin_vault,
-        has_one = stablecoin_mint
+        seeds = [configuration.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_SEED.as_ref()],
+        bump,
     )]
     pub configuration: Box<Account<'info, Configuration>>,
 
-    // Order.
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
     #[account(
         mut,
-        seeds = [
-            _order_id.to_string().as_ref(),
-            configuration.to_account_info().key().as_ref()
-        ],
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
         bump,
-        constraint = order.stablecoin_vault == stablecoin_vault.key(),
-        constraint = order.nft_vault == nft_vault.key(),
-        constraint = order.nft_mint == nft_mint.key(),
-        close = signer,
     )]
-    pub order: Box<Account<'info, Order>>,
+    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
-    pub stablecoin_mint: Box<Account<'info, Mint>>,
     #[account(
         mut,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
         bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
     )]
-    pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    pub currency: Box<Account<'info, Currency>>,
 
     #[account(
         mut,
@@ -438,111 +9169,178 @@ pub struct CancelOrder<'info> {
     )]
     pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+    /// CHECK: the approved collateral mint, read in the handler
+    pub collateral_mint: UncheckedAccount<'info>,
+
     #[account(
-        constraint = nft_mint.supply == 1,
-        constraint = nft_mint.decimals == 0,
+        mut,
+        seeds = [configuration.key().as_ref(), collateral_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_COLLATERAL_SEED.as_ref()],
+        bump,
     )]
-    pub nft_mint: Box<Account<'info, Mint>>,
+    pub collateral_token: Box<Account<'info, CollateralToken>>,
 
+    /// CHECK: a token account of the borrower for the collateral mint
     #[account(
         mut,
-        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
+        constraint = token_2022::is_account_of(&user_collateral_vault, &collateral_mint, &signer.key()),
+    )]
+    pub user_collateral_vault: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = signer,
+        seeds = [
+        configuration.order_id.to_string().as_ref(),
+        configuration.to_account_info().key().as_ref()
+        ],
+        space = 8 + Order::LEN,
         bump,
     )]
-    pub nft_vault: Box<Account<'info, TokenAccount>>,
+    pub order: Box<Account<'info, Order>>,
 
+    /// CHECK: associated token account of the order, created in the handler under the mint's token program
     #[account(
         mut,
-        constraint = user_nft_vault.mint == nft_mint.key(),
-        constraint = user_nft_vault.owner == signer.key(),
+        constraint = collateral_vault.key() == token_2022::associated_address(&order.key(), &collateral_mint.key(), collateral_mint.owner),
     )]
-    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    pub collateral_vault: UncheckedAccount<'info>,
 
     #[account(
-        mut
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserStats::LEN,
+        seeds = [signer.key().as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
     )]
-    pub signer: Signer<'info>,
+    pub borrower_stats: Box<Account<'info, UserStats>>,
 
     // misc
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the token program owning the collateral mint
+    #[account(
+        constraint = token_2022::is_token_program(collateral_token_program.key),
+        constraint = collateral_token_program.key == collateral_mint.owner,
+    )]
+    pub collateral_token_program: UncheckedAccount<'info>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
 #[instruction(_order_id: u64)]
-pub struct GiveLoan<'info> {
+pub struct CancelOrderToken<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    #[account(
-        mut,
-        has_one = stablecoin_vault,
-        has_one = stablecoin_mint
-    )]
+    #[account(mut)]
     pub configuration: Box<Account<'info, Configuration>>,
 
     // Order.
     #[account(
         mut,
-        constraint = order.stablecoin_vault == stablecoin_vault.key(),
         seeds = [
             _order_id.to_string().as_ref(),
             configuration.to_account_info().key().as_ref()
         ],
         bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == collateral_mint.key(),
+        constraint = order.nft_vault == collateral_vault.key(),
+        constraint = order.collateral_amount > 0,
+        constraint = order.borrower == signer.key(),
+        close = signer,
     )]
     pub order: Box<Account<'info, Order>>,
 
     pub stablecoin_mint: Box<Account<'info, Mint>>,
     #[account(
         mut,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
         bump,
     )]
     pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
-        constraint = lender_stablecoin_vault.owner == signer.key(),
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
     )]
-    pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    pub currency: Box<Account<'info, Currency>>,
+
     #[account(
         mut,
-        constraint = borrower_stablecoin_vault.mint == stablecoin_mint.key(),
-        constraint = borrower_stablecoin_vault.owner == order.borrower,
+        constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = user_stablecoin_vault.owner == signer.key(),
     )]
-    pub borrower_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+    pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: the order's collateral mint, read in the handler
+    #[account(mut)]
+    pub collateral_mint: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), collateral_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_COLLATERAL_SEED.as_ref()],
+        bump,
+    )]
+    pub collateral_token: Box<Account<'info, CollateralToken>>,
+
+    /// CHECK: the order's collateral vault
+    #[account(mut)]
+    pub collateral_vault: UncheckedAccount<'info>,
+
+    /// CHECK: a token account of the recipient for the collateral mint
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_collateral_vault, &collateral_mint, &signer.key()),
+    )]
+    pub user_collateral_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
 
     // misc
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the token program owning the collateral mint
+    #[account(
+        constraint = token_2022::is_token_program(collateral_token_program.key),
+        constraint = collateral_token_program.key == collateral_mint.owner,
+    )]
+    pub collateral_token_program: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(_order_id: u64)]
-pub struct Payback<'info> {
+pub struct PaybackToken<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    #[account(
-        mut,
-        has_one = stablecoin_vault,
-        has_one = stablecoin_mint
-    )]
+    #[account(mut)]
     pub configuration: Box<Account<'info, Configuration>>,
 
     // Order.
     #[account(
         mut,
-        constraint = order.stablecoin_vault == stablecoin_vault.key(),
-        constraint = order.nft_vault == nft_vault.key(),
-        constraint = order.nft_mint == nft_mint.key(),
         seeds = [
             _order_id.to_string().as_ref(),
             configuration.to_account_info().key().as_ref()
         ],
         bump,
+        constraint = order.stablecoin_vault == stablecoin_vault.key(),
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == collateral_mint.key(),
+        constraint = order.nft_vault == collateral_vault.key(),
+        constraint = order.collateral_amount > 0,
+        constraint = order.borrower == signer.key(),
         close = signer,
     )]
     pub order: Box<Account<'info, Order>>,
@@ -550,18 +9348,31 @@ pub struct Payback<'info> {
     pub stablecoin_mint: Box<Account<'info, Mint>>,
     #[account(
         mut,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
-        bump
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
     )]
     pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
     #[account(
         mut,
         constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
-        constraint = lender_stablecoin_vault.owner == order.lender,
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
     )]
     pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
     #[account(
         mut,
         constraint = user_stablecoin_vault.mint == stablecoin_mint.key(),
@@ -569,43 +9380,63 @@ pub struct Payback<'info> {
     )]
     pub user_stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+    /// CHECK: the order's collateral mint, read in the handler
+    #[account(mut)]
+    pub collateral_mint: UncheckedAccount<'info>,
+
     #[account(
-        constraint = nft_mint.supply == 1,
-        constraint = nft_mint.decimals == 0,
+        mut,
+        seeds = [configuration.key().as_ref(), collateral_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_COLLATERAL_SEED.as_ref()],
+        bump,
     )]
-    pub nft_mint: Box<Account<'info, Mint>>,
+    pub collateral_token: Box<Account<'info, CollateralToken>>,
+
+    /// CHECK: the order's collateral vault
+    #[account(mut)]
+    pub collateral_vault: UncheckedAccount<'info>,
+
+    /// CHECK: a token account of the recipient for the collateral mint
     #[account(
         mut,
-        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
-        bump
+        constraint = token_2022::is_account_of(&user_collateral_vault, &collateral_mint, &signer.key()),
     )]
-    pub nft_vault: Box<Account<'info, TokenAccount>>,
+    pub user_collateral_vault: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        constraint = user_nft_vault.mint == nft_mint.key(),
-        constraint = user_nft_vault.owner == signer.key(),
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
     )]
-    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
 
     // misc
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the token program owning the collateral mint
+    #[account(
+        constraint = token_2022::is_token_program(collateral_token_program.key),
+        constraint = collateral_token_program.key == collateral_mint.owner,
+    )]
+    pub collateral_token_program: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(_order_id: u64)]
-pub struct Liquidate<'info> {
+pub struct LiquidateToken<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    #[account(
-        mut,
-        has_one = stablecoin_vault,
-        has_one = stablecoin_mint
-    )]
+    #[account(mut)]
     pub configuration: Box<Account<'info, Configuration>>,
 
+    // Order.
     #[account(
         mut,
         seeds = [
@@ -614,50 +9445,109 @@ pub struct Liquidate<'info> {
         ],
         bump,
         constraint = order.stablecoin_vault == stablecoin_vault.key(),
-        constraint = order.nft_vault == nft_vault.key(),
-        constraint = order.nft_mint == nft_mint.key(),
-        close = signer
+        constraint = order.stablecoin_mint == stablecoin_mint.key(),
+        constraint = order.nft_mint == collateral_mint.key(),
+        constraint = order.nft_vault == collateral_vault.key(),
+        constraint = order.collateral_amount > 0,
+        close = lender,
     )]
     pub order: Box<Account<'info, Order>>,
 
+    /// CHECK: receives the rent of the order
+    #[account(
+        mut,
+        constraint = lender.key() == order.lender,
+    )]
+    pub lender: UncheckedAccount<'info>,
+
     pub stablecoin_mint: Box<Account<'info, Mint>>,
     #[account(
         mut,
-        seeds = [stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
-        bump
+        seeds = [vault_prefix(&configuration, &stablecoin_mint.key()), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()],
+        bump,
     )]
     pub stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+        mut,
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_CURRENCY_SEED.as_ref()],
+        bump,
+        has_one = stablecoin_vault,
+        has_one = stablecoin_mint,
+    )]
+    pub currency: Box<Account<'info, Currency>>,
+
     #[account(
         mut,
         constraint = lender_stablecoin_vault.mint == stablecoin_mint.key(),
-        constraint = lender_stablecoin_vault.owner == order.lender,
+        constraint = lender_stablecoin_vault.owner == order.payee(&note_vault)?,
     )]
     pub lender_stablecoin_vault: Box<Account<'info, TokenAccount>>,
 
+    /// CHECK: loan note account of the payee, read only when the loan has a note
+    pub note_vault: UncheckedAccount<'info>,
+
+    // receives the bounty, any account of the currency when the payee liquidates
     #[account(
         mut,
-        constraint = nft_mint.supply == 1,
-        constraint = nft_mint.decimals == 0,
+        constraint = keeper_stablecoin_vault.mint == stablecoin_mint.key(),
+        constraint = keeper_stablecoin_vault.owner == signer.key(),
     )]
-    pub nft_mint: Box<Account<'info, Mint>>,
+    pub keeper_stablecoin_vault: Box<Account<'info, TokenAccount>>,
+
+    // pays the bounty when it comes out of the reserve
     #[account(
         mut,
-        seeds = [nft_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()],
-        bump
+        seeds = [configuration.key().as_ref(), stablecoin_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED.as_ref()],
+        bump,
     )]
-    pub nft_vault: Box<Account<'info, TokenAccount>>,
+    pub fee_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: the order's collateral mint, read in the handler
+    #[account(mut)]
+    pub collateral_mint: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        constraint = user_nft_vault.mint == nft_mint.key(),
-        constraint = user_nft_vault.owner == order.lender,
+        seeds = [configuration.key().as_ref(), collateral_mint.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_COLLATERAL_SEED.as_ref()],
+        bump,
     )]
-    pub user_nft_vault: Box<Account<'info, TokenAccount>>,
+    pub collateral_token: Box<Account<'info, CollateralToken>>,
+
+    /// CHECK: the order's collateral vault
+    #[account(mut)]
+    pub collateral_vault: UncheckedAccount<'info>,
+
+    /// CHECK: a token account of the recipient for the collateral mint
+    #[account(
+        mut,
+        constraint = token_2022::is_account_of(&user_collateral_vault, &collateral_mint, &order.payee(&note_vault)?),
+    )]
+    pub user_collateral_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [order.borrower.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub borrower_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        mut,
+        seeds = [order.lender.as_ref(), configuration.key().as_ref(), constants::NFT_COLLATERIZED_LOANS_USER_STATS_SEED.as_ref()],
+        bump,
+    )]
+    pub lender_stats: Box<Account<'info, UserStats>>,
 
     // misc
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>
+    pub token_program: Program<'info, Token>,
+    /// CHECK: the token program owning the collateral mint
+    #[account(
+        constraint = token_2022::is_token_program(collateral_token_program.key),
+        constraint = collateral_token_program.key == collateral_mint.owner,
+    )]
+    pub collateral_token_program: UncheckedAccount<'info>,
 }
 
 #[account]
@@ -672,10 +9562,206 @@ pub struct Configuration {
     pub total_additional_collateral: u64,
     // fee percentage
     pub fee_pt: u8,
+    // loans currently funded and not yet settled
+    pub active_loans: u64,
+    // principal of the active loans
+    pub total_principal_outstanding: u64,
+    // cumulative principal lent
+    pub total_volume_lent: u64,
+    // cumulative interest paid back to lenders
+    pub total_interest_paid: u64,
+    // number of liquidated loans
+    pub total_liquidations: u64,
+    // shortest loan period accepted, in seconds
+    pub min_period: u64,
+    // longest loan period accepted, in seconds
+    pub max_period: u64,
+    // highest interest accepted, in basis points of the request amount
+    pub max_interest_bps: u64,
+    // admin allowed to update the configuration
+    pub authority: Pubkey,
+    // length of liquidation auctions in seconds, zero hands the nft to the lender
+    pub auction_duration: u64,
+    // opening price of an auction, in basis points of the debt
+    pub auction_start_bps: u64,
+    // closing price of an auction, in basis points of the debt
+    pub auction_end_bps: u64,
+    // dutch or english liquidation auctions
+    pub auction_kind: u8,
+    // seconds a late english auction bid pushes the end back to
+    pub auction_extension: u64,
+    // smallest raise over the highest bid, in basis points
+    pub min_bid_increment_bps: u64,
+    // keeper reward for liquidating someone else's loan, in basis points of the principal
+    pub keeper_bounty_bps: u64,
+    // pay keepers out of the fee vault instead of the lender's additional collateral
+    pub bounty_from_reserve: bool,
+    // keeper bounties charged on liquidated additional collateral
+    pub total_fees_collected: u64,
+    // keeper bounties paid out of the fee vault
+    pub total_bounties_paid: u64,
+    // smallest additional collateral accepted in the configuration mint
+    pub min_additional_collateral: u64,
 }
 
 impl Configuration {
-    pub const LEN:usize = 32 + 32 + 8 + 8 + 1;
+    pub const LEN:usize = 32 + 32 + 8 + 8 + 1 + 8 * 5 + 8 * 3 + 32 + 8 * 3 + 1 + 8 * 2 + 8 + 1 + 8 * 3;
+
+    pub fn validate_loan_terms(&self, request_amount: u64, interest: u64, period: u64) -> Result<()> {
+        if period < self.min_period {
+            return Err(ErrorCode::PeriodTooShort.into());
+        }
+        if period > self.max_period {
+            return Err(ErrorCode::PeriodTooLong.into());
+        }
+        // interest / request_amount <= max_interest_bps / BASIS_POINTS
+        let max_interest = (request_amount as u128) * (self.max_interest_bps as u128);
+        if (interest as u128) * (constants::BASIS_POINTS as u128) > max_interest {
+            return Err(ErrorCode::InterestTooHigh.into());
+        }
+        Ok(())
+    }
+
+    // Every currency counts and totals its own loans. The configuration does the same for the
+    // loans of its own mint, as it did before there were others.
+    fn totals(&self, currency: &Currency) -> bool {
+        currency.stablecoin_mint == self.stablecoin_mint
+    }
+
+    // The configuration keeps the minimum of its own mint, every other currency carries its own.
+    pub fn min_collateral(&self, currency: &Currency) -> u64 {
+        if self.totals(currency) {
+            self.min_additional_collateral
+        } else {
+            currency.min_additional_collateral
+        }
+    }
+
+    pub fn lock_collateral(&mut self, currency: &mut Currency, amount: u64) -> Result<()> {
+        currency.total_additional_collateral = currency.total_additional_collateral.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        if self.totals(currency) {
+            self.total_additional_collateral = self.total_additional_collateral.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn release_collateral(&mut self, currency: &mut Currency, amount: u64) -> Result<()> {
+        currency.total_additional_collateral = currency.total_additional_collateral.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+        if self.totals(currency) {
+            self.total_additional_collateral = self.total_additional_collateral.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_loan(&mut self, currency: &mut Currency, principal: u64) -> Result<()> {
+        currency.active_loans = currency.active_loans.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_principal_outstanding = currency.total_principal_outstanding.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_volume_lent = currency.total_volume_lent.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        if self.totals(currency) {
+            self.active_loans = self.active_loans.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+            self.total_principal_outstanding = self.total_principal_outstanding.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+            self.total_volume_lent = self.total_volume_lent.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_repayment(&mut self, currency: &mut Currency, principal: u64, interest: u64) -> Result<()> {
+        currency.active_loans = currency.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_principal_outstanding = currency.total_principal_outstanding.checked_sub(principal).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_interest_paid = currency.total_interest_paid.checked_add(interest).ok_or(ErrorCode::MathOverflow)?;
+        if self.totals(currency) {
+            self.active_loans = self.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+            self.total_principal_outstanding = self.total_principal_outstanding.checked_sub(principal).ok_or(ErrorCode::MathOverflow)?;
+            self.total_interest_paid = self.total_interest_paid.checked_add(interest).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_bounty(&mut self, currency: &mut Currency, from_collateral: u64, from_reserve: u64) -> Result<()> {
+        currency.total_fees_collected = currency.total_fees_collected.checked_add(from_collateral).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_bounties_paid = currency.total_bounties_paid.checked_add(from_reserve).ok_or(ErrorCode::MathOverflow)?;
+        if self.totals(currency) {
+            self.total_fees_collected = self.total_fees_collected.checked_add(from_collateral).ok_or(ErrorCode::MathOverflow)?;
+            self.total_bounties_paid = self.total_bounties_paid.checked_add(from_reserve).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_liquidation(&mut self, currency: &mut Currency, principal: u64) -> Result<()> {
+        currency.active_loans = currency.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_liquidations = currency.total_liquidations.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        currency.total_principal_outstanding = currency.total_principal_outstanding.checked_sub(principal).ok_or(ErrorCode::MathOverflow)?;
+        if self.totals(currency) {
+            self.active_loans = self.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+            self.total_liquidations = self.total_liquidations.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+            self.total_principal_outstanding = self.total_principal_outstanding.checked_sub(principal).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Currency {
+    // configuration approving the currency
+    pub configuration: Pubkey,
+    // mint of the loan currency
+    pub stablecoin_mint: Pubkey,
+    // vault holding the additional collateral in this currency
+    pub stablecoin_vault: Pubkey,
+    // whether new orders can be created in this currency
+    pub enabled: bool,
+    // smallest additional collateral accepted, the configuration keeps the one of its own mint
+    pub min_additional_collateral: u64,
+    // total additional collateral
+    pub total_additional_collateral: u64,
+    // principal of the active loans
+    pub total_principal_outstanding: u64,
+    // cumulative principal lent
+    pub total_volume_lent: u64,
+    // cumulative interest paid back to lenders
+    pub total_interest_paid: u64,
+    // vault holding the protocol fees in this currency
+    pub fee_vault: Pubkey,
+    // keeper bounties paid out of the fee vault
+    pub total_bounties_paid: u64,
+    // keeper bounties charged on liquidated additional collateral
+    pub total_fees_collected: u64,
+    // loans funded and not yet settled
+    pub active_loans: u64,
+    // loans whose collateral went to the lender
+    pub total_liquidations: u64,
+}
+
+impl Currency {
+    pub const LEN:usize = 32 * 3 + 1 + 8 + 8 * 4 + 32 + 8 * 4;
+}
+
+#[account]
+#[derive(Default)]
+pub struct CollateralToken {
+    // configuration approving the token
+    pub configuration: Pubkey,
+    // mint of the fungible collateral
+    pub mint: Pubkey,
+    // whether new orders can post this token
+    pub enabled: bool,
+    // total amount locked in open orders and active loans
+    pub total_collateral: u64,
+}
+
+impl CollateralToken {
+    pub const LEN:usize = 32 * 2 + 1 + 8;
+
+    pub fn lock_collateral(&mut self, amount: u64) -> Result<()> {
+        self.total_collateral = self.total_collateral.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn release_collateral(&mut self, amount: u64) -> Result<()> {
+        self.total_collateral = self.total_collateral.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
 }
 
 #[account]
@@ -685,7 +9771,7 @@ pub struct Order {
     pub borrower: Pubkey,
     /// vault to send the loan
     pub stablecoin_vault: Pubkey,
-    // mint of the nft
+    // mint of the nft, or of the fungible collateral
     pub nft_mint: Pubkey,
     /// collateral vault holding the nft
     pub nft_vault: Pubkey,
@@ -709,10 +9795,525 @@ pub struct Order {
     pub withdrew_at: u64,
     // status of the order
     pub order_status: bool,
+    // id of the order within its configuration
+    pub order_id: u64,
+    // mint of the loan currency
+    pub stablecoin_mint: Pubkey,
+    // amount of fungible collateral held in nft_vault, zero for nft orders
+    pub collateral_amount: u64,
+    // additional collateral when the loan was funded
+    pub funded_collateral: u64,
+    // borrow index of the pool when it funded the loan, zero for peer-to-peer loans
+    pub borrow_index: u128,
+    // mint of the loan note given to the lender, default when the loan has none
+    pub note_mint: Pubkey,
 }
 
 impl Order {
-    pub const LEN:usize = 32 * 4 + 8 * 4 + 32 + 8 * 4 + 1;
+    pub const LEN:usize = 32 * 4 + 8 * 4 + 32 + 8 * 4 + 1 + 8 + 32 + 8 * 2 + 16 + 32;
+
+    // Whoever holds the loan note is repaid, the lender when the loan has none.
+    // The note account is not read for loans without a note.
+    pub fn payee(&self, note_vault: &AccountInfo) -> Result<Pubkey> {
+        if self.note_mint == Pubkey::default() {
+            return Ok(self.lender);
+        }
+        let note_vault: Account<TokenAccount> = Account::try_from(note_vault)?;
+        if note_vault.mint != self.note_mint || note_vault.amount != 1 {
+            return Err(ErrorCode::InvalidLoanNote.into());
+        }
+        Ok(note_vault.owner)
+    }
+
+    // plain NFT orders keep the nft in the [nft_mint, "nft_vault"] PDA, other kinds hold it elsewhere
+    pub fn escrowed(&self, program_id: &Pubkey) -> bool {
+        let (nft_vault_address, _) = Pubkey::find_program_address(&[self.nft_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED.as_ref()], program_id);
+        self.nft_vault == nft_vault_address
+    }
+
+    // auctions sell escrowed nfts of lender funded SPL loans, whose additional collateral is held
+    // in the ["st_vault"] PDA of the currency
+    pub fn auctionable(&self, configuration: &Account<Configuration>, program_id: &Pubkey) -> bool {
+        if self.borrow_index != 0 || self.stablecoin_mint == constants::NATIVE_SOL_MINT || !self.escrowed(program_id) {
+            return false;
+        }
+        let (stablecoin_vault_address, _) = Pubkey::find_program_address(&[vault_prefix(configuration, &self.stablecoin_mint), self.stablecoin_mint.as_ref(), constants::NFT_COLLATERIZED_LOANS_ST_VAULT_SEED.as_ref()], program_id);
+        self.stablecoin_vault == stablecoin_vault_address
+    }
+
+    // principal plus interest owed to the lender
+    pub fn repayment_amount(&self) -> Result<u64> {
+        self.request_amount.checked_add(self.interest).ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    // last timestamp the borrower can pay back at
+    pub fn due_at(&self) -> Result<u64> {
+        self.loan_start_time.checked_add(self.period).ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    // interest earned pro rata over the elapsed part of the period
+    pub fn accrued_interest(&self, now: u64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.loan_start_time).min(self.period);
+        let accrued = (self.interest as u128)
+            .checked_mul(elapsed as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(self.period as u128)
+            .unwrap_or(0);
+        u64::try_from(accrued).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Bundle {
+    // order the nfts are collateral for
+    pub order: Pubkey,
+    // mints of the escrowed nfts, each held by an associated token account of the order
+    pub nft_mints: Vec<Pubkey>,
+}
+
+impl Bundle {
+    pub const MAX_NFTS: usize = 5;
+    pub const LEN:usize = 32 + 4 + 32 * Bundle::MAX_NFTS;
+}
+
+#[account]
+#[derive(Default)]
+pub struct LendingPool {
+    // configuration the pool lends in
+    pub configuration: Pubkey,
+    // verified metaplex collection the pool lends against
+    pub collection: Pubkey,
+    // mint of the loan currency
+    pub stablecoin_mint: Pubkey,
+    // admin allowed to change the limits
+    pub manager: Pubkey,
+    // vault holding the idle liquidity
+    pub liquidity_vault: Pubkey,
+    // mint of the LP shares
+    pub share_mint: Pubkey,
+    // vault holding the shares of queued withdrawals
+    pub share_escrow: Pubkey,
+    // principal of the active pool loans
+    pub total_borrowed: u64,
+    // pool loans currently funded and not yet settled
+    pub active_loans: u64,
+    // largest request the pool funds
+    pub max_loan_amount: u64,
+    // longest period the pool funds, in seconds
+    pub max_period: u64,
+    // highest share of the pool value lent out, in basis points
+    pub max_utilization_bps: u64,
+    // yearly borrow rate at zero utilization, in basis points
+    pub base_rate_bps: u64,
+    // yearly rate added up to the optimal utilization, in basis points
+    pub slope1_bps: u64,
+    // utilization where the curve steepens, in basis points
+    pub optimal_utilization_bps: u64,
+    // yearly rate added from the optimal to full utilization, in basis points
+    pub slope2_bps: u64,
+    // growth of a borrowed unit since the pool was created, BORROW_INDEX_ONE based
+    pub borrow_index: u128,
+    // last time the borrow index was brought up to date
+    pub last_accrued_at: u64,
+    // share of the pool value new loans leave liquid, in basis points
+    pub reserve_bps: u64,
+    // shares escrowed by pending withdrawal requests
+    pub queued_shares: u64,
+    // id of the oldest withdrawal request not yet claimed
+    pub queue_head: u64,
+    // id of the next withdrawal request
+    pub queue_tail: u64,
+    // carrying value of the defaulted nfts in custody
+    pub defaulted_value: u64,
+    // defaulted nfts in custody
+    pub defaulted_nfts: u64,
+    // debt never recovered from the defaulted nfts sold
+    pub realized_losses: u64,
+}
+
+impl LendingPool {
+    pub const LEN:usize = 32 * 7 + 8 * 5 + 8 * 4 + 16 + 8 + 8 * 4 + 8 * 3;
+
+    // idle liquidity plus the debt lent out plus the defaulted nfts in custody
+    pub fn total_value(&self, liquidity: u64) -> Result<u64> {
+        liquidity
+            .checked_add(self.total_borrowed)
+            .and_then(|value| value.checked_add(self.defaulted_value))
+            .ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    pub fn shares_for_deposit(&self, amount: u64, liquidity: u64, share_supply: u64) -> Result<u64> {
+        let value = self.total_value(liquidity)?;
+        if share_supply == 0 || value == 0 {
+            return Ok(amount);
+        }
+        let shares = (amount as u128) * (share_supply as u128) / (value as u128);
+        u64::try_from(shares).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    pub fn amount_for_shares(&self, shares: u64, liquidity: u64, share_supply: u64) -> Result<u64> {
+        if share_supply == 0 {
+            return Ok(0);
+        }
+        let amount = (shares as u128) * (self.total_value(liquidity)? as u128) / (share_supply as u128);
+        u64::try_from(amount).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    // Yearly borrow rate in basis points at the given borrowed amount and pool value.
+    pub fn borrow_rate_bps(&self, borrowed: u64, value: u64) -> Result<u64> {
+        let utilization = if value == 0 {
+            0
+        } else {
+            ((borrowed as u128) * (constants::BASIS_POINTS as u128) / (value as u128)).min(constants::BASIS_POINTS as u128) as u64
+        };
+        let optimal = self.optimal_utilization_bps.clamp(1, constants::BASIS_POINTS);
+        let rate = if utilization <= optimal {
+            let slope = (self.slope1_bps as u128) * (utilization as u128) / (optimal as u128);
+            (self.base_rate_bps as u128).checked_add(slope)
+        } else {
+            let excess = (self.slope2_bps as u128) * ((utilization - optimal) as u128) / ((constants::BASIS_POINTS - optimal).max(1) as u128);
+            (self.base_rate_bps as u128)
+                .checked_add(self.slope1_bps as u128)
+                .and_then(|rate| rate.checked_add(excess))
+        };
+        rate.and_then(|rate| u64::try_from(rate).ok()).ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    // Grows the borrow index and the lent out principal by the interest since the last update.
+    pub fn accrue(&mut self, liquidity: u64, now: u64) -> Result<()> {
+        if now <= self.last_accrued_at {
+            return Ok(());
+        }
+        let elapsed = now - self.last_accrued_at;
+        self.last_accrued_at = now;
+        if self.total_borrowed == 0 {
+            return Ok(());
+        }
+
+        let rate = self.borrow_rate_bps(self.total_borrowed, self.total_value(liquidity)?)?;
+        let growth = self.borrow_index
+            .checked_mul((rate as u128) * (elapsed as u128))
+            .ok_or(ErrorCode::MathOverflow)?
+            / ((constants::BASIS_POINTS as u128) * (constants::SECONDS_PER_YEAR as u128));
+        let index = self.borrow_index.checked_add(growth).ok_or(ErrorCode::MathOverflow)?;
+        let borrowed = (self.total_borrowed as u128)
+            .checked_mul(index)
+            .ok_or(ErrorCode::MathOverflow)?
+            / self.borrow_index;
+        self.total_borrowed = u64::try_from(borrowed).map_err(|_| ErrorCode::MathOverflow)?;
+        self.borrow_index = index;
+        Ok(())
+    }
+
+    // Principal plus interest owed on a pool loan at the current borrow index, rounded up.
+    pub fn debt_of(&self, order: &Order) -> Result<u64> {
+        if order.borrow_index == 0 {
+            return Err(ErrorCode::OrderNotQualified.into());
+        }
+        let grown = (order.request_amount as u128)
+            .checked_mul(self.borrow_index)
+            .ok_or(ErrorCode::MathOverflow)?;
+        // u128::div_ceil is newer than the BPF toolchain
+        let mut debt = grown / order.borrow_index;
+        if grown % order.borrow_index != 0 {
+            debt += 1;
+        }
+        u64::try_from(debt).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    // Interest over the whole period of the order at the rate once it is funded.
+    pub fn projected_interest(&self, order: &Order, liquidity: u64) -> Result<u64> {
+        let borrowed = self.total_borrowed.checked_add(order.request_amount).ok_or(ErrorCode::MathOverflow)?;
+        let rate = self.borrow_rate_bps(borrowed, self.total_value(liquidity)?)?;
+        let interest = (order.request_amount as u128) * (rate as u128) * (order.period as u128)
+            / ((constants::BASIS_POINTS as u128) * (constants::SECONDS_PER_YEAR as u128));
+        u64::try_from(interest).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    // current value of the shares waiting in the withdrawal queue
+    pub fn queued_value(&self, liquidity: u64, share_supply: u64) -> Result<u64> {
+        self.amount_for_shares(self.queued_shares, liquidity, share_supply)
+    }
+
+    // Risk limits an order must fit in to be funded by the pool.
+    pub fn check_order(&self, order: &Order, liquidity: u64, share_supply: u64) -> Result<()> {
+        if order.stablecoin_mint != self.stablecoin_mint || order.request_amount > self.max_loan_amount || order.period > self.max_period {
+            return Err(ErrorCode::OrderNotQualified.into());
+        }
+        // The reserve and the queued withdrawals stay liquid
+        let reserve = (self.total_value(liquidity)? as u128) * (self.reserve_bps as u128) / (constants::BASIS_POINTS as u128);
+        let kept = reserve + (self.queued_value(liquidity, share_supply)? as u128);
+        if (order.request_amount as u128) + kept > liquidity as u128 {
+            return Err(ErrorCode::InsufficientPoolLiquidity.into());
+        }
+        let borrowed = (self.total_borrowed as u128) + (order.request_amount as u128);
+        if borrowed * (constants::BASIS_POINTS as u128) > (self.total_value(liquidity)? as u128) * (self.max_utilization_bps as u128) {
+            return Err(ErrorCode::OrderNotQualified.into());
+        }
+        Ok(())
+    }
+
+    pub fn record_loan(&mut self, principal: u64) -> Result<()> {
+        self.total_borrowed = self.total_borrowed.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        self.active_loans = self.active_loans.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // The debt of a loan is rounded up, the pool total is not, so the last loan clears
+    // whatever rounding left in the total.
+    pub fn record_repayment(&mut self, debt: u64) -> Result<()> {
+        self.active_loans = self.active_loans.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        self.total_borrowed = if self.active_loans == 0 {
+            0
+        } else {
+            self.total_borrowed.checked_sub(debt).ok_or(ErrorCode::MathOverflow)?
+        };
+        Ok(())
+    }
+
+    // Moves the debt left after the recovered collateral into custody, returns it.
+    pub fn record_default(&mut self, debt: u64, recovered: u64) -> Result<u64> {
+        self.record_repayment(debt)?;
+        // Collateral beyond the debt stays with the pool and leaves no cost
+        let cost = debt.checked_sub(recovered.min(debt)).ok_or(ErrorCode::MathOverflow)?;
+        self.defaulted_value = self.defaulted_value.checked_add(cost).ok_or(ErrorCode::MathOverflow)?;
+        self.defaulted_nfts = self.defaulted_nfts.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        Ok(cost)
+    }
+
+    pub fn write_down(&mut self, amount: u64) -> Result<()> {
+        self.defaulted_value = self.defaulted_value.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // The price replaces the carrying value, anything short of the cost is a realized loss.
+    pub fn record_sale(&mut self, carrying_value: u64, cost: u64, price: u64) -> Result<()> {
+        self.write_down(carrying_value)?;
+        self.defaulted_nfts = self.defaulted_nfts.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        let loss = cost.checked_sub(price.min(cost)).ok_or(ErrorCode::MathOverflow)?;
+        self.realized_losses = self.realized_losses.checked_add(loss).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct WithdrawalRequest {
+    // pool the shares are withdrawn from
+    pub pool: Pubkey,
+    // depositor paid out on claim
+    pub owner: Pubkey,
+    // position in the queue of the pool
+    pub request_id: u64,
+    // escrowed shares, zero once cancelled
+    pub shares: u64,
+    // time the request was queued
+    pub requested_at: u64,
+}
+
+impl WithdrawalRequest {
+    pub const LEN:usize = 32 * 2 + 8 * 3;
+}
+
+#[account]
+#[derive(Default)]
+pub struct Auction {
+    // order whose collateral is auctioned
+    pub order: Pubkey,
+    // principal plus interest owed to the lender
+    pub debt: u64,
+    // price when the auction starts
+    pub start_price: u64,
+    // price the auction descends to
+    pub end_price: u64,
+    // auction start
+    pub start_time: u64,
+    // after this time the lender can claim the nft
+    pub end_time: u64,
+    // dutch or english auction
+    pub kind: u8,
+    // escrow of the highest english auction bid
+    pub bid_vault: Pubkey,
+    // highest bidder
+    pub bidder: Pubkey,
+    // highest bid, zero before the first bid
+    pub highest_bid: u64,
+    // started the auction and paid its rent
+    pub keeper: Pubkey,
+}
+
+impl Auction {
+    pub const LEN:usize = 32 + 8 * 5 + 1 + 32 * 2 + 8 + 32;
+
+    // the first bid must reach the end price, later ones must raise the highest bid by the increment
+    pub fn min_bid(&self, min_increment_bps: u64) -> Result<u64> {
+        if self.highest_bid == 0 {
+            return Ok(self.end_price.max(1));
+        }
+        let increment = bps_of(self.highest_bid, min_increment_bps)?.max(1);
+        self.highest_bid.checked_add(increment).ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    // receives the nft once the auction is settled
+    pub fn winner(&self, payee: Pubkey) -> Pubkey {
+        if self.highest_bid > 0 { self.bidder } else { payee }
+    }
+
+    // price descending linearly from the start price to the end price
+    pub fn current_price(&self, now: u64) -> Result<u64> {
+        dutch_price(self.start_price, self.end_price, self.start_time, self.end_time, now)
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct DefaultedNft {
+    // pool holding the nft
+    pub pool: Pubkey,
+    // mint of the nft
+    pub nft_mint: Pubkey,
+    // pool vault holding the nft
+    pub nft_vault: Pubkey,
+    // liquidator who paid the rent, refunded on sale
+    pub keeper: Pubkey,
+    // id of the defaulted order
+    pub order_id: u64,
+    // debt left after the additional collateral when the loan defaulted
+    pub cost: u64,
+    // value the nft is carried at in the pool
+    pub carrying_value: u64,
+    // time the loan was liquidated
+    pub defaulted_at: u64,
+    // sale price when listed
+    pub start_price: u64,
+    // sale price once the listing is over
+    pub end_price: u64,
+    // listing time, zero when not for sale
+    pub start_time: u64,
+    // time the price stops descending
+    pub end_time: u64,
+}
+
+impl DefaultedNft {
+    pub const LEN:usize = 32 * 4 + 8 * 8;
+}
+
+#[account]
+#[derive(Default)]
+pub struct LoanListing {
+    // order whose loan note is for sale
+    pub order: Pubkey,
+    // holder of the note when it was listed, paid on purchase
+    pub seller: Pubkey,
+    // note account the listing is approved to move the note from
+    pub note_vault: Pubkey,
+    // price in the loan currency
+    pub price: u64,
+    // time the note was listed
+    pub listed_at: u64,
+}
+
+impl LoanListing {
+    pub const LEN:usize = 32 * 3 + 8 * 2;
+}
+
+#[account]
+#[derive(Default)]
+pub struct PriceFeed {
+    // configuration the feed is used by
+    pub configuration: Pubkey,
+    // verified metaplex collection priced by the feed
+    pub collection: Pubkey,
+    // mint of the loan currency the price is quoted in
+    pub stablecoin_mint: Pubkey,
+    // signer allowed to submit prices
+    pub oracle: Pubkey,
+    // floor price, in raw units of the loan currency
+    pub price: u64,
+    // uncertainty of the price, in the same units
+    pub confidence: u64,
+    // time of the last submitted price
+    pub last_updated: u64,
+    // oldest price accepted for liquidations, in seconds
+    pub max_staleness: u64,
+    // debt over collateral value above which a loan can be liquidated, in basis points
+    pub liquidation_ltv_bps: u64,
+    // where the price is read from, see the oracle module
+    pub source: u8,
+    // pyth or switchboard price account
+    pub price_account: Pubkey,
+    // decimals of the loan currency external prices are converted to
+    pub decimals: u8,
+}
+
+impl PriceFeed {
+    pub const LEN:usize = 32 * 4 + 8 * 5 + 1 + 32 + 1;
+
+    pub fn is_liquidatable(&self, price: &oracle::OraclePrice, debt: u64, additional_collateral: u64, now: u64) -> Result<bool> {
+        if now.saturating_sub(price.last_updated) > self.max_staleness {
+            return Err(ErrorCode::StalePrice.into());
+        }
+        // Value the nft at the low end of the confidence interval
+        let value = (price.price.saturating_sub(price.confidence) as u128) + (additional_collateral as u128);
+        Ok((debt as u128) * (constants::BASIS_POINTS as u128) > value * (self.liquidation_ltv_bps as u128))
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct UserStats {
+    // owner of the positions
+    pub user: Pubkey,
+    // configuration the positions belong to
+    pub configuration: Pubkey,
+    // orders created by the user still waiting for a lender
+    pub open_orders: u64,
+    // loans the user has taken and not yet settled
+    pub active_borrows: u64,
+    // loans the user has given and not yet settled
+    pub active_loans: u64,
+    // cumulative amount borrowed in the configuration mint
+    pub total_borrowed: u64,
+    // cumulative amount lent in the configuration mint
+    pub total_lent: u64,
+    // ids of the open and active orders the user is part of
+    pub order_ids: Vec<u64>,
+}
+
+impl UserStats {
+    // ids per page, the account is created with one page and grows a page at a time
+    pub const PAGE_ORDERS: usize = 32;
+    pub const LEN:usize = 32 * 2 + 8 * 5 + 4 + 8 * UserStats::PAGE_ORDERS;
+
+    // Space for the pages holding the tracked ids.
+    pub fn space(&self) -> usize {
+        let pages = (self.order_ids.len() + UserStats::PAGE_ORDERS - 1) / UserStats::PAGE_ORDERS;
+        UserStats::LEN + 8 * UserStats::PAGE_ORDERS * pages.saturating_sub(1)
+    }
+
+    // Stats are created by the first instruction a user signs, the owner is filled in then.
+    pub fn open(&mut self, user: Pubkey, configuration: Pubkey) {
+        if self.user == Pubkey::default() {
+            self.user = user;
+            self.configuration = configuration;
+        }
+    }
+
+    pub fn track_order(&mut self, order_id: u64) -> Result<()> {
+        if self.order_ids.contains(&order_id) {
+            return Ok(());
+        }
+        self.order_ids.push(order_id);
+        Ok(())
+    }
+
+    // Returns whether the order was tracked, orders from before the index existed are not.
+    pub fn untrack_order(&mut self, order_id: u64) -> bool {
+        let tracked = self.order_ids.len();
+        self.order_ids.retain(|id| *id != order_id);
+        self.order_ids.len() != tracked
+    }
 }
 
 #[error_code]
@@ -729,4 +10330,80 @@ pub enum ErrorCode {
     RepaymentPeriodNotExceeded,
     #[msg("Already liquidated")]
     AlreadyLiquidated,
+    #[msg("Math operation overflowed")]
+    MathOverflow,
+    #[msg("Lender cannot fund their own order")]
+    CannotLendToSelf,
+    #[msg("Account already has the current layout")]
+    AlreadyMigrated,
+    #[msg("Loan terms are invalid")]
+    InvalidLoanTerms,
+    #[msg("Loan period is shorter than the minimum")]
+    PeriodTooShort,
+    #[msg("Loan period is longer than the maximum")]
+    PeriodTooLong,
+    #[msg("Interest is higher than the maximum rate")]
+    InterestTooHigh,
+    #[msg("Additional collateral is lower than the minimum")]
+    AdditionalCollateralTooLow,
+    #[msg("Currency is not enabled for new orders")]
+    CurrencyNotEnabled,
+    #[msg("Not a mint or token account of the SPL Token or Token-2022 program")]
+    InvalidTokenAccount,
+    #[msg("Token extension is not supported as collateral")]
+    UnsupportedTokenExtension,
+    #[msg("Bundle must hold between one and five distinct nfts")]
+    InvalidBundle,
+    #[msg("Remaining accounts do not match the bundle")]
+    BundleAccountMismatch,
+    #[msg("Collateral token is not enabled for new orders")]
+    CollateralNotEnabled,
+    #[msg("Withdrawal would take the loan above the liquidation LTV")]
+    WithdrawalExceedsLtv,
+    #[msg("Nft metadata account is invalid")]
+    InvalidMetadata,
+    #[msg("Price feed does not match the order")]
+    PriceFeedMismatch,
+    #[msg("Price feed has not been updated recently enough")]
+    StalePrice,
+    #[msg("Loan is within the liquidation LTV")]
+    LoanWithinLtv,
+    #[msg("Oracle price account is invalid or not trading")]
+    InvalidOraclePrice,
+    #[msg("Defaulted loans must be auctioned")]
+    AuctionRequired,
+    #[msg("Liquidation auctions are not enabled")]
+    AuctionNotEnabled,
+    #[msg("Auction has ended")]
+    AuctionEnded,
+    #[msg("Auction has not ended yet")]
+    AuctionNotEnded,
+    #[msg("Auction is of another kind")]
+    InvalidAuctionKind,
+    #[msg("Bid is below the minimum")]
+    BidTooLow,
+    #[msg("Batch accounts do not match the order")]
+    BatchAccountMismatch,
+    #[msg("Order kind cannot be settled in a batch")]
+    UnsupportedBatchOrder,
+    #[msg("Order does not fit the pool limits")]
+    OrderNotQualified,
+    #[msg("Pool does not hold enough liquidity")]
+    InsufficientPoolLiquidity,
+    #[msg("Withdrawal request is not at the head of the queue")]
+    WithdrawalNotNext,
+    #[msg("Carrying value can only be written down")]
+    CarryingValueIncrease,
+    #[msg("Defaulted nft is not for sale")]
+    NotForSale,
+    #[msg("Account does not hold the loan note")]
+    InvalidLoanNote,
+    #[msg("Sale price does not cover the loan")]
+    SalePriceTooLow,
+    #[msg("Loan of the listing is still active")]
+    LoanStillActive,
+    #[msg("Loan kind cannot be listed on the marketplace")]
+    UnsupportedLoanListing,
+    #[msg("Listing price is above the maximum of the buyer")]
+    ListingPriceTooHigh,
 }
\ No newline at end of file
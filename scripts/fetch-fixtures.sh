@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+# Dumps the mainnet Token Metadata program loaded as a genesis program by Anchor.toml.
+# The dump needs network access, MPL_TOKEN_METADATA_SO points to a local copy instead.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+FIXTURE=tests/fixtures/mpl_token_metadata.so
+
+if [ ! -f "$FIXTURE" ]; then
+  mkdir -p tests/fixtures
+  if [ -n "${MPL_TOKEN_METADATA_SO:-}" ]; then
+    cp "$MPL_TOKEN_METADATA_SO" "$FIXTURE"
+  elif ! solana program dump -u m metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s "$FIXTURE"; then
+    rm -f "$FIXTURE"
+    echo "Could not dump the Token Metadata program from mainnet." >&2
+    echo "Set MPL_TOKEN_METADATA_SO to a copy of the program to run the tests offline." >&2
+    exit 1
+  fi
+fi
@@ -1,12 +1,43 @@
 import { BN, Provider, utils } from "@project-serum/anchor";
 // eslint-disable-next-line node/no-extraneous-import
-import { PublicKey, LAMPORTS_PER_SOL, Keypair } from "@solana/web3.js";
+import {
+  PublicKey,
+  LAMPORTS_PER_SOL,
+  Keypair,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  SystemProgram,
+  Transaction,
+  sendAndConfirmTransaction,
+} from "@solana/web3.js";
 import * as spl from "@solana/spl-token";
 import { Account } from "@solana/spl-token";
+import {
+  PROGRAM_ID as TOKEN_METADATA_PROGRAM_ID,
+  TokenStandard,
+  createCreateInstruction,
+  createMintInstruction,
+  createVerifyCollectionInstruction,
+} from "@metaplex-foundation/mpl-token-metadata";
 
 const NFT_COLLATERIZED_LOANS_SEED: string = "config";
 const NFT_COLLATERIZED_LOANS_ST_VAULT_SEED: string = "st_vault";
 const NFT_COLLATERIZED_LOANS_NFT_VAULT_SEED: string = "nft_vault";
+const NFT_COLLATERIZED_LOANS_USER_STATS_SEED: string = "user_stats";
+const NFT_COLLATERIZED_LOANS_CURRENCY_SEED: string = "currency";
+const NFT_COLLATERIZED_LOANS_BUNDLE_SEED: string = "bundle";
+const NFT_COLLATERIZED_LOANS_COLLATERAL_SEED: string = "collateral";
+const NFT_COLLATERIZED_LOANS_PRICE_FEED_SEED: string = "price_feed";
+const NFT_COLLATERIZED_LOANS_AUCTION_SEED: string = "auction";
+const NFT_COLLATERIZED_LOANS_BID_VAULT_SEED: string = "bid_vault";
+const NFT_COLLATERIZED_LOANS_POOL_SEED: string = "pool";
+const NFT_COLLATERIZED_LOANS_POOL_VAULT_SEED: string = "pool_vault";
+const NFT_COLLATERIZED_LOANS_SHARE_MINT_SEED: string = "share_mint";
+const NFT_COLLATERIZED_LOANS_SHARE_ESCROW_SEED: string = "share_escrow";
+const NFT_COLLATERIZED_LOANS_WITHDRAWAL_SEED: string = "withdrawal";
+const NFT_COLLATERIZED_LOANS_DEFAULTED_SEED: string = "defaulted";
+const NFT_COLLATERIZED_LOANS_NOTE_SEED: string = "note";
+const NFT_COLLATERIZED_LOANS_LISTING_SEED: string = "listing";
+const NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED: string = "fee_vault";
 
 // airdrop SOL
 export const airdropSOL = async (
@@ -38,6 +69,73 @@ export const createTokenMint = async (
   );
 };
 
+// create Token-2022 mint with a single extension, the payer holds every authority
+export const createToken2022Mint = async (
+  provider: Provider,
+  payer: Keypair,
+  decimals: number,
+  extension: spl.ExtensionType,
+  feeBasisPoints = 0,
+  maxFee = BigInt(0)
+): Promise<PublicKey> => {
+  const mint = Keypair.generate();
+  const space = spl.getMintLen([extension]);
+  const lamports =
+    await provider.connection.getMinimumBalanceForRentExemption(space);
+
+  let extensionIx;
+  switch (extension) {
+    case spl.ExtensionType.TransferFeeConfig:
+      extensionIx = spl.createInitializeTransferFeeConfigInstruction(
+        mint.publicKey,
+        payer.publicKey,
+        payer.publicKey,
+        feeBasisPoints,
+        maxFee,
+        spl.TOKEN_2022_PROGRAM_ID
+      );
+      break;
+    case spl.ExtensionType.NonTransferable:
+      extensionIx = spl.createInitializeNonTransferableMintInstruction(
+        mint.publicKey,
+        spl.TOKEN_2022_PROGRAM_ID
+      );
+      break;
+    case spl.ExtensionType.PermanentDelegate:
+      extensionIx = spl.createInitializePermanentDelegateInstruction(
+        mint.publicKey,
+        payer.publicKey,
+        spl.TOKEN_2022_PROGRAM_ID
+      );
+      break;
+    default:
+      throw new Error(`unsupported extension ${extension}`);
+  }
+
+  const transaction = new Transaction().add(
+    SystemProgram.createAccount({
+      fromPubkey: payer.publicKey,
+      newAccountPubkey: mint.publicKey,
+      space,
+      lamports,
+      programId: spl.TOKEN_2022_PROGRAM_ID,
+    }),
+    extensionIx,
+    spl.createInitializeMintInstruction(
+      mint.publicKey,
+      decimals,
+      payer.publicKey,
+      null,
+      spl.TOKEN_2022_PROGRAM_ID
+    )
+  );
+  await sendAndConfirmTransaction(provider.connection, transaction, [
+    payer,
+    mint,
+  ]);
+  return mint.publicKey;
+};
+
 // mint Token to account
 export const mintTokenTo = async (
   provider: Provider,
@@ -45,13 +143,18 @@ export const mintTokenTo = async (
   mint: PublicKey,
   to: PublicKey,
   authority: PublicKey,
-  amount: number
+  amount: number,
+  programId = spl.TOKEN_PROGRAM_ID
 ): Promise<Account> => {
   const tokenAccount = await spl.getOrCreateAssociatedTokenAccount(
     provider.connection,
     payer,
     mint,
-    to
+    to,
+    false,
+    undefined,
+    undefined,
+    programId
   );
   await spl.mintTo(
     provider.connection,
@@ -59,7 +162,10 @@ export const mintTokenTo = async (
     mint,
     tokenAccount.address,
     authority,
-    amount
+    amount,
+    [],
+    undefined,
+    programId
   );
   return tokenAccount;
 };
@@ -98,13 +204,189 @@ export const createNFT = async (
   return [nftMint, userNFTAccount];
 };
 
+// create NFT with token metadata and master edition to account
+export const createMetaplexNFT = async (
+  provider: Provider,
+  payer: Keypair,
+  to: PublicKey,
+  tokenStandard: TokenStandard,
+  collection: PublicKey | null = null
+): Promise<[PublicKey, Account]> => {
+  const nftMint = Keypair.generate();
+  const [metadata] = await deriveMetadataPDA(nftMint.publicKey);
+  const [masterEdition] = await deriveEditionPDA(nftMint.publicKey);
+
+  // create mint, metadata and master edition
+  const createIx = createCreateInstruction(
+    {
+      metadata,
+      masterEdition,
+      mint: nftMint.publicKey,
+      authority: payer.publicKey,
+      payer: payer.publicKey,
+      updateAuthority: payer.publicKey,
+      sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      splTokenProgram: spl.TOKEN_PROGRAM_ID,
+    },
+    {
+      createArgs: {
+        __kind: "V1",
+        assetData: {
+          name: "Loan NFT",
+          symbol: "LNFT",
+          uri: "",
+          sellerFeeBasisPoints: 500,
+          creators: null,
+          primarySaleHappened: false,
+          isMutable: true,
+          tokenStandard,
+          collection: collection ? { verified: false, key: collection } : null,
+          uses: null,
+          collectionDetails: null,
+          ruleSet: null,
+        },
+        decimals: 0,
+        printSupply: { __kind: "Zero" },
+      },
+    }
+  );
+  // the mint is created by the instruction
+  createIx.keys = createIx.keys.map((key) =>
+    key.pubkey.equals(nftMint.publicKey) ? { ...key, isSigner: true } : key
+  );
+  await sendAndConfirmTransaction(
+    provider.connection,
+    new Transaction().add(createIx),
+    [payer, nftMint]
+  );
+
+  // mint nft to user
+  const userNFTAccount = await spl.getAssociatedTokenAddress(
+    nftMint.publicKey,
+    to
+  );
+  // only programmable NFTs have token records
+  const tokenRecord =
+    tokenStandard === TokenStandard.ProgrammableNonFungible
+      ? (await deriveTokenRecordPDA(nftMint.publicKey, userNFTAccount))[0]
+      : undefined;
+  const mintIx = createMintInstruction(
+    {
+      token: userNFTAccount,
+      tokenOwner: to,
+      metadata,
+      masterEdition,
+      tokenRecord,
+      mint: nftMint.publicKey,
+      authority: payer.publicKey,
+      payer: payer.publicKey,
+      sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      splTokenProgram: spl.TOKEN_PROGRAM_ID,
+      splAtaProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+    },
+    { mintArgs: { __kind: "V1", amount: 1, authorizationData: null } }
+  );
+  await sendAndConfirmTransaction(
+    provider.connection,
+    new Transaction().add(mintIx),
+    [payer]
+  );
+
+  return [
+    nftMint.publicKey,
+    await spl.getAccount(provider.connection, userNFTAccount),
+  ];
+};
+
+// verify an nft as member of a collection, signed by the collection update authority
+export const verifyCollection = async (
+  provider: Provider,
+  authority: Keypair,
+  mint: PublicKey,
+  collectionMint: PublicKey
+): Promise<void> => {
+  const [metadata] = await deriveMetadataPDA(mint);
+  const [collection] = await deriveMetadataPDA(collectionMint);
+  const [collectionMasterEditionAccount] = await deriveEditionPDA(
+    collectionMint
+  );
+  await sendAndConfirmTransaction(
+    provider.connection,
+    new Transaction().add(
+      createVerifyCollectionInstruction({
+        metadata,
+        collectionAuthority: authority.publicKey,
+        payer: authority.publicKey,
+        collectionMint,
+        collection,
+        collectionMasterEditionAccount,
+      })
+    ),
+    [authority]
+  );
+};
+
+// token metadata pdas
+export const deriveMetadataPDA = async (
+  mint: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      utils.bytes.utf8.encode("metadata"),
+      TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+      mint.toBuffer(),
+    ],
+    TOKEN_METADATA_PROGRAM_ID
+  );
+};
+
+export const deriveEditionPDA = async (
+  mint: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      utils.bytes.utf8.encode("metadata"),
+      TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+      mint.toBuffer(),
+      utils.bytes.utf8.encode("edition"),
+    ],
+    TOKEN_METADATA_PROGRAM_ID
+  );
+};
+
+export const deriveTokenRecordPDA = async (
+  mint: PublicKey,
+  token: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      utils.bytes.utf8.encode("metadata"),
+      TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+      mint.toBuffer(),
+      utils.bytes.utf8.encode("token_record"),
+      token.toBuffer(),
+    ],
+    TOKEN_METADATA_PROGRAM_ID
+  );
+};
+
 // stable coin account pda
 export const deriveSCAccountPDA = async (
+  configuration: PublicKey,
   scMint: PublicKey,
   programId: PublicKey
 ): Promise<[PublicKey, number]> => {
+  // the vault of the configuration mint keeps its original seeds
+  const [ownConfiguration] = await deriveConfigurationAccountPDA(
+    scMint,
+    programId
+  );
+  const prefix = ownConfiguration.equals(configuration)
+    ? []
+    : [configuration.toBuffer()];
   return await PublicKey.findProgramAddress(
     [
+      ...prefix,
       scMint.toBuffer(),
       Buffer.from(
         utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_ST_VAULT_SEED)
@@ -114,6 +396,34 @@ export const deriveSCAccountPDA = async (
   );
 };
 
+// protocol fee vault pda of a currency
+export const deriveFeeVaultPDA = async (
+  configuration: PublicKey,
+  scMint: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      configuration.toBuffer(),
+      scMint.toBuffer(),
+      Buffer.from(
+        utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_FEE_VAULT_SEED)
+      ),
+    ],
+    programId
+  );
+};
+
+// program data account of an upgradeable program, holding its upgrade authority
+export const deriveProgramDataPDA = async (
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [programId.toBuffer()],
+    new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
+};
+
 // configuration account pda
 export const deriveConfigurationAccountPDA = async (
   scMint: PublicKey,
@@ -128,6 +438,40 @@ export const deriveConfigurationAccountPDA = async (
   );
 };
 
+// approved currency pda
+export const deriveCurrencyPDA = async (
+  configuration: PublicKey,
+  scMint: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      configuration.toBuffer(),
+      scMint.toBuffer(),
+      Buffer.from(utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_CURRENCY_SEED)),
+    ],
+    programId
+  );
+};
+
+// approved collateral token pda
+export const deriveCollateralTokenPDA = async (
+  configuration: PublicKey,
+  mint: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      configuration.toBuffer(),
+      mint.toBuffer(),
+      Buffer.from(
+        utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_COLLATERAL_SEED)
+      ),
+    ],
+    programId
+  );
+};
+
 // NFT account pda
 export const deriveNFTAccountPDA = async (
   nftMint: PublicKey,
@@ -159,6 +503,195 @@ export const deriveOrderAccountPDA = async (
   );
 };
 
+// user stats pda
+export const deriveUserStatsPDA = async (
+  user: PublicKey,
+  configuration: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      user.toBuffer(),
+      configuration.toBuffer(),
+      Buffer.from(utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_USER_STATS_SEED)),
+    ],
+    programId
+  );
+};
+
+// bundle pda
+export const deriveBundlePDA = async (
+  order: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      order.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_BUNDLE_SEED),
+    ],
+    programId
+  );
+};
+
+// price feed pda
+export const derivePriceFeedPDA = async (
+  configuration: PublicKey,
+  collection: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      configuration.toBuffer(),
+      collection.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_PRICE_FEED_SEED),
+    ],
+    programId
+  );
+};
+
+// auction pda
+export const deriveAuctionPDA = async (
+  order: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      order.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_AUCTION_SEED),
+    ],
+    programId
+  );
+};
+
+// english auction bid escrow pda
+export const deriveBidVaultPDA = async (
+  auction: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      auction.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_BID_VAULT_SEED),
+    ],
+    programId
+  );
+};
+
+// lending pool pda
+export const derivePoolPDA = async (
+  configuration: PublicKey,
+  collection: PublicKey,
+  stablecoinMint: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      configuration.toBuffer(),
+      collection.toBuffer(),
+      stablecoinMint.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_POOL_SEED),
+    ],
+    programId
+  );
+};
+
+// pool liquidity vault pda, or the custody of a defaulted nft when given its mint
+export const derivePoolVaultPDA = async (
+  pool: PublicKey,
+  programId: PublicKey,
+  nftMint?: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      pool.toBuffer(),
+      ...(nftMint ? [nftMint.toBuffer()] : []),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_POOL_VAULT_SEED),
+    ],
+    programId
+  );
+};
+
+// pool LP share mint pda
+export const deriveShareMintPDA = async (
+  pool: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      pool.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_SHARE_MINT_SEED),
+    ],
+    programId
+  );
+};
+
+// escrow of the shares in the withdrawal queue pda
+export const deriveShareEscrowPDA = async (
+  pool: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      pool.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_SHARE_ESCROW_SEED),
+    ],
+    programId
+  );
+};
+
+// withdrawal request pda
+export const deriveWithdrawalPDA = async (
+  pool: PublicKey,
+  requestId: BN,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      pool.toBuffer(),
+      Buffer.from(utils.bytes.utf8.encode(requestId.toString())),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_WITHDRAWAL_SEED),
+    ],
+    programId
+  );
+};
+
+// defaulted nft in pool custody pda
+export const deriveDefaultedPDA = async (
+  pool: PublicKey,
+  nftMint: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [
+      pool.toBuffer(),
+      nftMint.toBuffer(),
+      utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_DEFAULTED_SEED),
+    ],
+    programId
+  );
+};
+
+// loan note mint pda
+export const deriveNoteMintPDA = async (
+  order: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [order.toBuffer(), utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_NOTE_SEED)],
+    programId
+  );
+};
+
+export const deriveLoanListingPDA = async (
+  order: PublicKey,
+  programId: PublicKey
+): Promise<[PublicKey, number]> => {
+  return await PublicKey.findProgramAddress(
+    [order.toBuffer(), utils.bytes.utf8.encode(NFT_COLLATERIZED_LOANS_LISTING_SEED)],
+    programId
+  );
+};
+
 export const sleep = (ms) => {
   return new Promise((resolve) => setTimeout(resolve, ms));
 };
@@ -1,24 +1,65 @@
 import * as anchor from "@project-serum/anchor";
+import * as fs from "fs";
 import { BN, Program } from "@project-serum/anchor";
 import { NftLoans } from "../target/types/nft_loans";
 import {
   Account,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
+  TOKEN_2022_PROGRAM_ID,
   TOKEN_PROGRAM_ID,
   createAssociatedTokenAccountInstruction,
+  getAccount,
   getAssociatedTokenAddress,
+  getMint,
+  getOrCreateAssociatedTokenAccount,
+  transfer,
 } from "@solana/spl-token";
+import {
+  PROGRAM_ID as TOKEN_METADATA_PROGRAM_ID,
+  TokenStandard,
+} from "@metaplex-foundation/mpl-token-metadata";
 import {
   airdropSOL,
+  createMetaplexNFT,
   createNFT,
+  createToken2022Mint,
   createTokenMint,
+  deriveAuctionPDA,
+  deriveBidVaultPDA,
+  deriveBundlePDA,
+  deriveCollateralTokenPDA,
   deriveConfigurationAccountPDA,
+  deriveCurrencyPDA,
+  deriveDefaultedPDA,
+  deriveEditionPDA,
+  deriveFeeVaultPDA,
+  deriveLoanListingPDA,
+  deriveMetadataPDA,
   deriveNFTAccountPDA,
+  deriveNoteMintPDA,
   deriveOrderAccountPDA,
+  derivePoolPDA,
+  derivePoolVaultPDA,
+  derivePriceFeedPDA,
+  deriveProgramDataPDA,
   deriveSCAccountPDA,
+  deriveShareEscrowPDA,
+  deriveShareMintPDA,
+  deriveTokenRecordPDA,
+  deriveUserStatsPDA,
+  deriveWithdrawalPDA,
   mintTokenTo,
   sleep,
+  verifyCollection,
 } from "./helpers";
-import { LAMPORTS_PER_SOL, PublicKey, Keypair } from "@solana/web3.js";
+import {
+  ComputeBudgetProgram,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  Keypair,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+} from "@solana/web3.js";
 import { expect } from "chai";
 
 const FEE_PT = 10;
@@ -49,6 +90,41 @@ describe("nft-loans", () => {
   let nftMint: PublicKey;
   let aliceNftAccount: Account;
 
+  // position indexes
+  let aliceStats: PublicKey;
+  let bobStats: PublicKey;
+
+  // loan note account of a holder
+  const noteVaultOf = async (order: PublicKey, holder: PublicKey) => {
+    const [noteMint] = await deriveNoteMintPDA(order, program.programId);
+    return await getAssociatedTokenAddress(noteMint, holder);
+  };
+
+  // marketplace listing of a loan, closed on payback and liquidation
+  const listingOf = async (order: PublicKey) =>
+    (await deriveLoanListingPDA(order, program.programId))[0];
+
+  // protocol fee vault of a currency
+  const feeVaultOf = async (configuration: PublicKey, mint: PublicKey) => {
+    const [feeVault] = await deriveFeeVaultPDA(
+      configuration,
+      mint,
+      program.programId
+    );
+    return feeVault;
+  };
+
+  // accounts give_loan mints the loan note with
+  const loanNoteAccounts = async (order: PublicKey, lender: PublicKey) => {
+    const [noteMint] = await deriveNoteMintPDA(order, program.programId);
+    return {
+      noteMint,
+      lenderNoteVault: await getAssociatedTokenAddress(noteMint, lender),
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      rent: SYSVAR_RENT_PUBKEY,
+    };
+  };
+
   before(async () => {
     // airdrop
     await airdropSOL(program.provider, owner.publicKey, 20);
@@ -104,11 +180,17 @@ describe("nft-loans", () => {
 
   it("Is initialized!", async () => {
     // get pda for stable coin account of program
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
     const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
-    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
@@ -119,6 +201,8 @@ describe("nft-loans", () => {
         signer: owner.publicKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+        currency: currencyPubKey,
         configuration: configurationPubKey,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -137,18 +221,88 @@ describe("nft-loans", () => {
     expect(configuration.stablecoinVault.toBase58()).to.eq(
       programSCVault.toBase58()
     );
+    // the vault of the configuration mint keeps the seeds it was deployed with
+    const [deployedVault] = await PublicKey.findProgramAddress(
+      [stableCoinMint.toBuffer(), Buffer.from("st_vault")],
+      program.programId
+    );
+    expect(programSCVault.toBase58()).to.eq(deployedVault.toBase58());
     expect(configuration.orderId.toNumber()).to.eq(0);
     expect(configuration.totalAdditionalCollateral.toNumber()).to.eq(0);
     expect(configuration.feePt).to.eq(FEE_PT);
+    expect(configuration.authority.toBase58()).to.eq(
+      owner.publicKey.toBase58()
+    );
+    expect(configuration.activeLoans.toNumber()).to.eq(0);
+    expect(configuration.totalPrincipalOutstanding.toNumber()).to.eq(0);
+    expect(configuration.totalVolumeLent.toNumber()).to.eq(0);
+    expect(configuration.totalInterestPaid.toNumber()).to.eq(0);
+    expect(configuration.totalLiquidations.toNumber()).to.eq(0);
+
+    // check base currency
+    const currency = await program.account.currency.fetch(currencyPubKey);
+    expect(currency.stablecoinMint.toBase58()).to.eq(stableCoinMint.toBase58());
+    expect(currency.stablecoinVault.toBase58()).to.eq(
+      programSCVault.toBase58()
+    );
+    expect(currency.enabled).to.eq(true);
+    expect(currency.totalAdditionalCollateral.toNumber()).to.eq(0);
+    expect(currency.totalPrincipalOutstanding.toNumber()).to.eq(0);
+    expect(currency.totalVolumeLent.toNumber()).to.eq(0);
+    expect(currency.totalInterestPaid.toNumber()).to.eq(0);
+  });
+
+  it("Initialize user stats!", async () => {
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    [aliceStats] = await deriveUserStatsPDA(
+      alice.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    [bobStats] = await deriveUserStatsPDA(
+      bob.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+
+    for (const [user, userStats] of [
+      [alice, aliceStats],
+      [bob, bobStats],
+    ] as [Keypair, PublicKey][]) {
+      await program.methods
+        .initializeUserStats()
+        .accounts({
+          signer: user.publicKey,
+          configuration: configurationPubKey,
+          userStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    const stats = await program.account.userStats.fetch(aliceStats);
+    expect(stats.user.toBase58()).to.eq(alice.publicKey.toBase58());
+    expect(stats.openOrders.toNumber()).to.eq(0);
+    expect(stats.orderIds.length).to.eq(0);
   });
 
   it("Create order!", async () => {
     // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
     const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
-    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
@@ -180,11 +334,13 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         userStablecoinVault: aliceSCAccount.address,
         nftMint,
         nftVault: programNFTVault,
         userNftVault: aliceNftAccount.address,
         order: orderPubKey,
+        borrowerStats: aliceStats,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
@@ -211,15 +367,28 @@ describe("nft-loans", () => {
       configurationPubKey
     );
     expect(configuration.orderId.toNumber()).to.eq(1);
+
+    // check borrower position index
+    const aliceStatsAccount = await program.account.userStats.fetch(aliceStats);
+    expect(aliceStatsAccount.openOrders.toNumber()).to.eq(1);
+    expect(aliceStatsAccount.orderIds.map((id) => id.toNumber())).to.deep.eq([
+      0,
+    ]);
   });
 
   it("Give loan!", async () => {
     // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
     const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
-    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
@@ -239,11 +408,15 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         lenderStablecoinVault: bobSCAccount.address,
         borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
         order: orderPubKey,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
       })
       .signers([bob])
       .rpc();
@@ -253,15 +426,41 @@ describe("nft-loans", () => {
     expect(order.lender.toBase58()).to.eq(bob.publicKey.toBase58());
     expect(order.loanStartTime.toNumber()).to.not.eq(0);
     expect(order.orderStatus).to.eq(false);
+
+    // check position indexes
+    const aliceStatsAccount = await program.account.userStats.fetch(aliceStats);
+    expect(aliceStatsAccount.openOrders.toNumber()).to.eq(0);
+    expect(aliceStatsAccount.activeBorrows.toNumber()).to.eq(1);
+    expect(aliceStatsAccount.totalBorrowed.toNumber()).to.eq(100);
+    const bobStatsAccount = await program.account.userStats.fetch(bobStats);
+    expect(bobStatsAccount.activeLoans.toNumber()).to.eq(1);
+    expect(bobStatsAccount.totalLent.toNumber()).to.eq(100);
+    expect(bobStatsAccount.orderIds.map((id) => id.toNumber())).to.deep.eq([
+      0,
+    ]);
+
+    // check protocol metrics
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.activeLoans.toNumber()).to.eq(1);
+    expect(configuration.totalPrincipalOutstanding.toNumber()).to.eq(100);
+    expect(configuration.totalVolumeLent.toNumber()).to.eq(100);
   });
 
   it("Pay back!", async () => {
     // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
     const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
-    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
@@ -285,12 +484,18 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        payee: bob.publicKey,
+        listing: await listingOf(orderPubKey),
         userStablecoinVault: aliceSCAccount.address,
         nftMint,
         nftVault: programNFTVault,
         userNftVault: aliceNftAccount.address,
         order: orderPubKey,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
@@ -305,6 +510,27 @@ describe("nft-loans", () => {
       isExisting = false;
     }
     expect(isExisting).to.eq(false);
+
+    // check position indexes
+    const aliceStatsAccount = await program.account.userStats.fetch(aliceStats);
+    expect(aliceStatsAccount.activeBorrows.toNumber()).to.eq(0);
+    expect(aliceStatsAccount.orderIds.length).to.eq(0);
+    const bobStatsAccount = await program.account.userStats.fetch(bobStats);
+    expect(bobStatsAccount.activeLoans.toNumber()).to.eq(0);
+    expect(bobStatsAccount.orderIds.length).to.eq(0);
+
+    // check protocol metrics
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.activeLoans.toNumber()).to.eq(0);
+    expect(configuration.totalPrincipalOutstanding.toNumber()).to.eq(0);
+    expect(configuration.totalVolumeLent.toNumber()).to.eq(100);
+    expect(configuration.totalInterestPaid.toNumber()).to.eq(10);
+    const bobBalance = await program.provider.connection.getTokenAccountBalance(
+      bobSCAccount.address
+    );
+    expect(bobBalance.value.amount).to.eq("1010");
   });
 
   it("Cancel order!", async () => {
@@ -316,11 +542,17 @@ describe("nft-loans", () => {
     );
 
     // pda
-    const [programSCVault, programSCNonce] = await deriveSCAccountPDA(
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
       stableCoinMint,
       program.programId
     );
-    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
@@ -354,11 +586,13 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         userStablecoinVault: aliceSCAccount.address,
         nftMint,
         nftVault: programNFTVault,
         userNftVault: aliceNftAccount.address,
         order: orderPubKey,
+        borrowerStats: aliceStats,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
@@ -374,11 +608,13 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         userStablecoinVault: aliceSCAccount.address,
         nftMint,
         nftVault: programNFTVault,
         userNftVault: aliceNftAccount.address,
         order: orderPubKey,
+        borrowerStats: aliceStats,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
@@ -411,11 +647,17 @@ describe("nft-loans", () => {
     );
 
     // pda
-    const [programSCVault, programSCNonce] = await deriveSCAccountPDA(
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
       stableCoinMint,
       program.programId
     );
-    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
       stableCoinMint,
       program.programId
     );
@@ -449,11 +691,13 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         userStablecoinVault: aliceSCAccount.address,
         nftMint,
         nftVault: programNFTVault,
         userNftVault: aliceNftAccount.address,
         order: orderPubKey,
+        borrowerStats: aliceStats,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
         rent: SYSVAR_RENT_PUBKEY,
@@ -469,26 +713,24 @@ describe("nft-loans", () => {
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         lenderStablecoinVault: bobSCAccount.address,
         borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
         order: orderPubKey,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
       })
       .signers([bob])
       .rpc();
 
-    // create bob nft token account address
+    // bob nft token account address, created by the instruction
     const bobNftTokenAccountPubKey = await getAssociatedTokenAddress(
       nftMint,
       bob.publicKey
     );
-    const instruction = createAssociatedTokenAccountInstruction(
-      bob.publicKey,
-      bobNftTokenAccountPubKey,
-      bob.publicKey,
-      nftMint
-    );
 
     await sleep(5000);
 
@@ -497,18 +739,28 @@ describe("nft-loans", () => {
       .liquidate(order_id)
       .accounts({
         signer: bob.publicKey,
+        lender: bob.publicKey,
         configuration: configurationPubKey,
         stablecoinMint: stableCoinMint,
         stablecoinVault: programSCVault,
+        currency: currencyPubKey,
         lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        listing: await listingOf(orderPubKey),
+        keeperStablecoinVault: bobSCAccount.address,
+        feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
         nftMint,
         nftVault: programNFTVault,
+        payee: bob.publicKey,
         userNftVault: bobNftTokenAccountPubKey,
         order: orderPubKey,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
         systemProgram: SYSTEM_PROGRAM_ID,
         tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
       })
-      .preInstructions([instruction])
       .signers([bob])
       .rpc();
 
@@ -517,7 +769,10 @@ describe("nft-loans", () => {
       configurationPubKey
     );
     expect(configuration.orderId.toNumber()).to.eq(1 + order_id.toNumber());
+    expect(configuration.activeLoans.toNumber()).to.eq(0);
+    expect(configuration.totalLiquidations.toNumber()).to.eq(1);
     expect(configuration.totalAdditionalCollateral.toNumber()).to.eq(0);
+    expect(configuration.totalPrincipalOutstanding.toNumber()).to.eq(0);
 
     // check order
     let isExisting = true;
@@ -528,4 +783,6195 @@ describe("nft-loans", () => {
     }
     expect(isExisting).to.eq(false);
   });
+
+  it("Migrates and settles loans of the deployed layout!", async () => {
+    // accounts in the layout of the deployed program, loaded from tests/fixtures
+    const fixture = (name: string) =>
+      JSON.parse(fs.readFileSync(`tests/fixtures/${name}.json`, "utf8"));
+    const keypairs = fixture("legacy_keypairs");
+    // upgrade authority of the program data fixture and mint authority of the stable coin
+    const authority = Keypair.fromSecretKey(Uint8Array.from(keypairs.authority));
+    const borrower = Keypair.fromSecretKey(Uint8Array.from(keypairs.borrower));
+    const lender = Keypair.fromSecretKey(Uint8Array.from(keypairs.lender));
+    const legacyMint = new PublicKey(fixture("legacy_stablecoin_mint").pubkey);
+    const legacyNftMints = [0, 1].map(
+      (i) => new PublicKey(fixture(`legacy_nft_mint_${i}`).pubkey)
+    );
+
+    await airdropSOL(program.provider, authority.publicKey, 20);
+    await airdropSOL(program.provider, borrower.publicKey, 20);
+    await airdropSOL(program.provider, lender.publicKey, 20);
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      legacyMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      legacyMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      legacyMint,
+      program.programId
+    );
+    const [programData] = await deriveProgramDataPDA(program.programId);
+    const feeVault = await feeVaultOf(configurationPubKey, legacyMint);
+
+    await program.methods
+      .migrateConfiguration()
+      .accounts({
+        signer: authority.publicKey,
+        programData,
+        stablecoinMint: legacyMint,
+        configuration: configurationPubKey,
+        currency: currencyPubKey,
+        feeVault,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([authority])
+      .rpc();
+
+    let configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.stablecoinVault.toBase58()).to.eq(
+      programSCVault.toBase58()
+    );
+    expect(configuration.orderId.toNumber()).to.eq(2);
+    expect(configuration.totalAdditionalCollateral.toNumber()).to.eq(20);
+    expect(configuration.feePt).to.eq(10);
+    expect(configuration.authority.toBase58()).to.eq(
+      authority.publicKey.toBase58()
+    );
+    expect(configuration.activeLoans.toNumber()).to.eq(0);
+
+    // both orders were funded before the metrics existed
+    const orderPubKeys: PublicKey[] = [];
+    for (const orderId of [new BN(0), new BN(1)]) {
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+      await program.methods
+        .migrateOrder(orderId)
+        .accounts({
+          signer: authority.publicKey,
+          configuration: configurationPubKey,
+          currency: currencyPubKey,
+          order: orderPubKey,
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+      const order = await program.account.order.fetch(orderPubKey);
+      expect(order.orderId.toNumber()).to.eq(orderId.toNumber());
+      expect(order.stablecoinMint.toBase58()).to.eq(legacyMint.toBase58());
+      expect(order.lender.toBase58()).to.eq(lender.publicKey.toBase58());
+      orderPubKeys.push(orderPubKey);
+    }
+
+    // an order cannot be migrated twice
+    let error;
+    try {
+      await program.methods
+        .migrateOrder(new BN(0))
+        .accounts({
+          signer: authority.publicKey,
+          configuration: configurationPubKey,
+          currency: currencyPubKey,
+          order: orderPubKeys[0],
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("AlreadyMigrated");
+
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.activeLoans.toNumber()).to.eq(2);
+    expect(configuration.totalPrincipalOutstanding.toNumber()).to.eq(200);
+
+    // the legacy orders are in nobody's position index
+    const [borrowerStats] = await deriveUserStatsPDA(
+      borrower.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    const [lenderStats] = await deriveUserStatsPDA(
+      lender.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    for (const [user, userStats] of [
+      [borrower, borrowerStats],
+      [lender, lenderStats],
+    ] as [Keypair, PublicKey][]) {
+      await program.methods
+        .initializeUserStats()
+        .accounts({
+          signer: user.publicKey,
+          configuration: configurationPubKey,
+          userStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    const borrowerSCAccount = await mintTokenTo(
+      program.provider,
+      authority,
+      legacyMint,
+      borrower.publicKey,
+      authority.publicKey,
+      110
+    );
+    const lenderSCAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      lender,
+      legacyMint,
+      lender.publicKey
+    );
+    const borrowerNftAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      borrower,
+      legacyNftMints[0],
+      borrower.publicKey
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      legacyNftMints[0],
+      program.programId
+    );
+
+    // the first loan is still running
+    await program.methods
+      .payback(new BN(0))
+      .accounts({
+        signer: borrower.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: legacyMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: lenderSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKeys[0], lender.publicKey),
+        payee: lender.publicKey,
+        listing: await listingOf(orderPubKeys[0]),
+        userStablecoinVault: borrowerSCAccount.address,
+        nftMint: legacyNftMints[0],
+        nftVault: programNFTVault,
+        userNftVault: borrowerNftAccount.address,
+        order: orderPubKeys[0],
+        borrowerStats,
+        lenderStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([borrower])
+      .rpc();
+
+    let balance = await program.provider.connection.getTokenAccountBalance(
+      borrowerNftAccount.address
+    );
+    expect(balance.value.amount).to.eq("1");
+    // principal and interest paid, additional collateral back
+    balance = await program.provider.connection.getTokenAccountBalance(
+      lenderSCAccount.address
+    );
+    expect(balance.value.amount).to.eq("110");
+    balance = await program.provider.connection.getTokenAccountBalance(
+      borrowerSCAccount.address
+    );
+    expect(balance.value.amount).to.eq("10");
+
+    // the second one is long overdue
+    const lenderNftAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      lender,
+      legacyNftMints[1],
+      lender.publicKey
+    );
+    const [overdueNFTVault] = await deriveNFTAccountPDA(
+      legacyNftMints[1],
+      program.programId
+    );
+    await program.methods
+      .liquidate(new BN(1))
+      .accounts({
+        signer: lender.publicKey,
+        lender: lender.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: legacyMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: lenderSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKeys[1], lender.publicKey),
+        listing: await listingOf(orderPubKeys[1]),
+        keeperStablecoinVault: lenderSCAccount.address,
+        feeVault,
+        nftMint: legacyNftMints[1],
+        nftVault: overdueNFTVault,
+        payee: lender.publicKey,
+        userNftVault: lenderNftAccount.address,
+        order: orderPubKeys[1],
+        borrowerStats,
+        lenderStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([lender])
+      .rpc();
+
+    balance = await program.provider.connection.getTokenAccountBalance(
+      lenderNftAccount.address
+    );
+    expect(balance.value.amount).to.eq("1");
+    balance = await program.provider.connection.getTokenAccountBalance(
+      lenderSCAccount.address
+    );
+    expect(balance.value.amount).to.eq("120");
+
+    // both orders are closed and the counts never went below zero
+    for (const orderPubKey of orderPubKeys) {
+      expect(
+        await program.provider.connection.getAccountInfo(orderPubKey)
+      ).to.eq(null);
+    }
+    const borrowerStatsAccount = await program.account.userStats.fetch(
+      borrowerStats
+    );
+    expect(borrowerStatsAccount.activeBorrows.toNumber()).to.eq(0);
+    const lenderStatsAccount = await program.account.userStats.fetch(
+      lenderStats
+    );
+    expect(lenderStatsAccount.activeLoans.toNumber()).to.eq(0);
+
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.activeLoans.toNumber()).to.eq(0);
+    expect(configuration.totalPrincipalOutstanding.toNumber()).to.eq(0);
+    expect(configuration.totalInterestPaid.toNumber()).to.eq(10);
+    expect(configuration.totalLiquidations.toNumber()).to.eq(1);
+    expect(configuration.totalAdditionalCollateral.toNumber()).to.eq(0);
+  });
+
+  it("Rejects overflowing loan terms!", async () => {
+    // create NFT
+    [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+
+    // order pda
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      configuration.orderId,
+      program.programId
+    );
+
+    const U64_MAX: BN = new BN("18446744073709551615");
+    // [requestAmount, interest, period]
+    const terms: BN[][] = [
+      [U64_MAX, new BN(1), new BN(3 * 86400)],
+      [new BN(100), U64_MAX, new BN(3 * 86400)],
+      [U64_MAX, U64_MAX, new BN(3 * 86400)],
+      [new BN(100), new BN(10), U64_MAX],
+    ];
+
+    for (const [requestAmount, interest, period] of terms) {
+      let error;
+      try {
+        await program.methods
+          .createOrder(requestAmount, interest, period, new BN(0))
+          .accounts({
+            signer: alice.publicKey,
+            configuration: configurationPubKey,
+            stablecoinMint: stableCoinMint,
+            stablecoinVault: programSCVault,
+            currency: currencyPubKey,
+            userStablecoinVault: aliceSCAccount.address,
+            nftMint,
+            nftVault: programNFTVault,
+            userNftVault: aliceNftAccount.address,
+            order: orderPubKey,
+            borrowerStats: aliceStats,
+            systemProgram: SYSTEM_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .signers([alice])
+          .rpc();
+      } catch (e) {
+        error = e;
+      }
+      expect(error.error.errorCode.code).to.eq("MathOverflow");
+    }
+
+    // nothing was created
+    const aliceNftBalance =
+      await program.provider.connection.getTokenAccountBalance(
+        aliceNftAccount.address
+      );
+    expect(aliceNftBalance.value.amount).to.eq("1");
+  });
+
+  it("Validates loan term bounds!", async () => {
+    // create NFT
+    [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+
+    // order pda
+    let configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      configuration.orderId,
+      program.programId
+    );
+
+    // only the authority can update the bounds
+    let error;
+    try {
+      await program.methods
+        .updateLoanTerms(new BN(60), new BN(30 * 86400), new BN(2000), new BN(5))
+        .accounts({
+          authority: alice.publicKey,
+          configuration: configurationPubKey,
+        })
+        .signers([alice])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("ConstraintHasOne");
+
+    // 1 minute to 30 days, 20% interest, 5 additional collateral
+    await program.methods
+      .updateLoanTerms(new BN(60), new BN(30 * 86400), new BN(2000), new BN(5))
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+      })
+      .signers([owner])
+      .rpc();
+
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.authority.toBase58()).to.eq(
+      owner.publicKey.toBase58()
+    );
+    expect(configuration.minPeriod.toNumber()).to.eq(60);
+    expect(configuration.maxPeriod.toNumber()).to.eq(30 * 86400);
+    expect(configuration.maxInterestBps.toNumber()).to.eq(2000);
+    expect(configuration.minAdditionalCollateral.toNumber()).to.eq(5);
+
+    // [requestAmount, interest, period, additionalCollateral, error]
+    const terms: [BN, BN, BN, BN, string][] = [
+      [new BN(100), new BN(10), new BN(0), new BN(10), "PeriodTooShort"],
+      [new BN(100), new BN(10), new BN(31 * 86400), new BN(10), "PeriodTooLong"],
+      [new BN(100), new BN(21), new BN(86400), new BN(10), "InterestTooHigh"],
+      [
+        new BN(100),
+        new BN(10),
+        new BN(86400),
+        new BN(4),
+        "AdditionalCollateralTooLow",
+      ],
+    ];
+
+    for (const [
+      requestAmount,
+      interest,
+      period,
+      additionalCollateral,
+      code,
+    ] of terms) {
+      error = undefined;
+      try {
+        await program.methods
+          .createOrder(requestAmount, interest, period, additionalCollateral)
+          .accounts({
+            signer: alice.publicKey,
+            configuration: configurationPubKey,
+            stablecoinMint: stableCoinMint,
+            stablecoinVault: programSCVault,
+            currency: currencyPubKey,
+            userStablecoinVault: aliceSCAccount.address,
+            nftMint,
+            nftVault: programNFTVault,
+            userNftVault: aliceNftAccount.address,
+            order: orderPubKey,
+            borrowerStats: aliceStats,
+            systemProgram: SYSTEM_PROGRAM_ID,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .signers([alice])
+          .rpc();
+      } catch (e) {
+        error = e;
+      }
+      expect(error.error.errorCode.code).to.eq(code);
+    }
+
+    // restore the default bounds for the following tests
+    await program.methods
+      .updateLoanTerms(
+        new BN(1),
+        new BN("18446744073709551615"),
+        new BN("18446744073709551615"),
+        new BN(0)
+      )
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+      })
+      .signers([owner])
+      .rpc();
+  });
+
+  it("Pages the position index past one page of orders!", async () => {
+    // a borrower with more orders than one page holds
+    const carol = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 20);
+    const carolSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      carol.publicKey,
+      owner.publicKey,
+      1000
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [carolStats] = await deriveUserStatsPDA(
+      carol.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+
+    const orders: [BN, PublicKey, PublicKey, PublicKey][] = [];
+    for (let i = 0; i < 33; i++) {
+      const [carolNftMint, carolNftAccount] = await createNFT(
+        program.provider,
+        carol,
+        carol.publicKey
+      );
+      const [programNFTVault] = await deriveNFTAccountPDA(
+        carolNftMint,
+        program.programId
+      );
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        configuration.orderId,
+        program.programId
+      );
+
+      await program.methods
+        .createOrder(new BN(100), new BN(10), new BN(3 * 86400), new BN(10))
+        .accounts({
+          signer: carol.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: carolSCAccount.address,
+          nftMint: carolNftMint,
+          nftVault: programNFTVault,
+          userNftVault: carolNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: carolStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([carol])
+        .rpc();
+      orders.push([
+        configuration.orderId,
+        orderPubKey,
+        carolNftMint,
+        carolNftAccount.address,
+      ]);
+    }
+
+    // the 33rd order opened a second page
+    let stats = await program.account.userStats.fetch(carolStats);
+    expect(stats.openOrders.toNumber()).to.eq(33);
+    expect(stats.orderIds.map((id) => id.toNumber())).to.deep.eq(
+      orders.map(([orderId]) => orderId.toNumber())
+    );
+    const page = await program.provider.connection.getAccountInfo(carolStats);
+    // discriminator, owner, configuration, counts, vec length and two pages of ids
+    expect(page.data.length).to.eq(8 + 32 * 2 + 8 * 5 + 4 + 8 * 32 * 2);
+
+    for (const [orderId, orderPubKey, carolNftMint, carolNftAccount] of orders) {
+      const [programNFTVault] = await deriveNFTAccountPDA(
+        carolNftMint,
+        program.programId
+      );
+      await program.methods
+        .cancelOrder(orderId)
+        .accounts({
+          signer: carol.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: carolSCAccount.address,
+          nftMint: carolNftMint,
+          nftVault: programNFTVault,
+          userNftVault: carolNftAccount,
+          order: orderPubKey,
+          borrowerStats: carolStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([carol])
+        .rpc();
+    }
+
+    stats = await program.account.userStats.fetch(carolStats);
+    expect(stats.openOrders.toNumber()).to.eq(0);
+    expect(stats.orderIds.length).to.eq(0);
+  });
+
+  it("Lends in another approved currency!", async () => {
+    // second stable coin
+    const usdtMint = await createTokenMint(
+      program.provider,
+      owner,
+      owner.publicKey,
+      null,
+      0
+    );
+    const aliceUsdtAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      usdtMint,
+      alice.publicKey,
+      owner.publicKey,
+      1000
+    );
+    const bobUsdtAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      usdtMint,
+      bob.publicKey,
+      owner.publicKey,
+      1000
+    );
+
+    // create NFT
+    [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [usdtVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      usdtMint,
+      program.programId
+    );
+    const [usdtCurrency] = await deriveCurrencyPDA(
+      configurationPubKey,
+      usdtMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+
+    // approve the currency
+    await program.methods
+      .addCurrency(new BN(0))
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: usdtMint,
+        stablecoinVault: usdtVault,
+        feeVault: await feeVaultOf(configurationPubKey, usdtMint),
+        currency: usdtCurrency,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+
+    // order pda
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(3 * 86400), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: usdtMint,
+        stablecoinVault: usdtVault,
+        currency: usdtCurrency,
+        userStablecoinVault: aliceUsdtAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+
+    const bobLentBefore = (await program.account.userStats.fetch(bobStats))
+      .totalLent;
+
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: usdtMint,
+        stablecoinVault: usdtVault,
+        currency: usdtCurrency,
+        lenderStablecoinVault: bobUsdtAccount.address,
+        borrowerStablecoinVault: aliceUsdtAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    // check order
+    const order = await program.account.order.fetch(orderPubKey);
+    expect(order.stablecoinMint.toBase58()).to.eq(usdtMint.toBase58());
+    expect(order.stablecoinVault.toBase58()).to.eq(usdtVault.toBase58());
+
+    // check balances and currency metrics
+    const aliceBalance =
+      await program.provider.connection.getTokenAccountBalance(
+        aliceUsdtAccount.address
+      );
+    expect(aliceBalance.value.amount).to.eq("1090");
+    const vaultBalance =
+      await program.provider.connection.getTokenAccountBalance(usdtVault);
+    expect(vaultBalance.value.amount).to.eq("10");
+    const currency = await program.account.currency.fetch(usdtCurrency);
+    expect(currency.totalAdditionalCollateral.toNumber()).to.eq(10);
+    expect(currency.totalPrincipalOutstanding.toNumber()).to.eq(100);
+    expect(currency.totalVolumeLent.toNumber()).to.eq(100);
+    expect(currency.activeLoans.toNumber()).to.eq(1);
+
+    // the configuration and the position totals only count its own mint
+    expect(
+      (
+        await program.account.configuration.fetch(configurationPubKey)
+      ).activeLoans.toNumber()
+    ).to.eq(configuration.activeLoans.toNumber());
+    expect(
+      (await program.account.userStats.fetch(bobStats)).totalLent.toNumber()
+    ).to.eq(bobLentBefore.toNumber());
+  });
+
+  it("Lends native SOL!", async () => {
+    // create NFT
+    [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+
+    // pda, native SOL uses the system program id in place of a mint
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [solVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      SYSTEM_PROGRAM_ID,
+      program.programId
+    );
+    const [solCurrency] = await deriveCurrencyPDA(
+      configurationPubKey,
+      SYSTEM_PROGRAM_ID,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+
+    await program.methods
+      .addNativeCurrency(new BN(0))
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        solVault,
+        feeVault: await feeVaultOf(configurationPubKey, SYSTEM_PROGRAM_ID),
+        currency: solCurrency,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+
+    // order pda
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+
+    const requestAmount: BN = new BN(LAMPORTS_PER_SOL);
+    const interest: BN = new BN(LAMPORTS_PER_SOL / 10);
+    const additionalCollateral: BN = new BN(LAMPORTS_PER_SOL / 2);
+    const vaultBalanceBefore = await program.provider.connection.getBalance(
+      solVault
+    );
+
+    await program.methods
+      .createOrderSol(
+        requestAmount,
+        interest,
+        new BN(3 * 86400),
+        additionalCollateral
+      )
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        solVault,
+        currency: solCurrency,
+        nftMint,
+        userNftVault: aliceNftAccount.address,
+        nftVault: programNFTVault,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+
+    expect(await program.provider.connection.getBalance(solVault)).to.eq(
+      vaultBalanceBefore + additionalCollateral.toNumber()
+    );
+
+    const aliceBalanceBefore = await program.provider.connection.getBalance(
+      alice.publicKey
+    );
+    await program.methods
+      .giveLoanSol(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        solVault,
+        currency: solCurrency,
+        borrower: alice.publicKey,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+      })
+      .signers([bob])
+      .rpc();
+    expect(
+      await program.provider.connection.getBalance(alice.publicKey)
+    ).to.eq(aliceBalanceBefore + requestAmount.toNumber());
+    // SOL loans mint no note and keep paying bob
+    const solOrder = await program.account.order.fetch(orderPubKey);
+    expect(solOrder.noteMint.toBase58()).to.eq(PublicKey.default.toBase58());
+
+    const bobBalanceBefore = await program.provider.connection.getBalance(
+      bob.publicKey
+    );
+    await program.methods
+      .paybackSol(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        solVault,
+        currency: solCurrency,
+        lender: bob.publicKey,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([alice])
+      .rpc();
+
+    // lender receives principal and interest
+    expect(await program.provider.connection.getBalance(bob.publicKey)).to.eq(
+      bobBalanceBefore + requestAmount.toNumber() + interest.toNumber()
+    );
+    // the collateral vault is back where it was
+    expect(await program.provider.connection.getBalance(solVault)).to.eq(
+      vaultBalanceBefore
+    );
+    const aliceNftBalance =
+      await program.provider.connection.getTokenAccountBalance(
+        aliceNftAccount.address
+      );
+    expect(aliceNftBalance.value.amount).to.eq("1");
+
+    const currency = await program.account.currency.fetch(solCurrency);
+    expect(currency.totalAdditionalCollateral.toNumber()).to.eq(0);
+    expect(currency.totalVolumeLent.toNumber()).to.eq(requestAmount.toNumber());
+    expect(currency.totalInterestPaid.toNumber()).to.eq(interest.toNumber());
+
+    // a keeper liquidates a defaulted loan, the lender gets the collateral and the order rent
+    const keeper = Keypair.generate();
+    await airdropSOL(program.provider, keeper.publicKey, 5);
+    const [defaultedNft, aliceDefaultedNft] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+    const [defaultedNftVault] = await deriveNFTAccountPDA(
+      defaultedNft,
+      program.programId
+    );
+    const defaultedId = (
+      await program.account.configuration.fetch(configurationPubKey)
+    ).orderId;
+    const [defaultedOrder] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      defaultedId,
+      program.programId
+    );
+    await program.methods
+      .createOrderSol(requestAmount, interest, new BN(3), additionalCollateral)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        solVault,
+        currency: solCurrency,
+        nftMint: defaultedNft,
+        userNftVault: aliceDefaultedNft.address,
+        nftVault: defaultedNftVault,
+        order: defaultedOrder,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoanSol(defaultedId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: defaultedOrder,
+        solVault,
+        currency: solCurrency,
+        borrower: alice.publicKey,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+      })
+      .signers([bob])
+      .rpc();
+
+    const bobNftAccount = await getAssociatedTokenAddress(
+      defaultedNft,
+      bob.publicKey
+    );
+    const instruction = createAssociatedTokenAccountInstruction(
+      keeper.publicKey,
+      bobNftAccount,
+      bob.publicKey,
+      defaultedNft
+    );
+    await sleep(5000);
+
+    const orderRent = await program.provider.connection.getBalance(
+      defaultedOrder
+    );
+    const bobBefore = await program.provider.connection.getBalance(
+      bob.publicKey
+    );
+    await program.methods
+      .liquidateSol(defaultedId)
+      .accounts({
+        signer: keeper.publicKey,
+        configuration: configurationPubKey,
+        order: defaultedOrder,
+        solVault,
+        feeVault: await feeVaultOf(configurationPubKey, SYSTEM_PROGRAM_ID),
+        currency: solCurrency,
+        lender: bob.publicKey,
+        nftMint: defaultedNft,
+        nftVault: defaultedNftVault,
+        userNftVault: bobNftAccount,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([instruction])
+      .signers([keeper])
+      .rpc();
+
+    const bobNftBalance =
+      await program.provider.connection.getTokenAccountBalance(bobNftAccount);
+    expect(bobNftBalance.value.amount).to.eq("1");
+    expect(await program.provider.connection.getBalance(bob.publicKey)).to.eq(
+      bobBefore + additionalCollateral.toNumber() + orderRent
+    );
+    expect(
+      await program.provider.connection.getAccountInfo(defaultedOrder)
+    ).to.eq(null);
+  });
+
+  it("Lends against a programmable NFT!", async () => {
+    // create pNFT, transfers go through the token metadata program
+    const [pnftMint, alicePnftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.ProgrammableNonFungible
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [nftMetadata] = await deriveMetadataPDA(pnftMint);
+    const [nftEdition] = await deriveEditionPDA(pnftMint);
+    const [aliceTokenRecord] = await deriveTokenRecordPDA(
+      pnftMint,
+      alicePnftAccount.address
+    );
+    const computeBudget = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 400_000,
+    });
+
+    // pNFT accounts shared by every instruction
+    const pnftAccounts = async (orderPubKey: PublicKey) => {
+      const nftVault = await getAssociatedTokenAddress(
+        pnftMint,
+        orderPubKey,
+        true
+      );
+      const [vaultTokenRecord] = await deriveTokenRecordPDA(
+        pnftMint,
+        nftVault
+      );
+      return {
+        nftMint: pnftMint,
+        nftVault,
+        userNftVault: alicePnftAccount.address,
+        nftMetadata,
+        nftEdition,
+        userTokenRecord: aliceTokenRecord,
+        vaultTokenRecord,
+        authorizationRulesProgram: TOKEN_METADATA_PROGRAM_ID,
+        authorizationRules: TOKEN_METADATA_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      };
+    };
+
+    const createOrder = async () => {
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+      const accounts = await pnftAccounts(orderPubKey);
+
+      await program.methods
+        .createOrderPnft(new BN(100), new BN(10), new BN(3 * 86400), new BN(0))
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: stableCoinVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+          ...accounts,
+        })
+        .preInstructions([computeBudget])
+        .signers([alice])
+        .rpc();
+
+      // the escrowed pNFT stays frozen
+      const vault = await getAccount(
+        program.provider.connection,
+        accounts.nftVault
+      );
+      expect(vault.amount.toString()).to.eq("1");
+      expect(vault.isFrozen).to.eq(true);
+
+      return { orderId, orderPubKey, accounts };
+    };
+
+    // cancel returns the pNFT
+    let { orderId, orderPubKey, accounts } = await createOrder();
+    await program.methods
+      .cancelOrderPnft(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...accounts,
+      })
+      .preInstructions([computeBudget])
+      .signers([alice])
+      .rpc();
+
+    let aliceNft = await getAccount(
+      program.provider.connection,
+      alicePnftAccount.address
+    );
+    expect(aliceNft.amount.toString()).to.eq("1");
+    expect(aliceNft.isFrozen).to.eq(true);
+    expect(
+      await program.provider.connection.getAccountInfo(accounts.nftVault)
+    ).to.eq(null);
+
+    // loan and payback
+    ({ orderId, orderPubKey, accounts } = await createOrder());
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    await program.methods
+      .paybackPnft(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        userStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...accounts,
+      })
+      .preInstructions([computeBudget])
+      .signers([alice])
+      .rpc();
+
+    aliceNft = await getAccount(
+      program.provider.connection,
+      alicePnftAccount.address
+    );
+    expect(aliceNft.amount.toString()).to.eq("1");
+    expect(
+      await program.provider.connection.getAccountInfo(accounts.nftVault)
+    ).to.eq(null);
+    expect(
+      (await program.account.userStats.fetch(aliceStats)).orderIds
+        .map((id) => id.toNumber())
+        .includes(orderId.toNumber())
+    ).to.eq(false);
+  });
+
+  it("Lends against a delegated NFT!", async () => {
+    // the nft stays in alice's wallet, frozen through its master edition
+    const [nftMint, aliceNftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [nftEdition] = await deriveEditionPDA(nftMint);
+
+    const createOrder = async (period: BN) => {
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+
+      await program.methods
+        .createOrderDelegated(new BN(100), new BN(10), period, new BN(0))
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: stableCoinVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          userNftVault: aliceNftAccount.address,
+          nftEdition,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+
+      const aliceNft = await getAccount(
+        program.provider.connection,
+        aliceNftAccount.address
+      );
+      expect(aliceNft.amount.toString()).to.eq("1");
+      expect(aliceNft.isFrozen).to.eq(true);
+      expect(aliceNft.delegate.toBase58()).to.eq(orderPubKey.toBase58());
+
+      const order = await program.account.order.fetch(orderPubKey);
+      expect(order.nftVault.toBase58()).to.eq(
+        aliceNftAccount.address.toBase58()
+      );
+
+      await program.methods
+        .giveLoan(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: stableCoinVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          order: orderPubKey,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+        })
+        .signers([bob])
+        .rpc();
+
+      return { orderId, orderPubKey };
+    };
+
+    // payback thaws and revokes
+    let { orderId, orderPubKey } = await createOrder(new BN(3 * 86400));
+    await program.methods
+      .paybackDelegated(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        userNftVault: aliceNftAccount.address,
+        nftEdition,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([alice])
+      .rpc();
+
+    let aliceNft = await getAccount(
+      program.provider.connection,
+      aliceNftAccount.address
+    );
+    expect(aliceNft.isFrozen).to.eq(false);
+    expect(aliceNft.delegate).to.eq(null);
+
+    // liquidation thaws and moves the nft to the lender
+    ({ orderId, orderPubKey } = await createOrder(new BN(3)));
+    const bobNftTokenAccountPubKey = await getAssociatedTokenAddress(
+      nftMint,
+      bob.publicKey
+    );
+    const instruction = createAssociatedTokenAccountInstruction(
+      bob.publicKey,
+      bobNftTokenAccountPubKey,
+      bob.publicKey,
+      nftMint
+    );
+
+    await sleep(5000);
+
+    await program.methods
+      .liquidateDelegated(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lender: bob.publicKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        keeperStablecoinVault: bobSCAccount.address,
+        feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+        nftMint,
+        borrowerNftVault: aliceNftAccount.address,
+        userNftVault: bobNftTokenAccountPubKey,
+        nftEdition,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([instruction])
+      .signers([bob])
+      .rpc();
+
+    aliceNft = await getAccount(
+      program.provider.connection,
+      aliceNftAccount.address
+    );
+    expect(aliceNft.amount.toString()).to.eq("0");
+    expect(aliceNft.isFrozen).to.eq(false);
+    const bobNft = await getAccount(
+      program.provider.connection,
+      bobNftTokenAccountPubKey
+    );
+    expect(bobNft.amount.toString()).to.eq("1");
+  });
+
+  it("Lends against a bundle of NFTs!", async () => {
+    // create NFTs
+    const nfts: [PublicKey, Account][] = [];
+    for (let i = 0; i < 3; i++) {
+      nfts.push(await createNFT(program.provider, alice, alice.publicKey));
+    }
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const [bundlePubKey] = await deriveBundlePDA(
+      orderPubKey,
+      program.programId
+    );
+    const nftVaults = await Promise.all(
+      nfts.map(([mint]) => getAssociatedTokenAddress(mint, orderPubKey, true))
+    );
+
+    await program.methods
+      .createOrderBundle(new BN(100), new BN(10), new BN(3 * 86400), new BN(0))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        order: orderPubKey,
+        bundle: bundlePubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .remainingAccounts(
+        nfts.flatMap(([mint, account], i) => [
+          { pubkey: mint, isWritable: false, isSigner: false },
+          { pubkey: account.address, isWritable: true, isSigner: false },
+          { pubkey: nftVaults[i], isWritable: true, isSigner: false },
+        ])
+      )
+      .signers([alice])
+      .rpc();
+
+    // check bundle
+    const bundle = await program.account.bundle.fetch(bundlePubKey);
+    expect(bundle.nftMints.map((mint) => mint.toBase58())).to.deep.eq(
+      nfts.map(([mint]) => mint.toBase58())
+    );
+    for (const nftVault of nftVaults) {
+      const vault = await getAccount(program.provider.connection, nftVault);
+      expect(vault.amount.toString()).to.eq("1");
+    }
+
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    // a partial list of accounts is rejected
+    const releaseAccounts = nfts.flatMap(([, account], i) => [
+      { pubkey: nftVaults[i], isWritable: true, isSigner: false },
+      { pubkey: account.address, isWritable: true, isSigner: false },
+    ]);
+    const payback = (remainingAccounts) =>
+      program.methods
+        .paybackBundle(orderId)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          bundle: bundlePubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: stableCoinVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+          userStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(remainingAccounts)
+        .signers([alice])
+        .rpc();
+
+    let error;
+    try {
+      await payback(releaseAccounts.slice(2));
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("BundleAccountMismatch");
+
+    await payback(releaseAccounts);
+
+    // every nft is back and the vaults are closed
+    for (const [, account] of nfts) {
+      const aliceNft = await getAccount(
+        program.provider.connection,
+        account.address
+      );
+      expect(aliceNft.amount.toString()).to.eq("1");
+    }
+    for (const nftVault of nftVaults) {
+      expect(
+        await program.provider.connection.getAccountInfo(nftVault)
+      ).to.eq(null);
+    }
+    expect(
+      await program.provider.connection.getAccountInfo(bundlePubKey)
+    ).to.eq(null);
+  });
+
+  it("Lends against fungible token collateral!", async () => {
+    // governance token
+    const govMint = await createTokenMint(
+      program.provider,
+      owner,
+      owner.publicKey,
+      null,
+      6
+    );
+    const aliceGovAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      govMint,
+      alice.publicKey,
+      owner.publicKey,
+      1_000_000
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [collateralToken] = await deriveCollateralTokenPDA(
+      configurationPubKey,
+      govMint,
+      program.programId
+    );
+
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const collateralVault = await getAssociatedTokenAddress(
+      govMint,
+      orderPubKey,
+      true
+    );
+    const createOrder = () =>
+      program.methods
+        .createOrderToken(
+          new BN(100),
+          new BN(10),
+          new BN(3),
+          new BN(0),
+          new BN(400_000)
+        )
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: stableCoinVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          collateralMint: govMint,
+          collateralToken,
+          userCollateralVault: aliceGovAccount.address,
+          order: orderPubKey,
+          collateralVault,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          collateralTokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+
+    // only approved tokens are accepted
+    let error;
+    try {
+      await createOrder();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("AccountNotInitialized");
+
+    await program.methods
+      .addCollateralToken()
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        collateralMint: govMint,
+        collateralToken,
+        systemProgram: SYSTEM_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+    await createOrder();
+
+    const order = await program.account.order.fetch(orderPubKey);
+    expect(order.nftMint.toBase58()).to.eq(govMint.toBase58());
+    expect(order.collateralAmount.toNumber()).to.eq(400_000);
+    let collateral = await program.account.collateralToken.fetch(
+      collateralToken
+    );
+    expect(collateral.totalCollateral.toNumber()).to.eq(400_000);
+
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    // lender takes the tokens after the period
+    const bobGovAccount = await getAssociatedTokenAddress(
+      govMint,
+      bob.publicKey
+    );
+    const instruction = createAssociatedTokenAccountInstruction(
+      bob.publicKey,
+      bobGovAccount,
+      bob.publicKey,
+      govMint
+    );
+
+    await sleep(5000);
+
+    await program.methods
+      .liquidateToken(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lender: bob.publicKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        keeperStablecoinVault: bobSCAccount.address,
+        feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+        collateralMint: govMint,
+        collateralToken,
+        collateralVault,
+        userCollateralVault: bobGovAccount,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralTokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([instruction])
+      .signers([bob])
+      .rpc();
+
+    const bobGov = await getAccount(program.provider.connection, bobGovAccount);
+    expect(bobGov.amount.toString()).to.eq("400000");
+    const aliceGov = await getAccount(
+      program.provider.connection,
+      aliceGovAccount.address
+    );
+    expect(aliceGov.amount.toString()).to.eq("600000");
+    expect(
+      await program.provider.connection.getAccountInfo(collateralVault)
+    ).to.eq(null);
+    collateral = await program.account.collateralToken.fetch(collateralToken);
+    expect(collateral.totalCollateral.toNumber()).to.eq(0);
+  });
+
+  it("Lends against Token-2022 collateral!", async () => {
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const addCollateralToken = async (mint: PublicKey) => {
+      const [collateralToken] = await deriveCollateralTokenPDA(
+        configurationPubKey,
+        mint,
+        program.programId
+      );
+      await program.methods
+        .addCollateralToken()
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+          collateralMint: mint,
+          collateralToken,
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+      return collateralToken;
+    };
+
+    // tokens that cannot be seized, or that a delegate could pull out of the vault, are refused
+    for (const extension of [
+      ExtensionType.NonTransferable,
+      ExtensionType.PermanentDelegate,
+    ]) {
+      const mint = await createToken2022Mint(
+        program.provider,
+        owner,
+        6,
+        extension
+      );
+      let error;
+      try {
+        await addCollateralToken(mint);
+      } catch (e) {
+        error = e;
+      }
+      expect(error.error.errorCode.code).to.eq("UnsupportedTokenExtension");
+    }
+
+    // 1% transfer fee
+    const feeMint = await createToken2022Mint(
+      program.provider,
+      owner,
+      6,
+      ExtensionType.TransferFeeConfig,
+      100,
+      BigInt(1_000_000)
+    );
+    const aliceFeeAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      feeMint,
+      alice.publicKey,
+      owner.publicKey,
+      1_000_000,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const collateralToken = await addCollateralToken(feeMint);
+
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const collateralVault = await getAssociatedTokenAddress(
+      feeMint,
+      orderPubKey,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await program.methods
+      .createOrderToken(
+        new BN(100),
+        new BN(10),
+        new BN(3),
+        new BN(0),
+        new BN(400_000)
+      )
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        collateralMint: feeMint,
+        collateralToken,
+        userCollateralVault: aliceFeeAccount.address,
+        order: orderPubKey,
+        collateralVault,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralTokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+
+    // alice pays the fee into the vault and the fee out of it up front
+    const aliceFee = await getAccount(
+      program.provider.connection,
+      aliceFeeAccount.address,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    expect(aliceFee.amount.toString()).to.eq("591877");
+    const vault = await getAccount(
+      program.provider.connection,
+      collateralVault,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    expect(vault.amount.toString()).to.eq("404041");
+    const order = await program.account.order.fetch(orderPubKey);
+    expect(order.collateralAmount.toNumber()).to.eq(400_000);
+
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    const bobFeeAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      bob,
+      feeMint,
+      bob.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    await sleep(5000);
+
+    await program.methods
+      .liquidateToken(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lender: bob.publicKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        keeperStablecoinVault: bobSCAccount.address,
+        feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+        collateralMint: feeMint,
+        collateralToken,
+        collateralVault,
+        userCollateralVault: bobFeeAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralTokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([bob])
+      .rpc();
+
+    // the lender receives the agreed amount, and the withheld fees were harvested to close the vault
+    const bobFee = await getAccount(
+      program.provider.connection,
+      bobFeeAccount.address,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    expect(bobFee.amount.toString()).to.eq("400000");
+    expect(
+      await program.provider.connection.getAccountInfo(collateralVault)
+    ).to.eq(null);
+    const collateral = await program.account.collateralToken.fetch(
+      collateralToken
+    );
+    expect(collateral.totalCollateral.toNumber()).to.eq(0);
+  });
+
+  it("Lends in a Token-2022 currency with a transfer fee!", async () => {
+    // create NFT
+    [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+
+    // 1% transfer fee
+    const feeMint = await createToken2022Mint(
+      program.provider,
+      owner,
+      6,
+      ExtensionType.TransferFeeConfig,
+      100,
+      BigInt(1_000_000)
+    );
+    const aliceFeeAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      feeMint,
+      alice.publicKey,
+      owner.publicKey,
+      1_000_000,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const bobFeeAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      feeMint,
+      bob.publicKey,
+      owner.publicKey,
+      1_000_000,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const balanceOf = async (account: PublicKey) =>
+      (
+        await getAccount(
+          program.provider.connection,
+          account,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ).amount.toString();
+
+    // pda, the vaults are associated token accounts of the currency and the configuration
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      feeMint,
+      program.programId
+    );
+    const stablecoinVault = await getAssociatedTokenAddress(
+      feeMint,
+      currencyPubKey,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const feeVault = await getAssociatedTokenAddress(
+      feeMint,
+      configurationPubKey,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+
+    await program.methods
+      .addToken2022Currency(new BN(0))
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: feeMint,
+        stablecoinVault,
+        feeVault,
+        currency: currencyPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+
+    // order pda
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+
+    await program.methods
+      .createOrder2022(
+        new BN(100_000),
+        new BN(10_000),
+        new BN(3 * 86400),
+        new BN(50_000)
+      )
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: feeMint,
+        stablecoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceFeeAccount.address,
+        nftMint,
+        userNftVault: aliceNftAccount.address,
+        nftVault: programNFTVault,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+
+    // alice pays the fee into the vault, which holds the whole additional collateral
+    expect(await balanceOf(aliceFeeAccount.address)).to.eq("949494");
+    expect(await balanceOf(stablecoinVault)).to.eq("50000");
+
+    await program.methods
+      .giveLoan2022(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: feeMint,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobFeeAccount.address,
+        borrowerStablecoinVault: aliceFeeAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([bob])
+      .rpc();
+
+    // bob pays the fee, alice nets the request amount
+    expect(await balanceOf(bobFeeAccount.address)).to.eq("898989");
+    expect(await balanceOf(aliceFeeAccount.address)).to.eq("1049494");
+    // Token-2022 loans mint no note and keep paying bob
+    const feeOrder = await program.account.order.fetch(orderPubKey);
+    expect(feeOrder.noteMint.toBase58()).to.eq(PublicKey.default.toBase58());
+
+    await program.methods
+      .payback2022(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        stablecoinMint: feeMint,
+        stablecoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceFeeAccount.address,
+        lenderStablecoinVault: bobFeeAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([alice])
+      .rpc();
+
+    // bob nets principal plus interest, alice gets the additional collateral less the fee out of the vault
+    expect(await balanceOf(bobFeeAccount.address)).to.eq("1008989");
+    expect(await balanceOf(aliceFeeAccount.address)).to.eq("987882");
+    expect(await balanceOf(stablecoinVault)).to.eq("0");
+    const aliceNftBalance =
+      await program.provider.connection.getTokenAccountBalance(
+        aliceNftAccount.address
+      );
+    expect(aliceNftBalance.value.amount).to.eq("1");
+
+    const currency = await program.account.currency.fetch(currencyPubKey);
+    expect(currency.totalAdditionalCollateral.toNumber()).to.eq(0);
+    expect(currency.totalVolumeLent.toNumber()).to.eq(100_000);
+    expect(currency.totalInterestPaid.toNumber()).to.eq(10_000);
+  });
+
+  it("Adds and withdraws additional collateral!", async () => {
+    // create NFT
+    [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(3 * 86400), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+
+    const accounts = {
+      signer: alice.publicKey,
+      configuration: configurationPubKey,
+      order: orderPubKey,
+      stablecoinMint: stableCoinMint,
+      stablecoinVault: stableCoinVault,
+      currency: currencyPubKey,
+      userStablecoinVault: aliceSCAccount.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    const addCollateral = (amount: number) =>
+      program.methods
+        .addCollateral(orderId, new BN(amount))
+        .accounts(accounts)
+        .signers([alice])
+        .rpc();
+    // active loans pass the nft metadata, this one has no collection and so no price feed
+    const [nftMetadata] = await deriveMetadataPDA(nftMint);
+    const withdrawCollateral = (amount: number) =>
+      program.methods
+        .withdrawCollateral(orderId, new BN(amount))
+        .accounts(accounts)
+        .remainingAccounts([
+          { pubkey: nftMetadata, isWritable: false, isSigner: false },
+        ])
+        .signers([alice])
+        .rpc();
+    const configurationBefore = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const lockedDelta = async () =>
+      (
+        await program.account.configuration.fetch(configurationPubKey)
+      ).totalAdditionalCollateral
+        .sub(configurationBefore.totalAdditionalCollateral)
+        .toNumber();
+
+    // unfilled orders can move freely above the currency minimum
+    await addCollateral(5);
+    await withdrawCollateral(10);
+    let order = await program.account.order.fetch(orderPubKey);
+    expect(order.additionalCollateral.toNumber()).to.eq(5);
+    expect(await lockedDelta()).to.eq(-5);
+
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    // active loans keep what the lender funded against
+    await addCollateral(5);
+    let error;
+    try {
+      await withdrawCollateral(6);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("AdditionalCollateralTooLow");
+
+    await withdrawCollateral(5);
+    order = await program.account.order.fetch(orderPubKey);
+    expect(order.additionalCollateral.toNumber()).to.eq(5);
+    expect(order.fundedCollateral.toNumber()).to.eq(5);
+    expect(await lockedDelta()).to.eq(-5);
+  });
+
+  it("Liquidates under-collateralized loans from the price feed!", async () => {
+    // collection and a verified member owned by alice
+    const [collectionMint] = await createMetaplexNFT(
+      program.provider,
+      owner,
+      owner.publicKey,
+      TokenStandard.NonFungible
+    );
+    const [nftMint, aliceNftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible,
+      collectionMint
+    );
+    await verifyCollection(program.provider, owner, nftMint, collectionMint);
+    const [nftMetadata] = await deriveMetadataPDA(nftMint);
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const [priceFeed] = await derivePriceFeedPDA(
+      configurationPubKey,
+      collectionMint,
+      program.programId
+    );
+
+    // local oracle, 60s staleness, liquidation at 80% ltv
+    const oracle = Keypair.generate();
+    await program.methods
+      .addPriceFeed(
+        collectionMint,
+        stableCoinMint,
+        oracle.publicKey,
+        new BN(60),
+        new BN(8000)
+      )
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        priceFeed,
+        systemProgram: SYSTEM_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+    const submitPrice = async (signer: Keypair, price: number) => {
+      await program.methods
+        .submitPrice(new BN(price), new BN(10))
+        .accounts({
+          oracle: signer.publicKey,
+          priceFeed,
+        })
+        .signers([signer])
+        .rpc();
+    };
+    await submitPrice(oracle, 1000);
+
+    // only the oracle can submit prices
+    let error;
+    try {
+      await submitPrice(alice, 1);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("ConstraintHasOne");
+
+    // one day loan
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(86400), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    const bobNftTokenAccountPubKey = await getAssociatedTokenAddress(
+      nftMint,
+      bob.publicKey
+    );
+    const liquidate = async () => {
+      await program.methods
+        .liquidate(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          lender: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+          listing: await listingOf(orderPubKey),
+          keeperStablecoinVault: bobSCAccount.address,
+          feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+          nftMint,
+          nftVault: programNFTVault,
+          payee: bob.publicKey,
+          userNftVault: bobNftTokenAccountPubKey,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .remainingAccounts([
+          { pubkey: priceFeed, isWritable: false, isSigner: false },
+          { pubkey: nftMetadata, isWritable: false, isSigner: false },
+        ])
+        .signers([bob])
+        .rpc();
+    };
+
+    // 100 borrowed against a floor of 990 plus 10 collateral
+    error = undefined;
+    try {
+      await liquidate();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("LoanWithinLtv");
+
+    // alice tops up while the floor is high
+    const collateralAccounts = {
+      signer: alice.publicKey,
+      configuration: configurationPubKey,
+      order: orderPubKey,
+      stablecoinMint: stableCoinMint,
+      stablecoinVault: programSCVault,
+      currency: currencyPubKey,
+      userStablecoinVault: aliceSCAccount.address,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    await program.methods
+      .addCollateral(orderId, new BN(100))
+      .accounts(collateralAccounts)
+      .signers([alice])
+      .rpc();
+    const withdrawCollateral = (amount: number) =>
+      program.methods
+        .withdrawCollateral(orderId, new BN(amount))
+        .accounts(collateralAccounts)
+        .remainingAccounts([
+          { pubkey: nftMetadata, isWritable: false, isSigner: false },
+          { pubkey: priceFeed, isWritable: false, isSigner: false },
+        ])
+        .signers([alice])
+        .rpc();
+
+    // floor drops to 90, 90 + 10 collateral would leave the loan above 80% ltv
+    await submitPrice(oracle, 100);
+    error = undefined;
+    try {
+      await withdrawCollateral(100);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("WithdrawalExceedsLtv");
+
+    // 90 + 60 collateral keeps it within
+    await withdrawCollateral(50);
+    const order = await program.account.order.fetch(orderPubKey);
+    expect(order.additionalCollateral.toNumber()).to.eq(60);
+
+    // floor drops to 0, so the loan is above 80% ltv before it is due
+    await submitPrice(oracle, 10);
+    await liquidate();
+
+    const bobNft = await getAccount(
+      program.provider.connection,
+      bobNftTokenAccountPubKey
+    );
+    expect(bobNft.amount.toString()).to.eq("1");
+    const feed = await program.account.priceFeed.fetch(priceFeed);
+    expect(feed.price.toNumber()).to.eq(10);
+    expect(feed.collection.toBase58()).to.eq(collectionMint.toBase58());
+  });
+
+  it("Decodes the Switchboard pull feed fixture!", async () => {
+    // offsets read by the oracle module, see PullFeedAccountData in switchboard-on-demand
+    const fixture = JSON.parse(
+      fs.readFileSync("tests/fixtures/switchboard_feed.json", "utf8")
+    );
+    const data = Buffer.from(fixture.account.data[0], "base64");
+    expect(fixture.account.owner).to.eq(
+      "SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv"
+    );
+    expect([...data.subarray(0, 8)]).to.deep.eq([
+      196, 27, 108, 196, 10, 215, 219, 40,
+    ]);
+
+    // 18 decimal fixed point result
+    const lastUpdate = new BN(data.subarray(2216, 2224), "le");
+    const value = new BN(data.subarray(2264, 2280), "le");
+    const stdDev = new BN(data.subarray(2280, 2296), "le");
+    expect(lastUpdate.toNumber()).to.eq(1700000000);
+    expect(value.toString()).to.eq("2500000000000000000");
+    expect(stdDev.toString()).to.eq("500000000000000000");
+
+    // the validator serves the fixture unchanged
+    const account = await program.provider.connection.getAccountInfo(
+      new PublicKey(fixture.pubkey)
+    );
+    expect(account.data.equals(data)).to.eq(true);
+  });
+
+  it("Reads prices from Pyth and Switchboard accounts!", async () => {
+    // fixture accounts loaded by the local validator, see Anchor.toml
+    // pyth: 5.0 +- 1.0, switchboard: 2.5 +- 0.5, both published at 1700000000
+    const pythPrice = new PublicKey(
+      "2ZtkwcsfVASgN4G1SKYqMix3TcjMVQs2mxxEU9hbE7Cz"
+    );
+    const switchboardFeed = new PublicKey(
+      "86Lk8w6cxwaceTQ6EmxkbHz5kcv9C34GDj5U9uk65nND"
+    );
+    const PYTH = 1;
+    const SWITCHBOARD = 2;
+
+    // top up the lender for the two liquidations
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      200
+    );
+
+    const [collectionMint] = await createMetaplexNFT(
+      program.provider,
+      owner,
+      owner.publicKey,
+      TokenStandard.NonFungible
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [priceFeed] = await derivePriceFeedPDA(
+      configurationPubKey,
+      collectionMint,
+      program.programId
+    );
+
+    // the fixtures are old, start with a one minute staleness window
+    await program.methods
+      .addPriceFeed(
+        collectionMint,
+        stableCoinMint,
+        owner.publicKey,
+        new BN(60),
+        new BN(8000)
+      )
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        priceFeed,
+        systemProgram: SYSTEM_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+    const setPriceSource = async (source: number, priceAccount: PublicKey) => {
+      await program.methods
+        .setPriceSource(source, priceAccount, 0)
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+          priceFeed,
+        })
+        .signers([owner])
+        .rpc();
+    };
+    const updateStaleness = async (maxStaleness: BN) => {
+      await program.methods
+        .updatePriceFeed(owner.publicKey, maxStaleness, new BN(8000))
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+          priceFeed,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    // one day loan against a verified member of the collection
+    const openLoan = async () => {
+      const [nftMint, aliceNftAccount] = await createMetaplexNFT(
+        program.provider,
+        alice,
+        alice.publicKey,
+        TokenStandard.NonFungible,
+        collectionMint
+      );
+      await verifyCollection(program.provider, owner, nftMint, collectionMint);
+      const [nftVault] = await deriveNFTAccountPDA(nftMint, program.programId);
+
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+      await program.methods
+        .createOrder(new BN(100), new BN(10), new BN(86400), new BN(10))
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault,
+          userNftVault: aliceNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+      await program.methods
+        .giveLoan(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          order: orderPubKey,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+        })
+        .signers([bob])
+        .rpc();
+
+      return { nftMint, nftVault, orderId, orderPubKey };
+    };
+
+    const liquidate = async (
+      { nftMint, nftVault, orderId, orderPubKey },
+      priceAccount: PublicKey
+    ) => {
+      const [nftMetadata] = await deriveMetadataPDA(nftMint);
+      const bobNftTokenAccountPubKey = await getAssociatedTokenAddress(
+        nftMint,
+        bob.publicKey
+      );
+      await program.methods
+        .liquidate(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          lender: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+          listing: await listingOf(orderPubKey),
+          keeperStablecoinVault: bobSCAccount.address,
+          feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+          nftMint,
+          nftVault,
+          payee: bob.publicKey,
+          userNftVault: bobNftTokenAccountPubKey,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .remainingAccounts([
+          { pubkey: priceFeed, isWritable: false, isSigner: false },
+          { pubkey: nftMetadata, isWritable: false, isSigner: false },
+          { pubkey: priceAccount, isWritable: false, isSigner: false },
+        ])
+        .signers([bob])
+        .rpc();
+    };
+
+    // pyth
+    await setPriceSource(PYTH, pythPrice);
+    const pythLoan = await openLoan();
+
+    // the feed only accepts its own price account
+    let error;
+    try {
+      await liquidate(pythLoan, switchboardFeed);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("PriceFeedMismatch");
+
+    error = undefined;
+    try {
+      await liquidate(pythLoan, pythPrice);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("StalePrice");
+
+    await updateStaleness(new BN(10_000_000_000));
+    await liquidate(pythLoan, pythPrice);
+
+    // switchboard
+    await setPriceSource(SWITCHBOARD, switchboardFeed);
+    const switchboardLoan = await openLoan();
+    await liquidate(switchboardLoan, switchboardFeed);
+
+    const feed = await program.account.priceFeed.fetch(priceFeed);
+    expect(feed.source).to.eq(SWITCHBOARD);
+    expect(feed.priceAccount.toBase58()).to.eq(switchboardFeed.toBase58());
+    let isExisting = true;
+    try {
+      await program.account.order.fetch(switchboardLoan.orderPubKey);
+    } catch (e) {
+      isExisting = false;
+    }
+    expect(isExisting).to.eq(false);
+  });
+
+  it("Auctions defaulted collateral!", async () => {
+    const carol = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 5);
+    const carolSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      carol.publicKey,
+      owner.publicKey,
+      1000
+    );
+    // top up the lender for the two loans
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      200
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+
+    const updateAuctionTerms = async (duration: number) => {
+      await program.methods
+        .updateAuctionTerms(new BN(duration), new BN(20000), new BN(5000))
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+        })
+        .signers([owner])
+        .rpc();
+    };
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+
+    // 3s loan of 100 + 10 interest with 10 additional collateral, funded by bob
+    const defaultedLoan = async () => {
+      const [nftMint, aliceNftAccount] = await createNFT(
+        program.provider,
+        alice,
+        alice.publicKey
+      );
+      const [nftVault] = await deriveNFTAccountPDA(nftMint, program.programId);
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+      const [auction] = await deriveAuctionPDA(orderPubKey, program.programId);
+
+      await program.methods
+        .createOrder(new BN(100), new BN(10), new BN(3), new BN(10))
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault,
+          userNftVault: aliceNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+      await program.methods
+        .giveLoan(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          order: orderPubKey,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+        })
+        .signers([bob])
+        .rpc();
+      await sleep(5000);
+
+      return { nftMint, nftVault, orderId, orderPubKey, auction };
+    };
+
+    const startAuction = async (
+      { nftMint, nftVault, orderId, orderPubKey, auction },
+      keeper: Keypair,
+      keeperVault: PublicKey
+    ) => {
+      await program.methods
+        .startDutchAuction(orderId)
+        .accounts({
+          signer: keeper.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+          auction,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          keeperStablecoinVault: keeperVault,
+          feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+          nftMint,
+          nftVault,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([keeper])
+        .rpc();
+    };
+
+    // one hour auction from 200% down to 50% of the debt
+    await updateAuctionTerms(3600);
+    let loan = await defaultedLoan();
+
+    // the nft can no longer go straight to the lender
+    const bobNftAccount = await getAssociatedTokenAddress(
+      loan.nftMint,
+      bob.publicKey
+    );
+    let error;
+    try {
+      await program.methods
+        .liquidate(loan.orderId)
+        .accounts({
+          signer: bob.publicKey,
+          lender: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: await noteVaultOf(loan.orderPubKey, bob.publicKey),
+          listing: await listingOf(loan.orderPubKey),
+          keeperStablecoinVault: bobSCAccount.address,
+          feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+          nftMint: loan.nftMint,
+          nftVault: loan.nftVault,
+          payee: bob.publicKey,
+          userNftVault: bobNftAccount,
+          order: loan.orderPubKey,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([bob])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("AuctionRequired");
+
+    await startAuction(loan, bob, bobSCAccount.address);
+    const auctionAccount = await program.account.auction.fetch(loan.auction);
+    expect(auctionAccount.keeper.toBase58()).to.eq(bob.publicKey.toBase58());
+    expect(auctionAccount.debt.toNumber()).to.eq(110);
+    expect(auctionAccount.startPrice.toNumber()).to.eq(220);
+    expect(auctionAccount.endPrice.toNumber()).to.eq(55);
+
+    // carol buys, bob is repaid and alice keeps the surplus and her collateral
+    const bobBefore = await balance(bobSCAccount.address);
+    const aliceBefore = await balance(aliceSCAccount.address);
+    const carolBefore = await balance(carolSCAccount.address);
+    const carolNftAccount = await getAssociatedTokenAddress(
+      loan.nftMint,
+      carol.publicKey
+    );
+    await program.methods
+      .buyDutchAuction(loan.orderId)
+      .accounts({
+        signer: carol.publicKey,
+        configuration: configurationPubKey,
+        order: loan.orderPubKey,
+        auction: loan.auction,
+        lender: bob.publicKey,
+        keeper: bob.publicKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        buyerStablecoinVault: carolSCAccount.address,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(loan.orderPubKey, bob.publicKey),
+        borrowerStablecoinVault: aliceSCAccount.address,
+        nftMint: loan.nftMint,
+        nftVault: loan.nftVault,
+        userNftVault: carolNftAccount,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([
+        createAssociatedTokenAccountInstruction(
+          carol.publicKey,
+          carolNftAccount,
+          carol.publicKey,
+          loan.nftMint
+        ),
+      ])
+      .signers([carol])
+      .rpc();
+
+    const paid = carolBefore - (await balance(carolSCAccount.address));
+    expect(paid).to.be.lte(220).and.gt(110);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(110);
+    expect((await balance(aliceSCAccount.address)) - aliceBefore).to.eq(
+      paid - 110 + 10
+    );
+    expect(await balance(carolNftAccount)).to.eq(1);
+
+    // nobody buys a two second auction started by carol, she closes it
+    await updateAuctionTerms(2);
+    loan = await defaultedLoan();
+    await startAuction(loan, carol, carolSCAccount.address);
+
+    // bob sells the note during the auction, carol is paid as the holder at the end
+    const [noteMint] = await deriveNoteMintPDA(loan.orderPubKey, program.programId);
+    const carolNoteVault = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      noteMint,
+      carol.publicKey
+    );
+    await transfer(
+      program.provider.connection,
+      bob,
+      await noteVaultOf(loan.orderPubKey, bob.publicKey),
+      carolNoteVault.address,
+      bob,
+      1
+    );
+    await sleep(3000);
+
+    const carolNftVault = await getAssociatedTokenAddress(
+      loan.nftMint,
+      carol.publicKey
+    );
+    const carolBeforeClaim = await balance(carolSCAccount.address);
+    await program.methods
+      .claimDutchAuction(loan.orderId)
+      .accounts({
+        signer: carol.publicKey,
+        configuration: configurationPubKey,
+        order: loan.orderPubKey,
+        auction: loan.auction,
+        lender: bob.publicKey,
+        keeper: carol.publicKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: carolSCAccount.address,
+        noteVault: carolNoteVault.address,
+        nftMint: loan.nftMint,
+        nftVault: loan.nftVault,
+        userNftVault: carolNftVault,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([
+        createAssociatedTokenAccountInstruction(
+          carol.publicKey,
+          carolNftVault,
+          carol.publicKey,
+          loan.nftMint
+        ),
+      ])
+      .signers([carol])
+      .rpc();
+
+    expect(await balance(carolNftVault)).to.eq(1);
+    expect((await balance(carolSCAccount.address)) - carolBeforeClaim).to.eq(
+      10
+    );
+    let isExisting = true;
+    try {
+      await program.account.auction.fetch(loan.auction);
+    } catch (e) {
+      isExisting = false;
+    }
+    expect(isExisting).to.eq(false);
+
+    // back to handing the nft to the lender
+    await updateAuctionTerms(0);
+  });
+
+  it("Auctions defaulted collateral to the highest bidder!", async () => {
+    const carol = Keypair.generate();
+    const dave = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 5);
+    await airdropSOL(program.provider, dave.publicKey, 5);
+    const carolSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      carol.publicKey,
+      owner.publicKey,
+      1000
+    );
+    const daveSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      dave.publicKey,
+      owner.publicKey,
+      1000
+    );
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      100
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+
+    // 4s english auctions opening at 50% of the debt, 10% raises, 8s anti-sniping window
+    await program.methods
+      .updateAuctionTerms(new BN(4), new BN(20000), new BN(5000))
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+      })
+      .signers([owner])
+      .rpc();
+    const updateAuctionKind = async (kind: number) => {
+      await program.methods
+        .updateAuctionKind(kind, new BN(8), new BN(1000))
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+        })
+        .signers([owner])
+        .rpc();
+    };
+    await updateAuctionKind(1);
+
+    // 3s loan of 100 + 10 interest with 10 additional collateral, funded by bob
+    const [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+    const [nftVault] = await deriveNFTAccountPDA(nftMint, program.programId);
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const [auction] = await deriveAuctionPDA(orderPubKey, program.programId);
+    const [bidVault] = await deriveBidVaultPDA(auction, program.programId);
+
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(3), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+    await sleep(5000);
+
+    await program.methods
+      .startEnglishAuction(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        order: orderPubKey,
+        noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+        auction,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        keeperStablecoinVault: bobSCAccount.address,
+        feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+        bidVault,
+        nftMint,
+        nftVault,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([bob])
+      .rpc();
+    const openingEnd = (await program.account.auction.fetch(auction)).endTime;
+
+    const bid = async (
+      bidder: Keypair,
+      bidderVault: PublicKey,
+      previousBidderVault: PublicKey,
+      amount: number
+    ) => {
+      await program.methods
+        .bid(orderId, new BN(amount))
+        .accounts({
+          signer: bidder.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          auction,
+          bidVault,
+          userStablecoinVault: bidderVault,
+          previousBidderStablecoinVault: previousBidderVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bidder])
+        .rpc();
+    };
+
+    // bids open at 55 and must beat the last one by 10%
+    let error;
+    try {
+      await bid(carol, carolSCAccount.address, carolSCAccount.address, 50);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("BidTooLow");
+
+    const carolBefore = await balance(carolSCAccount.address);
+    await bid(carol, carolSCAccount.address, carolSCAccount.address, 100);
+    expect(await balance(bidVault)).to.eq(100);
+
+    error = undefined;
+    try {
+      await bid(dave, daveSCAccount.address, carolSCAccount.address, 105);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("BidTooLow");
+
+    // carol is refunded as soon as she is outbid
+    await bid(dave, daveSCAccount.address, carolSCAccount.address, 150);
+    expect(await balance(carolSCAccount.address)).to.eq(carolBefore);
+    expect(await balance(bidVault)).to.eq(150);
+
+    // the late bid pushed the deadline back
+    let auctionAccount = await program.account.auction.fetch(auction);
+    expect(auctionAccount.bidder.toBase58()).to.eq(dave.publicKey.toBase58());
+    expect(auctionAccount.endTime.toNumber()).to.be.gt(openingEnd.toNumber());
+
+    // anyone settles once the auction is over
+    const daveNftAccount = await getAssociatedTokenAddress(
+      nftMint,
+      dave.publicKey
+    );
+    const settle = async () => {
+      await program.methods
+        .settleEnglishAuction(orderId)
+        .accounts({
+          signer: carol.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          auction,
+          lender: bob.publicKey,
+          keeper: bob.publicKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          bidVault,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+          borrowerStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault,
+          userNftVault: daveNftAccount,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([
+          createAssociatedTokenAccountInstruction(
+            carol.publicKey,
+            daveNftAccount,
+            dave.publicKey,
+            nftMint
+          ),
+        ])
+        .signers([carol])
+        .rpc();
+    };
+    error = undefined;
+    try {
+      await settle();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("AuctionNotEnded");
+
+    await sleep(9000);
+    const bobBefore = await balance(bobSCAccount.address);
+    const aliceBefore = await balance(aliceSCAccount.address);
+    await settle();
+
+    // bob gets the 110 owed, alice the 40 surplus and her 10 collateral
+    expect(await balance(daveNftAccount)).to.eq(1);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(110);
+    expect((await balance(aliceSCAccount.address)) - aliceBefore).to.eq(50);
+    let isExisting = true;
+    try {
+      await getAccount(program.provider.connection, bidVault);
+    } catch (e) {
+      isExisting = false;
+    }
+    expect(isExisting).to.eq(false);
+
+    // back to handing the nft to the lender
+    await updateAuctionKind(0);
+    await program.methods
+      .updateAuctionTerms(new BN(0), new BN(0), new BN(0))
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+      })
+      .signers([owner])
+      .rpc();
+  });
+
+  it("Pays keepers for liquidating loans!", async () => {
+    const keeper = Keypair.generate();
+    await airdropSOL(program.provider, keeper.publicKey, 5);
+    const keeperSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      keeper.publicKey,
+      owner.publicKey,
+      1
+    );
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      200
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+    const updateKeeperBounty = async (bountyBps: number, fromReserve: boolean) => {
+      await program.methods
+        .updateKeeperBounty(new BN(bountyBps), fromReserve)
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    // 3s loan of 100 + 10 interest with 10 additional collateral, funded by bob
+    const defaultedLoan = async () => {
+      const [nftMint, aliceNftAccount] = await createNFT(
+        program.provider,
+        alice,
+        alice.publicKey
+      );
+      const [nftVault] = await deriveNFTAccountPDA(nftMint, program.programId);
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+
+      await program.methods
+        .createOrder(new BN(100), new BN(10), new BN(3), new BN(10))
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault,
+          userNftVault: aliceNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+      await program.methods
+        .giveLoan(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          order: orderPubKey,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+        })
+        .signers([bob])
+        .rpc();
+      await sleep(5000);
+
+      return { nftMint, nftVault, orderId, orderPubKey };
+    };
+
+    // the keeper settles bob's loan and pays for bob's associated token account of the nft
+    const keeperLiquidate = async (
+      { nftMint, nftVault, orderId, orderPubKey },
+      payee: PublicKey
+    ) => {
+      await program.methods
+        .liquidate(orderId)
+        .accounts({
+          signer: keeper.publicKey,
+          lender: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: await noteVaultOf(orderPubKey, bob.publicKey),
+          listing: await listingOf(orderPubKey),
+          keeperStablecoinVault: keeperSCAccount.address,
+          feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+          nftMint,
+          nftVault,
+          payee,
+          userNftVault: await getAssociatedTokenAddress(nftMint, payee),
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([keeper])
+        .rpc();
+    };
+
+    // 5% of the principal out of the lender's additional collateral
+    await updateKeeperBounty(500, false);
+    let loan = await defaultedLoan();
+
+    // the nft cannot be sent to the keeper
+    let error;
+    try {
+      await keeperLiquidate(loan, keeper.publicKey);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("ConstraintAddress");
+
+    let bobNftAccount = await getAssociatedTokenAddress(
+      loan.nftMint,
+      bob.publicKey
+    );
+    expect(
+      await program.provider.connection.getAccountInfo(bobNftAccount)
+    ).to.eq(null);
+    let bobBefore = await balance(bobSCAccount.address);
+    let keeperBefore = await balance(keeperSCAccount.address);
+    const feesBefore = (
+      await program.account.configuration.fetch(configurationPubKey)
+    ).totalFeesCollected.toNumber();
+    await keeperLiquidate(loan, bob.publicKey);
+
+    expect(await balance(bobNftAccount)).to.eq(1);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(5);
+    expect((await balance(keeperSCAccount.address)) - keeperBefore).to.eq(5);
+    // the bounty is a fee charged on the collateral
+    let configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.totalFeesCollected.toNumber() - feesBefore).to.eq(5);
+
+    // out of the fee vault, the lender keeps all the collateral
+    await updateKeeperBounty(500, true);
+    loan = await defaultedLoan();
+    const feeVault = await feeVaultOf(configurationPubKey, stableCoinMint);
+    await transfer(
+      program.provider.connection,
+      bob,
+      bobSCAccount.address,
+      feeVault,
+      bob,
+      3
+    );
+    const bountiesBefore = (
+      await program.account.currency.fetch(currencyPubKey)
+    ).totalBountiesPaid.toNumber();
+    const configurationBountiesBefore = configuration.totalBountiesPaid.toNumber();
+
+    bobNftAccount = await getAssociatedTokenAddress(
+      loan.nftMint,
+      bob.publicKey
+    );
+    bobBefore = await balance(bobSCAccount.address);
+    keeperBefore = await balance(keeperSCAccount.address);
+    const vaultBefore = await balance(programSCVault);
+    const feeVaultBefore = await balance(feeVault);
+    await keeperLiquidate(loan, bob.publicKey);
+
+    const bounty = Math.min(5, feeVaultBefore);
+    expect(bounty).to.be.greaterThan(0);
+    expect(await balance(bobNftAccount)).to.eq(1);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(10);
+    expect((await balance(keeperSCAccount.address)) - keeperBefore).to.eq(
+      bounty
+    );
+    // the bounty leaves the fee vault, the collateral vault only releases this loan's collateral
+    expect(feeVaultBefore - (await balance(feeVault))).to.eq(bounty);
+    expect(vaultBefore - (await balance(programSCVault))).to.eq(10);
+    const currency = await program.account.currency.fetch(currencyPubKey);
+    expect(currency.totalBountiesPaid.toNumber() - bountiesBefore).to.eq(
+      bounty
+    );
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(
+      configuration.totalBountiesPaid.toNumber() - configurationBountiesBefore
+    ).to.eq(bounty);
+    expect(configuration.totalFeesCollected.toNumber() - feesBefore).to.eq(5);
+
+    // with auctions the keeper is paid for starting one, the rest of the collateral is auctioned
+    await updateKeeperBounty(500, false);
+    const updateAuctionTerms = async (duration: number) => {
+      await program.methods
+        .updateAuctionTerms(new BN(duration), new BN(20000), new BN(5000))
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+        })
+        .signers([owner])
+        .rpc();
+    };
+    await updateAuctionTerms(2);
+    loan = await defaultedLoan();
+    const [auction] = await deriveAuctionPDA(loan.orderPubKey, program.programId);
+    keeperBefore = await balance(keeperSCAccount.address);
+    await program.methods
+      .startDutchAuction(loan.orderId)
+      .accounts({
+        signer: keeper.publicKey,
+        configuration: configurationPubKey,
+        order: loan.orderPubKey,
+        noteVault: await noteVaultOf(loan.orderPubKey, bob.publicKey),
+        auction,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        keeperStablecoinVault: keeperSCAccount.address,
+        feeVault,
+        nftMint: loan.nftMint,
+        nftVault: loan.nftVault,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([keeper])
+      .rpc();
+    expect((await balance(keeperSCAccount.address)) - keeperBefore).to.eq(5);
+    const order = await program.account.order.fetch(loan.orderPubKey);
+    expect(order.additionalCollateral.toNumber()).to.eq(5);
+
+    // nobody buys, the keeper closes the auction and bob gets the nft and the rest
+    await sleep(3000);
+    bobNftAccount = await getAssociatedTokenAddress(
+      loan.nftMint,
+      bob.publicKey
+    );
+    bobBefore = await balance(bobSCAccount.address);
+    await program.methods
+      .claimDutchAuction(loan.orderId)
+      .accounts({
+        signer: keeper.publicKey,
+        configuration: configurationPubKey,
+        order: loan.orderPubKey,
+        auction,
+        lender: bob.publicKey,
+        keeper: keeper.publicKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        noteVault: await noteVaultOf(loan.orderPubKey, bob.publicKey),
+        nftMint: loan.nftMint,
+        nftVault: loan.nftVault,
+        userNftVault: bobNftAccount,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([
+        createAssociatedTokenAccountInstruction(
+          keeper.publicKey,
+          bobNftAccount,
+          bob.publicKey,
+          loan.nftMint
+        ),
+      ])
+      .signers([keeper])
+      .rpc();
+    expect(await balance(bobNftAccount)).to.eq(1);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(5);
+
+    await updateAuctionTerms(0);
+    await updateKeeperBounty(0, false);
+  });
+
+  it("Pays back and liquidates loans in batches!", async () => {
+    const keeper = Keypair.generate();
+    await airdropSOL(program.provider, keeper.publicKey, 5);
+    const keeperSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      keeper.publicKey,
+      owner.publicKey,
+      1
+    );
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      400
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const computeBudget = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 1_000_000,
+    });
+
+    // loan of 100 + 10 interest with 10 additional collateral, funded by bob
+    const openLoan = async (period: BN) => {
+      const [nftMint, aliceNftAccount] = await createNFT(
+        program.provider,
+        alice,
+        alice.publicKey
+      );
+      const [nftVault] = await deriveNFTAccountPDA(nftMint, program.programId);
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+
+      await program.methods
+        .createOrder(new BN(100), new BN(10), period, new BN(10))
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault,
+          userNftVault: aliceNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+      await program.methods
+        .giveLoan(orderId)
+        .accounts({
+          signer: bob.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          order: orderPubKey,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+        })
+        .signers([bob])
+        .rpc();
+
+      const noteVault = await noteVaultOf(orderPubKey, bob.publicKey);
+      return {
+        nftMint,
+        nftVault,
+        aliceNftAccount,
+        orderId,
+        orderPubKey,
+        noteVault,
+      };
+    };
+
+    // order, lender stablecoin vault, nft mint, nft vault, user nft vault, borrower stats, lender stats,
+    // loan note account
+    const batchEntry = (loan, userNftVault: PublicKey) =>
+      [
+        loan.orderPubKey,
+        bobSCAccount.address,
+        loan.nftMint,
+        loan.nftVault,
+        userNftVault,
+        aliceStats,
+        bobStats,
+        loan.noteVault,
+      ].map((pubkey) => ({
+        pubkey,
+        isWritable: !pubkey.equals(loan.nftMint),
+        isSigner: false,
+      }));
+
+    const paybackBatch = async (atomic: boolean, entries) => {
+      await program.methods
+        .paybackBatch(atomic)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(entries.flat())
+        .preInstructions([computeBudget])
+        .signers([alice])
+        .rpc();
+    };
+
+    const first = await openLoan(new BN(3 * 86400));
+    const second = await openLoan(new BN(3 * 86400));
+    const firstEntry = batchEntry(first, first.aliceNftAccount.address);
+    const secondEntry = batchEntry(second, second.aliceNftAccount.address);
+
+    // an atomic batch fails on the first bad entry, here an nft vault of someone else
+    let error;
+    try {
+      await paybackBatch(true, [
+        firstEntry,
+        batchEntry(second, bobSCAccount.address),
+      ]);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("BatchAccountMismatch");
+    await program.account.order.fetch(first.orderPubKey);
+
+    // otherwise bad entries are skipped, like the first order passed twice
+    await paybackBatch(false, [firstEntry, secondEntry, firstEntry]);
+    for (const loan of [first, second]) {
+      const aliceNft = await getAccount(
+        program.provider.connection,
+        loan.aliceNftAccount.address
+      );
+      expect(aliceNft.amount.toString()).to.eq("1");
+      let isExisting = true;
+      try {
+        await program.account.order.fetch(loan.orderPubKey);
+      } catch (e) {
+        isExisting = false;
+      }
+      expect(isExisting).to.eq(false);
+    }
+
+    // a keeper liquidates two due loans into bob's associated token accounts, next to one
+    // that is not due yet
+    const third = await openLoan(new BN(3));
+    const fourth = await openLoan(new BN(3));
+    const notDue = await openLoan(new BN(3 * 86400));
+    await sleep(5000);
+
+    const liquidationEntry = async (loan) => {
+      const bobNftAccount = await getAssociatedTokenAddress(
+        loan.nftMint,
+        bob.publicKey
+      );
+      const createAta = createAssociatedTokenAccountInstruction(
+        keeper.publicKey,
+        bobNftAccount,
+        bob.publicKey,
+        loan.nftMint
+      );
+      const entry = [
+        ...batchEntry(loan, bobNftAccount),
+        { pubkey: bob.publicKey, isWritable: true, isSigner: false },
+      ];
+      return { createAta, entry };
+    };
+    const liquidateBatch = async (atomic: boolean, loans) => {
+      const entries = await Promise.all(loans.map(liquidationEntry));
+      await program.methods
+        .liquidateBatch(atomic)
+        .accounts({
+          signer: keeper.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          keeperStablecoinVault: keeperSCAccount.address,
+          feeVault: await feeVaultOf(configurationPubKey, stableCoinMint),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(entries.map(({ entry }) => entry).flat())
+        .preInstructions([
+          computeBudget,
+          ...entries.map(({ createAta }) => createAta),
+        ])
+        .signers([keeper])
+        .rpc();
+    };
+
+    // atomic, the loan that is not due fails the whole batch
+    error = undefined;
+    try {
+      await liquidateBatch(true, [third, notDue, fourth]);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("RepaymentPeriodNotExceeded");
+    for (const loan of [third, notDue, fourth]) {
+      await program.account.order.fetch(loan.orderPubKey);
+    }
+
+    // otherwise it is skipped and stays open
+    await liquidateBatch(false, [third, notDue, fourth]);
+    for (const loan of [third, fourth]) {
+      const bobNft = await getAccount(
+        program.provider.connection,
+        await getAssociatedTokenAddress(loan.nftMint, bob.publicKey)
+      );
+      expect(bobNft.amount.toString()).to.eq("1");
+      expect(
+        await program.provider.connection.getAccountInfo(loan.orderPubKey)
+      ).to.eq(null);
+    }
+    const notDueOrder = await program.account.order.fetch(notDue.orderPubKey);
+    expect(notDueOrder.withdrewAt.toNumber()).to.eq(0);
+    const bobNotDueNft = await getAccount(
+      program.provider.connection,
+      await getAssociatedTokenAddress(notDue.nftMint, bob.publicKey)
+    );
+    expect(bobNotDueNft.amount.toString()).to.eq("0");
+    await paybackBatch(true, [
+      batchEntry(notDue, notDue.aliceNftAccount.address),
+    ]);
+
+    // every order is settled in the stats
+    const stats = await program.account.userStats.fetch(bobStats);
+    const settled = [first, second, third, fourth, notDue].map((loan) =>
+      loan.orderId.toNumber()
+    );
+    expect(
+      stats.orderIds.some((id) => settled.includes(id.toNumber()))
+    ).to.eq(false);
+  });
+
+  it("Lends from a pool of depositors!", async () => {
+    const [collectionMint] = await createMetaplexNFT(
+      program.provider,
+      owner,
+      owner.publicKey,
+      TokenStandard.NonFungible
+    );
+    const [nftMint, aliceNftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible,
+      collectionMint
+    );
+    await verifyCollection(program.provider, owner, nftMint, collectionMint);
+    const [nftMetadata] = await deriveMetadataPDA(nftMint);
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      1000
+    );
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      alice.publicKey,
+      owner.publicKey,
+      200
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const [pool] = await derivePoolPDA(
+      configurationPubKey,
+      collectionMint,
+      stableCoinMint,
+      program.programId
+    );
+    const [liquidityVault] = await derivePoolVaultPDA(pool, program.programId);
+    const [shareMint] = await deriveShareMintPDA(pool, program.programId);
+    const [shareEscrow] = await deriveShareEscrowPDA(pool, program.programId);
+
+    // loans up to 500 for a day, 90% utilization
+    await program.methods
+      .createPool(
+        collectionMint,
+        owner.publicKey,
+        new BN(500),
+        new BN(86400),
+        new BN(9000)
+      )
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        pool,
+        currency: currencyPubKey,
+        stablecoinMint: stableCoinMint,
+        liquidityVault,
+        shareMint,
+        shareEscrow,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+
+    // 10% a year at zero utilization, 210% at the optimal 80% and the 1000% maximum
+    // at full utilization
+    await program.methods
+      .updatePoolRates(
+        new BN(1000),
+        new BN(20000),
+        new BN(8000),
+        new BN(79000)
+      )
+      .accounts({
+        manager: owner.publicKey,
+        pool,
+        liquidityVault,
+      })
+      .signers([owner])
+      .rpc();
+
+    // the curve cannot go past the maximum borrow rate at full utilization
+    let rateError;
+    try {
+      await program.methods
+        .updatePoolRates(
+          new BN(1000),
+          new BN(20000),
+          new BN(8000),
+          new BN(80000)
+        )
+        .accounts({
+          manager: owner.publicKey,
+          pool,
+          liquidityVault,
+        })
+        .signers([owner])
+        .rpc();
+    } catch (e) {
+      rateError = e;
+    }
+    expect(rateError.error.errorCode.code).to.eq("InvalidLoanTerms");
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+    const bobShareAccount = await getAssociatedTokenAddress(
+      shareMint,
+      bob.publicKey
+    );
+    const poolAccounts = {
+      configuration: configurationPubKey,
+      pool,
+      stablecoinMint: stableCoinMint,
+      liquidityVault,
+      shareMint,
+      userStablecoinVault: bobSCAccount.address,
+      userShareVault: bobShareAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // the first deposit gets one share per token
+    await program.methods
+      .depositPool(new BN(1000))
+      .accounts({ signer: bob.publicKey, ...poolAccounts })
+      .preInstructions([
+        createAssociatedTokenAccountInstruction(
+          bob.publicKey,
+          bobShareAccount,
+          bob.publicKey,
+          shareMint
+        ),
+      ])
+      .signers([bob])
+      .rpc();
+    expect(await balance(bobShareAccount)).to.eq(1000);
+    expect(await balance(liquidityVault)).to.eq(1000);
+
+    const aliceNft = {
+      nftMint,
+      nftVault: programNFTVault,
+      userNftVault: aliceNftAccount.address,
+    };
+    const createOrder = async (
+      period: number,
+      amount = 100,
+      interest = 10,
+      nft = aliceNft
+    ) => {
+      const configuration = await program.account.configuration.fetch(
+        configurationPubKey
+      );
+      const orderId = configuration.orderId;
+      const [orderPubKey] = await deriveOrderAccountPDA(
+        configurationPubKey,
+        orderId,
+        program.programId
+      );
+      await program.methods
+        .createOrder(
+          new BN(amount),
+          new BN(interest),
+          new BN(period),
+          new BN(10)
+        )
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          ...nft,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([alice])
+        .rpc();
+      return [orderId, orderPubKey];
+    };
+    const fundOrder = async (orderId, orderPubKey, metadata = nftMetadata) => {
+      await program.methods
+        .fundOrderFromPool(orderId)
+        .accounts({
+          signer: owner.publicKey,
+          configuration: configurationPubKey,
+          pool,
+          liquidityVault,
+          shareMint,
+          order: orderPubKey,
+          nftMetadata: metadata,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    const cancelOrder = (orderId, orderPubKey) =>
+      program.methods
+        .cancelOrder(orderId)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault: programNFTVault,
+          userNftVault: aliceNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([alice])
+        .rpc();
+
+    const paybackPool = (orderId, orderPubKey, nft = aliceNft) =>
+      program.methods
+        .paybackPool(orderId)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          pool,
+          liquidityVault,
+          order: orderPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          userStablecoinVault: aliceSCAccount.address,
+          ...nft,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([alice])
+        .rpc();
+
+    // two days is beyond the pool limits
+    let [orderId, orderPubKey] = await createOrder(2 * 86400);
+    let error;
+    try {
+      await fundOrder(orderId, orderPubKey);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("OrderNotQualified");
+    await cancelOrder(orderId, orderPubKey);
+
+    // 500 for a day at 50% utilization is 135% a year, about 1.8 of interest, more
+    // than the interest free order offers
+    [orderId, orderPubKey] = await createOrder(86400, 500, 0);
+    error = undefined;
+    try {
+      await fundOrder(orderId, orderPubKey);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("OrderNotQualified");
+    await cancelOrder(orderId, orderPubKey);
+
+    // pool loans are only repaid and liquidated from the nft vault pda, so orders
+    // holding the nft anywhere else cannot be funded from the pool
+    const [delegatedMint, aliceDelegatedAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible,
+      collectionMint
+    );
+    await verifyCollection(
+      program.provider,
+      owner,
+      delegatedMint,
+      collectionMint
+    );
+    const [delegatedEdition] = await deriveEditionPDA(delegatedMint);
+    let configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const delegatedOrderId = configuration.orderId;
+    const [delegatedOrder] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      delegatedOrderId,
+      program.programId
+    );
+    const delegatedAccounts = {
+      signer: alice.publicKey,
+      configuration: configurationPubKey,
+      stablecoinMint: stableCoinMint,
+      stablecoinVault: programSCVault,
+      currency: currencyPubKey,
+      userStablecoinVault: aliceSCAccount.address,
+      nftMint: delegatedMint,
+      userNftVault: aliceDelegatedAccount.address,
+      nftEdition: delegatedEdition,
+      order: delegatedOrder,
+      borrowerStats: aliceStats,
+      tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      systemProgram: SYSTEM_PROGRAM_ID,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    await program.methods
+      .createOrderDelegated(new BN(100), new BN(10), new BN(60), new BN(0))
+      .accounts({ ...delegatedAccounts, rent: SYSVAR_RENT_PUBKEY })
+      .signers([alice])
+      .rpc();
+    error = undefined;
+    try {
+      await program.methods
+        .fundOrderFromPool(delegatedOrderId)
+        .accounts({
+          signer: owner.publicKey,
+          configuration: configurationPubKey,
+          pool,
+          liquidityVault,
+          shareMint,
+          order: delegatedOrder,
+          nftMetadata: (await deriveMetadataPDA(delegatedMint))[0],
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("OrderNotQualified");
+    await program.methods
+      .cancelOrderDelegated(delegatedOrderId)
+      .accounts(delegatedAccounts)
+      .signers([alice])
+      .rpc();
+
+    const [pnftMint, alicePnftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.ProgrammableNonFungible,
+      collectionMint
+    );
+    await verifyCollection(program.provider, owner, pnftMint, collectionMint);
+    const [pnftMetadata] = await deriveMetadataPDA(pnftMint);
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const pnftOrderId = configuration.orderId;
+    const [pnftOrder] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      pnftOrderId,
+      program.programId
+    );
+    const pnftVault = await getAssociatedTokenAddress(
+      pnftMint,
+      pnftOrder,
+      true
+    );
+    const pnftAccounts = {
+      signer: alice.publicKey,
+      configuration: configurationPubKey,
+      stablecoinMint: stableCoinMint,
+      stablecoinVault: programSCVault,
+      currency: currencyPubKey,
+      userStablecoinVault: aliceSCAccount.address,
+      order: pnftOrder,
+      borrowerStats: aliceStats,
+      nftMint: pnftMint,
+      nftVault: pnftVault,
+      userNftVault: alicePnftAccount.address,
+      nftMetadata: pnftMetadata,
+      nftEdition: (await deriveEditionPDA(pnftMint))[0],
+      userTokenRecord: (
+        await deriveTokenRecordPDA(pnftMint, alicePnftAccount.address)
+      )[0],
+      vaultTokenRecord: (await deriveTokenRecordPDA(pnftMint, pnftVault))[0],
+      authorizationRulesProgram: TOKEN_METADATA_PROGRAM_ID,
+      authorizationRules: TOKEN_METADATA_PROGRAM_ID,
+      tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SYSTEM_PROGRAM_ID,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    const computeBudget = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 400_000,
+    });
+    await program.methods
+      .createOrderPnft(new BN(100), new BN(10), new BN(60), new BN(0))
+      .accounts({ ...pnftAccounts, rent: SYSVAR_RENT_PUBKEY })
+      .preInstructions([computeBudget])
+      .signers([alice])
+      .rpc();
+    error = undefined;
+    try {
+      await program.methods
+        .fundOrderFromPool(pnftOrderId)
+        .accounts({
+          signer: owner.publicKey,
+          configuration: configurationPubKey,
+          pool,
+          liquidityVault,
+          shareMint,
+          order: pnftOrder,
+          nftMetadata: pnftMetadata,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("OrderNotQualified");
+    await program.methods
+      .cancelOrderPnft(pnftOrderId)
+      .accounts(pnftAccounts)
+      .preInstructions([computeBudget])
+      .signers([alice])
+      .rpc();
+
+    [orderId, orderPubKey] = await createOrder(60);
+    await fundOrder(orderId, orderPubKey);
+    let order = await program.account.order.fetch(orderPubKey);
+    expect(order.lender.toString()).to.eq(pool.toString());
+    // the interest of the order stays the most alice pays
+    expect(order.interest.toNumber()).to.eq(10);
+    // the pool stays the lender, there is no note to sell
+    expect(order.noteMint.toBase58()).to.eq(PublicKey.default.toBase58());
+    let poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.totalBorrowed.toNumber()).to.eq(100);
+    expect(await balance(liquidityVault)).to.eq(900);
+
+    // lent out principal cannot be withdrawn
+    error = undefined;
+    try {
+      await program.methods
+        .withdrawPool(new BN(1000))
+        .accounts({ signer: bob.publicKey, ...poolAccounts })
+        .signers([bob])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InsufficientPoolLiquidity");
+
+    const interestBefore = (
+      await program.account.configuration.fetch(configurationPubKey)
+    ).totalInterestPaid.toNumber();
+    const aliceBefore = await balance(aliceSCAccount.address);
+    await sleep(2000);
+    await paybackPool(orderId, orderPubKey);
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    // interest accrued through the borrow index for the seconds the loan was open,
+    // rounded up to the smallest unit
+    const interest =
+      configuration.totalInterestPaid.toNumber() - interestBefore;
+    expect(interest).to.eq(1);
+    // alice paid the principal and the accrued interest, and got her collateral back
+    expect(aliceBefore - (await balance(aliceSCAccount.address))).to.eq(
+      100 + interest - 10
+    );
+    poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.totalBorrowed.toNumber()).to.eq(0);
+    expect(poolAccount.activeLoans.toNumber()).to.eq(0);
+    expect(await balance(aliceNftAccount.address)).to.eq(1);
+
+    // a second loan raises the utilization after the first is funded. At 10% the
+    // rate comes to nothing over a minute, so interest free orders fund, but the
+    // index grows at 160% a year once 600 of the 1000 are lent out
+    const [secondMint, aliceSecondAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible,
+      collectionMint
+    );
+    await verifyCollection(program.provider, owner, secondMint, collectionMint);
+    const secondNft = {
+      nftMint: secondMint,
+      nftVault: (await deriveNFTAccountPDA(secondMint, program.programId))[0],
+      userNftVault: aliceSecondAccount.address,
+    };
+    const [firstId, firstOrder] = await createOrder(60, 100, 0);
+    await fundOrder(firstId, firstOrder);
+    const [secondId, secondOrder] = await createOrder(60, 500, 0, secondNft);
+    const [secondMetadata] = await deriveMetadataPDA(secondMint);
+    await fundOrder(secondId, secondOrder, secondMetadata);
+    poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.totalBorrowed.toNumber()).to.eq(600);
+
+    await sleep(2000);
+    const aliceBeforeCapped = await balance(aliceSCAccount.address);
+    await paybackPool(firstId, firstOrder);
+    await paybackPool(secondId, secondOrder, secondNft);
+    poolAccount = await program.account.lendingPool.fetch(pool);
+    const first = await program.account.order.fetch(firstOrder);
+    // the debt at the index grew past the principal ...
+    expect(poolAccount.borrowIndex.gt(first.borrowIndex)).to.be.true;
+    // ... but alice only paid the principal the interest free orders agreed on,
+    // and the pool bore the rest
+    expect(aliceBeforeCapped - (await balance(aliceSCAccount.address))).to.eq(
+      600 - 20
+    );
+    configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    expect(configuration.totalInterestPaid.toNumber() - interestBefore).to.eq(
+      interest
+    );
+    expect(poolAccount.totalBorrowed.toNumber()).to.eq(0);
+    expect(poolAccount.activeLoans.toNumber()).to.eq(0);
+    expect(await balance(liquidityVault)).to.eq(1000 + interest);
+
+    // the interest raised the value of every share
+    const bobBefore = await balance(bobSCAccount.address);
+    await program.methods
+      .withdrawPool(new BN(1000))
+      .accounts({ signer: bob.publicKey, ...poolAccounts })
+      .signers([bob])
+      .rpc();
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(
+      1000 + interest
+    );
+    expect(await balance(bobShareAccount)).to.eq(0);
+  });
+
+  it("Queues pool withdrawals behind active loans!", async () => {
+    const [collectionMint] = await createMetaplexNFT(
+      program.provider,
+      owner,
+      owner.publicKey,
+      TokenStandard.NonFungible
+    );
+    const [nftMint, aliceNftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible,
+      collectionMint
+    );
+    await verifyCollection(program.provider, owner, nftMint, collectionMint);
+    const [nftMetadata] = await deriveMetadataPDA(nftMint);
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      200
+    );
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      alice.publicKey,
+      owner.publicKey,
+      100
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const [pool] = await derivePoolPDA(
+      configurationPubKey,
+      collectionMint,
+      stableCoinMint,
+      program.programId
+    );
+    const [liquidityVault] = await derivePoolVaultPDA(pool, program.programId);
+    const [shareMint] = await deriveShareMintPDA(pool, program.programId);
+    const [shareEscrow] = await deriveShareEscrowPDA(pool, program.programId);
+
+    // interest free pool keeping 20% of its value liquid
+    await program.methods
+      .createPool(
+        collectionMint,
+        owner.publicKey,
+        new BN(500),
+        new BN(86400),
+        new BN(9000)
+      )
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        pool,
+        currency: currencyPubKey,
+        stablecoinMint: stableCoinMint,
+        liquidityVault,
+        shareMint,
+        shareEscrow,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+    await program.methods
+      .updatePoolReserve(new BN(2000))
+      .accounts({ manager: owner.publicKey, pool })
+      .signers([owner])
+      .rpc();
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+    const bobShareAccount = await getAssociatedTokenAddress(
+      shareMint,
+      bob.publicKey
+    );
+    const poolAccounts = {
+      configuration: configurationPubKey,
+      pool,
+      stablecoinMint: stableCoinMint,
+      liquidityVault,
+      shareMint,
+      userStablecoinVault: bobSCAccount.address,
+      userShareVault: bobShareAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    await program.methods
+      .depositPool(new BN(120))
+      .accounts({ signer: bob.publicKey, ...poolAccounts })
+      .preInstructions([
+        createAssociatedTokenAccountInstruction(
+          bob.publicKey,
+          bobShareAccount,
+          bob.publicKey,
+          shareMint
+        ),
+      ])
+      .signers([bob])
+      .rpc();
+
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(86400), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    const fundOrder = async () => {
+      await program.methods
+        .fundOrderFromPool(orderId)
+        .accounts({
+          signer: owner.publicKey,
+          configuration: configurationPubKey,
+          pool,
+          liquidityVault,
+          shareMint,
+          order: orderPubKey,
+          nftMetadata,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          borrowerStablecoinVault: aliceSCAccount.address,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    // 100 lent out of 120 would eat into the reserve of 24
+    let error;
+    try {
+      await fundOrder();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InsufficientPoolLiquidity");
+    await program.methods
+      .depositPool(new BN(80))
+      .accounts({ signer: bob.publicKey, ...poolAccounts })
+      .signers([bob])
+      .rpc();
+    await fundOrder();
+    expect(await balance(liquidityVault)).to.eq(100);
+
+    const requestWithdrawal = async (shares: number) => {
+      const { queueTail } = await program.account.lendingPool.fetch(pool);
+      const [withdrawal] = await deriveWithdrawalPDA(
+        pool,
+        queueTail,
+        program.programId
+      );
+      await program.methods
+        .requestWithdrawal(new BN(shares))
+        .accounts({
+          signer: bob.publicKey,
+          pool,
+          withdrawal,
+          shareEscrow,
+          userShareVault: bobShareAccount,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bob])
+        .rpc();
+      return [queueTail, withdrawal];
+    };
+    const claimWithdrawal = async (requestId, withdrawal) => {
+      await program.methods
+        .claimWithdrawal(requestId)
+        .accounts({
+          signer: owner.publicKey,
+          pool,
+          withdrawal,
+          owner: bob.publicKey,
+          liquidityVault,
+          shareMint,
+          shareEscrow,
+          ownerStablecoinVault: bobSCAccount.address,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    // 150 shares are worth more than the 100 left in the pool
+    const [firstId, firstRequest] = await requestWithdrawal(150);
+    const [secondId, secondRequest] = await requestWithdrawal(10);
+    expect(await balance(shareEscrow)).to.eq(160);
+    error = undefined;
+    try {
+      await claimWithdrawal(firstId, firstRequest);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InsufficientPoolLiquidity");
+
+    // requests are paid in order
+    error = undefined;
+    try {
+      await claimWithdrawal(secondId, secondRequest);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("WithdrawalNotNext");
+    await program.methods
+      .cancelWithdrawal(secondId)
+      .accounts({
+        signer: bob.publicKey,
+        pool,
+        withdrawal: secondRequest,
+        shareEscrow,
+        userShareVault: bobShareAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([bob])
+      .rpc();
+    expect(await balance(bobShareAccount)).to.eq(50);
+
+    // instant withdrawals wait behind the queue
+    error = undefined;
+    try {
+      await program.methods
+        .withdrawPool(new BN(10))
+        .accounts({ signer: bob.publicKey, ...poolAccounts })
+        .signers([bob])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InsufficientPoolLiquidity");
+
+    // the repayment fills the queue
+    await program.methods
+      .paybackPool(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        pool,
+        liquidityVault,
+        order: orderPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([alice])
+      .rpc();
+    const bobBefore = await balance(bobSCAccount.address);
+    await claimWithdrawal(firstId, firstRequest);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(150);
+    // the cancelled request only gives its rent back
+    await claimWithdrawal(secondId, secondRequest);
+    expect((await balance(bobSCAccount.address)) - bobBefore).to.eq(150);
+
+    const poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.queuedShares.toNumber()).to.eq(0);
+    expect(poolAccount.queueHead.toNumber()).to.eq(2);
+    expect(await balance(shareEscrow)).to.eq(0);
+  });
+
+  it("Carries defaulted pool loans until the manager sells them!", async () => {
+    const keeper = Keypair.generate();
+    await airdropSOL(program.provider, keeper.publicKey, 5);
+    const [collectionMint] = await createMetaplexNFT(
+      program.provider,
+      owner,
+      owner.publicKey,
+      TokenStandard.NonFungible
+    );
+    const [nftMint, aliceNftAccount] = await createMetaplexNFT(
+      program.provider,
+      alice,
+      alice.publicKey,
+      TokenStandard.NonFungible,
+      collectionMint
+    );
+    await verifyCollection(program.provider, owner, nftMint, collectionMint);
+    const [nftMetadata] = await deriveMetadataPDA(nftMint);
+    await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      bob.publicKey,
+      owner.publicKey,
+      300
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const [pool] = await derivePoolPDA(
+      configurationPubKey,
+      collectionMint,
+      stableCoinMint,
+      program.programId
+    );
+    const [liquidityVault] = await derivePoolVaultPDA(pool, program.programId);
+    const [poolNftVault] = await derivePoolVaultPDA(
+      pool,
+      program.programId,
+      nftMint
+    );
+    const [shareMint] = await deriveShareMintPDA(pool, program.programId);
+    const [shareEscrow] = await deriveShareEscrowPDA(pool, program.programId);
+    const [defaulted] = await deriveDefaultedPDA(
+      pool,
+      nftMint,
+      program.programId
+    );
+
+    // interest free pool
+    await program.methods
+      .createPool(
+        collectionMint,
+        owner.publicKey,
+        new BN(500),
+        new BN(86400),
+        new BN(9000)
+      )
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        pool,
+        currency: currencyPubKey,
+        stablecoinMint: stableCoinMint,
+        liquidityVault,
+        shareMint,
+        shareEscrow,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([owner])
+      .rpc();
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+    const bobShareAccount = await getAssociatedTokenAddress(
+      shareMint,
+      bob.publicKey
+    );
+    await program.methods
+      .depositPool(new BN(200))
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        pool,
+        stablecoinMint: stableCoinMint,
+        liquidityVault,
+        shareMint,
+        userStablecoinVault: bobSCAccount.address,
+        userShareVault: bobShareAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([
+        createAssociatedTokenAccountInstruction(
+          bob.publicKey,
+          bobShareAccount,
+          bob.publicKey,
+          shareMint
+        ),
+      ])
+      .signers([bob])
+      .rpc();
+
+    // 3s loan of 100 with 10 additional collateral
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(3), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .fundOrderFromPool(orderId)
+      .accounts({
+        signer: owner.publicKey,
+        configuration: configurationPubKey,
+        pool,
+        liquidityVault,
+        shareMint,
+        order: orderPubKey,
+        nftMetadata,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+    await sleep(4000);
+    const liquidatePool = () =>
+      program.methods
+        .liquidatePool(orderId)
+        .accounts({
+          signer: keeper.publicKey,
+          configuration: configurationPubKey,
+          pool,
+          liquidityVault,
+          order: orderPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          nftMint,
+          nftVault: programNFTVault,
+          poolNftVault,
+          defaulted,
+          borrowerStats: aliceStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([keeper])
+        .rpc();
+    const updateAuctionTerms = (duration: number) =>
+      program.methods
+        .updateAuctionTerms(new BN(duration), new BN(20000), new BN(5000))
+        .accounts({
+          authority: owner.publicKey,
+          configuration: configurationPubKey,
+        })
+        .signers([owner])
+        .rpc();
+
+    // pool loans cannot be auctioned, so they liquidate directly while auctions are enabled
+    await updateAuctionTerms(3600);
+    await liquidatePool();
+    await updateAuctionTerms(0);
+
+    // the debt left after the collateral is carried at cost, the shares keep their value
+    let poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.totalBorrowed.toNumber()).to.eq(0);
+    expect(poolAccount.defaultedValue.toNumber()).to.eq(90);
+    expect(poolAccount.defaultedNfts.toNumber()).to.eq(1);
+    expect(await balance(liquidityVault)).to.eq(110);
+    expect(await balance(poolNftVault)).to.eq(1);
+
+    const manage = { manager: owner.publicKey, pool, defaulted };
+    await program.methods
+      .writeDownDefaultedNft(new BN(60))
+      .accounts(manage)
+      .signers([owner])
+      .rpc();
+    let error;
+    try {
+      await program.methods
+        .writeDownDefaultedNft(new BN(70))
+        .accounts(manage)
+        .signers([owner])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("CarryingValueIncrease");
+    poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.defaultedValue.toNumber()).to.eq(60);
+
+    const bobNftAccount = await getAssociatedTokenAddress(
+      nftMint,
+      bob.publicKey
+    );
+    const buy = async () => {
+      await program.methods
+        .buyDefaultedNft()
+        .accounts({
+          signer: bob.publicKey,
+          pool,
+          liquidityVault,
+          defaulted,
+          keeper: keeper.publicKey,
+          poolNftVault,
+          buyerStablecoinVault: bobSCAccount.address,
+          buyerNftVault: bobNftAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([
+          createAssociatedTokenAccountInstruction(
+            bob.publicKey,
+            bobNftAccount,
+            bob.publicKey,
+            nftMint
+          ),
+        ])
+        .signers([bob])
+        .rpc();
+    };
+    error = undefined;
+    try {
+      await buy();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("NotForSale");
+
+    // fixed price sale for 50
+    await program.methods
+      .listDefaultedNft(new BN(50), new BN(50), new BN(0))
+      .accounts(manage)
+      .signers([owner])
+      .rpc();
+    await buy();
+    expect(await balance(bobNftAccount)).to.eq(1);
+    expect(await balance(liquidityVault)).to.eq(160);
+
+    // 90 owed, 50 recovered
+    poolAccount = await program.account.lendingPool.fetch(pool);
+    expect(poolAccount.defaultedValue.toNumber()).to.eq(0);
+    expect(poolAccount.defaultedNfts.toNumber()).to.eq(0);
+    expect(poolAccount.realizedLosses.toNumber()).to.eq(40);
+    expect(
+      await program.provider.connection.getAccountInfo(defaulted)
+    ).to.eq(null);
+  });
+
+  it("Pays whoever holds the loan note!", async () => {
+    const carol = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 5);
+    const [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+    const carolSCAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      stableCoinMint,
+      carol.publicKey
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId: BN = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const [noteMint] = await deriveNoteMintPDA(orderPubKey, program.programId);
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(86400), new BN(0))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    // bob holds the only note that will ever exist
+    const order = await program.account.order.fetch(orderPubKey);
+    expect(order.noteMint.toBase58()).to.eq(noteMint.toBase58());
+    const bobNoteVault = await noteVaultOf(orderPubKey, bob.publicKey);
+    expect(await balance(bobNoteVault)).to.eq(1);
+    const note = await getMint(program.provider.connection, noteMint);
+    expect(Number(note.supply)).to.eq(1);
+    expect(note.mintAuthority).to.eq(null);
+
+    // bob sells the note to carol off-protocol
+    const carolNoteVault = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      noteMint,
+      carol.publicKey
+    );
+    await transfer(
+      program.provider.connection,
+      bob,
+      bobNoteVault,
+      carolNoteVault.address,
+      bob,
+      1
+    );
+
+    // carol takes the position over from bob
+    const [carolStats] = await deriveUserStatsPDA(
+      carol.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    const claimLoanPosition = async (holder: Keypair, noteVault: PublicKey) => {
+      const [holderStats] = await deriveUserStatsPDA(
+        holder.publicKey,
+        configurationPubKey,
+        program.programId
+      );
+      await program.methods
+        .claimLoanPosition(orderId)
+        .accounts({
+          signer: holder.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          noteVault,
+          lenderStats: bobStats,
+          holderStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([holder])
+        .rpc();
+    };
+    const bobLoansBefore = (
+      await program.account.userStats.fetch(bobStats)
+    ).activeLoans.toNumber();
+    let error;
+    try {
+      await claimLoanPosition(bob, bobNoteVault);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InvalidLoanNote");
+    await claimLoanPosition(carol, carolNoteVault.address);
+    expect(
+      (await program.account.order.fetch(orderPubKey)).lender.toBase58()
+    ).to.eq(carol.publicKey.toBase58());
+    const bobStatsAfter = await program.account.userStats.fetch(bobStats);
+    expect(bobStatsAfter.activeLoans.toNumber()).to.eq(bobLoansBefore - 1);
+    expect(bobStatsAfter.orderIds.map((id) => id.toNumber())).to.not.include(
+      orderId.toNumber()
+    );
+    let carolStatsAccount = await program.account.userStats.fetch(carolStats);
+    expect(carolStatsAccount.activeLoans.toNumber()).to.eq(1);
+    expect(carolStatsAccount.orderIds.map((id) => id.toNumber())).to.deep.eq([
+      orderId.toNumber(),
+    ]);
+    // the amount lent stays with bob, who funded the loan
+    expect(carolStatsAccount.totalLent.toNumber()).to.eq(0);
+
+    const paybackAccounts = {
+      signer: alice.publicKey,
+      configuration: configurationPubKey,
+      stablecoinMint: stableCoinMint,
+      stablecoinVault: programSCVault,
+      currency: currencyPubKey,
+      userStablecoinVault: aliceSCAccount.address,
+      nftMint,
+      nftVault: programNFTVault,
+      userNftVault: aliceNftAccount.address,
+      order: orderPubKey,
+      borrowerStats: aliceStats,
+      lenderStats: carolStats,
+      listing: await listingOf(orderPubKey),
+      systemProgram: SYSTEM_PROGRAM_ID,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // the original lender no longer gets paid
+    error = undefined;
+    try {
+      await program.methods
+        .payback(orderId)
+        .accounts({
+          ...paybackAccounts,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: bobNoteVault,
+          payee: bob.publicKey,
+        })
+        .signers([alice])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InvalidLoanNote");
+
+    // principal plus interest goes to carol
+    await program.methods
+      .payback(orderId)
+      .accounts({
+        ...paybackAccounts,
+        lenderStablecoinVault: carolSCAccount.address,
+        noteVault: carolNoteVault.address,
+        payee: carol.publicKey,
+      })
+      .signers([alice])
+      .rpc();
+    expect(await balance(carolSCAccount.address)).to.eq(110);
+    expect(await balance(aliceNftAccount.address)).to.eq(1);
+    carolStatsAccount = await program.account.userStats.fetch(carolStats);
+    expect(carolStatsAccount.activeLoans.toNumber()).to.eq(0);
+
+    // the note of the settled loan is burnt and its rent goes back to carol
+    await program.methods
+      .closeLoanNote()
+      .accounts({
+        signer: carol.publicKey,
+        order: orderPubKey,
+        noteMint,
+        noteVault: carolNoteVault.address,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([carol])
+      .rpc();
+    expect(
+      await program.provider.connection.getAccountInfo(carolNoteVault.address)
+    ).to.eq(null);
+    expect(
+      Number((await getMint(program.provider.connection, noteMint)).supply)
+    ).to.eq(0);
+  });
+
+  it("Pays whoever holds the note of a token loan!", async () => {
+    const carol = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 5);
+    const carolSCAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      stableCoinMint,
+      carol.publicKey
+    );
+    const govMint = await createTokenMint(
+      program.provider,
+      owner,
+      owner.publicKey,
+      null,
+      6
+    );
+    const aliceGovAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      govMint,
+      alice.publicKey,
+      owner.publicKey,
+      400_000
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [stableCoinVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [collateralToken] = await deriveCollateralTokenPDA(
+      configurationPubKey,
+      govMint,
+      program.programId
+    );
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId: BN = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const collateralVault = await getAssociatedTokenAddress(
+      govMint,
+      orderPubKey,
+      true
+    );
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+
+    await program.methods
+      .addCollateralToken()
+      .accounts({
+        authority: owner.publicKey,
+        configuration: configurationPubKey,
+        collateralMint: govMint,
+        collateralToken,
+        systemProgram: SYSTEM_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+    await program.methods
+      .createOrderToken(
+        new BN(100),
+        new BN(10),
+        new BN(86400),
+        new BN(0),
+        new BN(400_000)
+      )
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        collateralMint: govMint,
+        collateralToken,
+        userCollateralVault: aliceGovAccount.address,
+        order: orderPubKey,
+        collateralVault,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralTokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: stableCoinVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    // bob hands the note to carol
+    const [noteMint] = await deriveNoteMintPDA(orderPubKey, program.programId);
+    const bobNoteVault = await noteVaultOf(orderPubKey, bob.publicKey);
+    const carolNoteVault = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      noteMint,
+      carol.publicKey
+    );
+    await transfer(
+      program.provider.connection,
+      bob,
+      bobNoteVault,
+      carolNoteVault.address,
+      bob,
+      1
+    );
+
+    const payback = (lenderStablecoinVault: PublicKey, noteVault: PublicKey) =>
+      program.methods
+        .paybackToken(orderId)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: stableCoinVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault,
+          noteVault,
+          userStablecoinVault: aliceSCAccount.address,
+          collateralMint: govMint,
+          collateralToken,
+          collateralVault,
+          userCollateralVault: aliceGovAccount.address,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          collateralTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([alice])
+        .rpc();
+
+    // the original lender no longer gets paid
+    let error;
+    try {
+      await payback(bobSCAccount.address, bobNoteVault);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InvalidLoanNote");
+
+    // principal plus interest goes to carol, the tokens back to alice
+    await payback(carolSCAccount.address, carolNoteVault.address);
+    expect(await balance(carolSCAccount.address)).to.eq(110);
+    expect(await balance(aliceGovAccount.address)).to.eq(400_000);
+    expect(
+      await program.provider.connection.getAccountInfo(collateralVault)
+    ).to.eq(null);
+  });
+
+  it("Sells an nft still under a loan!", async () => {
+    const carol = Keypair.generate();
+    const dave = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 5);
+    await airdropSOL(program.provider, dave.publicKey, 5);
+    const [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+    const carolSCAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      stableCoinMint,
+      carol.publicKey
+    );
+    const daveSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      dave.publicKey,
+      owner.publicKey,
+      200
+    );
+    const daveNftAccount = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      dave,
+      nftMint,
+      dave.publicKey
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const [carolStats] = await deriveUserStatsPDA(
+      carol.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId: BN = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+
+    // carol has never used the program, her stats are created by the transfer
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(86400), new BN(10))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    const transferBorrower = (newBorrower: Keypair, newBorrowerStats: PublicKey) =>
+      program.methods
+        .transferBorrower(orderId)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          nftMint,
+          nftVault: programNFTVault,
+          newBorrower: newBorrower.publicKey,
+          borrowerStats: aliceStats,
+          newBorrowerStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+        })
+        .signers([alice, newBorrower])
+        .rpc();
+
+    // the lender cannot take over the loan
+    let error;
+    try {
+      await transferBorrower(bob, bobStats);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("ConstraintRaw");
+
+    // alice hands the position to carol, who signs for it
+    await transferBorrower(carol, carolStats);
+    const order = await program.account.order.fetch(orderPubKey);
+    expect(order.borrower.toBase58()).to.eq(carol.publicKey.toBase58());
+    const aliceStatsAccount = await program.account.userStats.fetch(aliceStats);
+    expect(
+      aliceStatsAccount.orderIds.map((id) => id.toNumber())
+    ).to.not.include(orderId.toNumber());
+    let carolStatsAccount = await program.account.userStats.fetch(carolStats);
+    expect(carolStatsAccount.user.toBase58()).to.eq(carol.publicKey.toBase58());
+    expect(carolStatsAccount.activeBorrows.toNumber()).to.eq(1);
+    expect(carolStatsAccount.orderIds.map((id) => id.toNumber())).to.deep.eq([
+      orderId.toNumber(),
+    ]);
+
+    // alice can no longer pay back
+    const bobNoteVault = await noteVaultOf(orderPubKey, bob.publicKey);
+    error = undefined;
+    try {
+      await program.methods
+        .payback(orderId)
+        .accounts({
+          signer: alice.publicKey,
+          configuration: configurationPubKey,
+          stablecoinMint: stableCoinMint,
+          stablecoinVault: programSCVault,
+          currency: currencyPubKey,
+          lenderStablecoinVault: bobSCAccount.address,
+          noteVault: bobNoteVault,
+          payee: bob.publicKey,
+          listing: await listingOf(orderPubKey),
+          userStablecoinVault: aliceSCAccount.address,
+          nftMint,
+          nftVault: programNFTVault,
+          userNftVault: aliceNftAccount.address,
+          order: orderPubKey,
+          borrowerStats: aliceStats,
+          lenderStats: bobStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([alice])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("ConstraintRaw");
+
+    // carol sells the nft to dave, who pays off the loan
+    const sellAccounts = {
+      signer: carol.publicKey,
+      buyer: dave.publicKey,
+      configuration: configurationPubKey,
+      order: orderPubKey,
+      stablecoinMint: stableCoinMint,
+      stablecoinVault: programSCVault,
+      currency: currencyPubKey,
+      lenderStablecoinVault: bobSCAccount.address,
+      noteVault: bobNoteVault,
+      borrowerStablecoinVault: carolSCAccount.address,
+      buyerStablecoinVault: daveSCAccount.address,
+      nftMint,
+      nftVault: programNFTVault,
+      buyerNftVault: daveNftAccount.address,
+      borrowerStats: carolStats,
+      lenderStats: bobStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    error = undefined;
+    try {
+      await program.methods
+        .sellAndRepay(orderId, new BN(109))
+        .accounts(sellAccounts)
+        .signers([carol, dave])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("SalePriceTooLow");
+
+    const bobBefore = await balance(bobSCAccount.address);
+    await program.methods
+      .sellAndRepay(orderId, new BN(150))
+      .accounts(sellAccounts)
+      .signers([carol, dave])
+      .rpc();
+
+    // bob is repaid, carol keeps the rest and the additional collateral
+    expect(await balance(bobSCAccount.address)).to.eq(bobBefore + 110);
+    expect(await balance(carolSCAccount.address)).to.eq(40 + 10);
+    expect(await balance(daveSCAccount.address)).to.eq(50);
+    expect(await balance(daveNftAccount.address)).to.eq(1);
+    carolStatsAccount = await program.account.userStats.fetch(carolStats);
+    expect(carolStatsAccount.activeBorrows.toNumber()).to.eq(0);
+    expect(carolStatsAccount.orderIds.length).to.eq(0);
+  });
+
+  it("Trades active loans on the marketplace!", async () => {
+    const carol = Keypair.generate();
+    const dave = Keypair.generate();
+    await airdropSOL(program.provider, carol.publicKey, 5);
+    await airdropSOL(program.provider, dave.publicKey, 5);
+    const [nftMint, aliceNftAccount] = await createNFT(
+      program.provider,
+      alice,
+      alice.publicKey
+    );
+    const carolSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      carol.publicKey,
+      owner.publicKey,
+      200
+    );
+    const daveSCAccount = await mintTokenTo(
+      program.provider,
+      owner,
+      stableCoinMint,
+      dave.publicKey,
+      owner.publicKey,
+      200
+    );
+
+    // pda
+    const [configurationPubKey] = await deriveConfigurationAccountPDA(
+      stableCoinMint,
+      program.programId
+    );
+    const [programSCVault] = await deriveSCAccountPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [currencyPubKey] = await deriveCurrencyPDA(
+      configurationPubKey,
+      stableCoinMint,
+      program.programId
+    );
+    const [programNFTVault] = await deriveNFTAccountPDA(
+      nftMint,
+      program.programId
+    );
+    const configuration = await program.account.configuration.fetch(
+      configurationPubKey
+    );
+    const orderId: BN = configuration.orderId;
+    const [orderPubKey] = await deriveOrderAccountPDA(
+      configurationPubKey,
+      orderId,
+      program.programId
+    );
+    const [listing] = await deriveLoanListingPDA(
+      orderPubKey,
+      program.programId
+    );
+    const [noteMint] = await deriveNoteMintPDA(orderPubKey, program.programId);
+
+    const balance = async (account: PublicKey) =>
+      Number(
+        (await getAccount(program.provider.connection, account)).amount
+      );
+
+    await program.methods
+      .createOrder(new BN(100), new BN(10), new BN(86400), new BN(0))
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([alice])
+      .rpc();
+    await program.methods
+      .giveLoan(orderId)
+      .accounts({
+        signer: bob.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: bobSCAccount.address,
+        borrowerStablecoinVault: aliceSCAccount.address,
+        borrowerStats: aliceStats,
+        lenderStats: bobStats,
+        order: orderPubKey,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        ...(await loanNoteAccounts(orderPubKey, bob.publicKey)),
+      })
+      .signers([bob])
+      .rpc();
+
+    const bobNoteVault = await noteVaultOf(orderPubKey, bob.publicKey);
+    const [carolStats] = await deriveUserStatsPDA(
+      carol.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    const [daveStats] = await deriveUserStatsPDA(
+      dave.publicKey,
+      configurationPubKey,
+      program.programId
+    );
+    const carolNoteVault = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      carol,
+      noteMint,
+      carol.publicKey
+    );
+    const listLoan = async (seller: Keypair, noteVault: PublicKey, price: number) => {
+      await program.methods
+        .listLoan(orderId, new BN(price))
+        .accounts({
+          signer: seller.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          noteVault,
+          listing,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([seller])
+        .rpc();
+    };
+
+    // only the note can be listed, not another token account of the seller
+    let error;
+    try {
+      await listLoan(bob, bobSCAccount.address, 120);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("InvalidLoanNote");
+
+    // bob lists the loan and changes his mind
+    await listLoan(bob, bobNoteVault, 120);
+    let bobNote = await getAccount(program.provider.connection, bobNoteVault);
+    expect(bobNote.delegate.toBase58()).to.eq(listing.toBase58());
+    await program.methods
+      .cancelLoanListing()
+      .accounts({
+        signer: bob.publicKey,
+        listing,
+        noteVault: bobNoteVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([bob])
+      .rpc();
+    bobNote = await getAccount(program.provider.connection, bobNoteVault);
+    expect(bobNote.delegate).to.eq(null);
+    expect(
+      await program.provider.connection.getAccountInfo(listing)
+    ).to.eq(null);
+
+    // carol buys the loan note from bob, at no more than she agreed to pay
+    await listLoan(bob, bobNoteVault, 105);
+    const bobBefore = await balance(bobSCAccount.address);
+    const carolBuysLoan = (maxPrice: number) =>
+      program.methods
+        .buyLoan(orderId, new BN(maxPrice))
+        .accounts({
+          signer: carol.publicKey,
+          configuration: configurationPubKey,
+          order: orderPubKey,
+          listing,
+          seller: bob.publicKey,
+          noteVault: bobNoteVault,
+          buyerNoteVault: carolNoteVault.address,
+          sellerStablecoinVault: bobSCAccount.address,
+          buyerStablecoinVault: carolSCAccount.address,
+          lenderStats: bobStats,
+          buyerStats: carolStats,
+          systemProgram: SYSTEM_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([carol])
+        .rpc();
+    error = undefined;
+    try {
+      await carolBuysLoan(104);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("ListingPriceTooHigh");
+    await carolBuysLoan(105);
+    expect(await balance(bobSCAccount.address)).to.eq(bobBefore + 105);
+    expect(await balance(carolSCAccount.address)).to.eq(95);
+    expect(await balance(bobNoteVault)).to.eq(0);
+    expect(await balance(carolNoteVault.address)).to.eq(1);
+    // the position moved to carol, who is now the lender of the order
+    expect(
+      (await program.account.order.fetch(orderPubKey)).lender.toBase58()
+    ).to.eq(carol.publicKey.toBase58());
+    let carolStatsAccount = await program.account.userStats.fetch(carolStats);
+    expect(carolStatsAccount.activeLoans.toNumber()).to.eq(1);
+    expect(carolStatsAccount.orderIds.map((id) => id.toNumber())).to.deep.eq([
+      orderId.toNumber(),
+    ]);
+    expect(
+      (await program.account.userStats.fetch(bobStats)).orderIds.map((id) =>
+        id.toNumber()
+      )
+    ).to.not.include(orderId.toNumber());
+
+    // carol lists it again, then hands the note to dave outside the marketplace
+    await listLoan(carol, carolNoteVault.address, 110);
+    const daveNoteVault = await getOrCreateAssociatedTokenAccount(
+      program.provider.connection,
+      dave,
+      noteMint,
+      dave.publicKey
+    );
+    await transfer(
+      program.provider.connection,
+      carol,
+      carolNoteVault.address,
+      daveNoteVault.address,
+      carol,
+      1
+    );
+
+    // the listing of carol can no longer deliver the note, dave closes it for her
+    const closeLoanListing = (signer: Keypair, noteVault: PublicKey) =>
+      program.methods
+        .closeLoanListing()
+        .accounts({
+          signer: signer.publicKey,
+          listing,
+          order: orderPubKey,
+          noteVault,
+          seller: carol.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+    const carolLamports = await program.provider.connection.getBalance(
+      carol.publicKey
+    );
+    await closeLoanListing(dave, carolNoteVault.address);
+    expect(
+      await program.provider.connection.getBalance(carol.publicKey)
+    ).to.be.greaterThan(carolLamports);
+    expect(
+      await program.provider.connection.getAccountInfo(listing)
+    ).to.eq(null);
+
+    // a listing the seller can still deliver stays up
+    await listLoan(dave, daveNoteVault.address, 120);
+    error = undefined;
+    try {
+      await program.methods
+        .closeLoanListing()
+        .accounts({
+          signer: carol.publicKey,
+          listing,
+          order: orderPubKey,
+          noteVault: daveNoteVault.address,
+          seller: dave.publicKey,
+        })
+        .signers([carol])
+        .rpc();
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("LoanStillActive");
+
+    // alice pays back dave first, which closes his listing and gives him its rent
+    const daveLamports = await program.provider.connection.getBalance(
+      dave.publicKey
+    );
+    await program.methods
+      .payback(orderId)
+      .accounts({
+        signer: alice.publicKey,
+        configuration: configurationPubKey,
+        stablecoinMint: stableCoinMint,
+        stablecoinVault: programSCVault,
+        currency: currencyPubKey,
+        lenderStablecoinVault: daveSCAccount.address,
+        noteVault: daveNoteVault.address,
+        payee: dave.publicKey,
+        listing,
+        userStablecoinVault: aliceSCAccount.address,
+        nftMint,
+        nftVault: programNFTVault,
+        userNftVault: aliceNftAccount.address,
+        order: orderPubKey,
+        borrowerStats: aliceStats,
+        lenderStats: carolStats,
+        systemProgram: SYSTEM_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([alice])
+      .rpc();
+    expect(await balance(daveSCAccount.address)).to.eq(200 + 110);
+    carolStatsAccount = await program.account.userStats.fetch(carolStats);
+    expect(carolStatsAccount.activeLoans.toNumber()).to.eq(0);
+    expect(
+      await program.provider.connection.getAccountInfo(listing)
+    ).to.eq(null);
+    expect(
+      await program.provider.connection.getBalance(dave.publicKey)
+    ).to.be.greaterThan(daveLamports);
+
+    // the note of the settled loan can no longer be listed
+    error = undefined;
+    try {
+      await listLoan(dave, daveNoteVault.address, 120);
+    } catch (e) {
+      error = e;
+    }
+    expect(error.error.errorCode.code).to.eq("AccountNotInitialized");
+  });
 });